@@ -0,0 +1,463 @@
+#![deny(bare_trait_objects)]
+#![warn(unreachable_pub)]
+#![warn(unused_qualifications)]
+
+//! Derive macros for the `FromArgdata` and `ToArgdata` traits of the
+//! [`argdata`](https://docs.rs/argdata) crate.
+//!
+//! Don't use this crate directly, but enable the `derive` feature of
+//! `argdata` instead. See the documentation of `argdata::FromArgdata` for
+//! how types are represented and which attributes are supported.
+
+extern crate proc_macro;
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::{
+	parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Fields, GenericParam,
+	Ident, Lifetime, LitStr, Path, Type,
+};
+
+#[proc_macro_derive(FromArgdata, attributes(argdata))]
+pub fn derive_from_argdata(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	from_argdata(&input)
+		.unwrap_or_else(Error::into_compile_error)
+		.into()
+}
+
+#[proc_macro_derive(ToArgdata, attributes(argdata))]
+pub fn derive_to_argdata(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	to_argdata(&input)
+		.unwrap_or_else(Error::into_compile_error)
+		.into()
+}
+
+/// The `#[argdata(...)]` attributes of a struct or enum.
+#[derive(Default)]
+struct ContainerAttrs {
+	deny_unknown_fields: bool,
+}
+
+/// The `#[argdata(...)]` attributes of a field or variant.
+#[derive(Default)]
+struct FieldAttrs {
+	rename: Option<String>,
+	default: Option<Option<Path>>,
+}
+
+fn container_attrs(attrs: &[Attribute]) -> Result<ContainerAttrs, Error> {
+	let mut result = ContainerAttrs::default();
+	for attr in attrs.iter().filter(|a| a.path().is_ident("argdata")) {
+		attr.parse_nested_meta(|meta| {
+			if meta.path.is_ident("deny_unknown_fields") {
+				result.deny_unknown_fields = true;
+				Ok(())
+			} else {
+				Err(meta.error("unknown argdata attribute"))
+			}
+		})?;
+	}
+	Ok(result)
+}
+
+fn field_attrs(attrs: &[Attribute], is_field: bool) -> Result<FieldAttrs, Error> {
+	let mut result = FieldAttrs::default();
+	for attr in attrs.iter().filter(|a| a.path().is_ident("argdata")) {
+		attr.parse_nested_meta(|meta| {
+			if meta.path.is_ident("rename") {
+				result.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+				Ok(())
+			} else if is_field && meta.path.is_ident("default") {
+				result.default = Some(
+					if meta.input.is_empty() || meta.input.peek(syn::Token![,]) {
+						None
+					} else {
+						Some(meta.value()?.parse::<LitStr>()?.parse()?)
+					},
+				);
+				Ok(())
+			} else {
+				Err(meta.error("unknown argdata attribute"))
+			}
+		})?;
+	}
+	Ok(result)
+}
+
+/// The name of a field or variant in the argdata representation.
+fn name(ident: &Ident, attrs: &FieldAttrs) -> LitStr {
+	let name = match &attrs.rename {
+		Some(name) => name.clone(),
+		None => ident.unraw().to_string(),
+	};
+	LitStr::new(&name, ident.span())
+}
+
+fn is_option(ty: &Type) -> bool {
+	match ty {
+		Type::Path(ty) => {
+			ty.qself.is_none() && ty.path.segments.last().is_some_and(|s| s.ident == "Option")
+		}
+		_ => false,
+	}
+}
+
+/// The names to bind the fields to in patterns and in the generated code.
+fn bindings(fields: &Fields) -> Vec<Ident> {
+	(0..fields.len())
+		.map(|i| Ident::new(&format!("__field{}", i), Span::call_site()))
+		.collect()
+}
+
+/// A pattern binding all fields of `path` to the names from `bindings()`.
+fn pattern(path: &TokenStream, fields: &Fields) -> TokenStream {
+	let bindings = bindings(fields);
+	match fields {
+		Fields::Named(fields) => {
+			let names = fields.named.iter().map(|f| &f.ident);
+			quote!(#path { #(#names: #bindings),* })
+		}
+		Fields::Unnamed(_) => quote!(#path(#(#bindings),*)),
+		Fields::Unit => quote!(#path),
+	}
+}
+
+/// Add the name of the enum variant, if any, to an error.
+fn wrap(e: TokenStream, variant: Option<&LitStr>) -> TokenStream {
+	match variant {
+		Some(variant) => quote!(#e.in_field(#variant)),
+		None => e,
+	}
+}
+
+/// Generate an expression that reads `fields` from `argdata` and constructs `path`.
+///
+/// `variant` is the name of the enum variant, if any, which is added to all errors.
+fn read_fields(
+	path: &TokenStream,
+	fields: &Fields,
+	container: &ContainerAttrs,
+	argdata: &TokenStream,
+	variant: Option<&LitStr>,
+) -> Result<TokenStream, Error> {
+	let error = quote!(::argdata::FromArgdataError);
+	let wrap = |e| wrap(e, variant);
+	let read_err = match variant {
+		Some(variant) => quote!(.map_err(|e| #error::from(e).in_field(#variant))),
+		None => quote!(),
+	};
+	let bindings = bindings(fields);
+	Ok(match fields {
+		Fields::Named(named) => {
+			let mut names = Vec::new();
+			let mut reads = Vec::new();
+			let mut missing = Vec::new();
+			for field in &named.named {
+				let attrs = field_attrs(&field.attrs, true)?;
+				let name = name(field.ident.as_ref().unwrap(), &attrs);
+				let field_err = wrap(quote!(e.in_field(#name)));
+				reads.push(if is_option(&field.ty) {
+					// Read the value as the type inside the `Option` first, so
+					// that `Some(None)` round-trips if that type is an
+					// `Option` itself. Other nulls are `None`.
+					quote!(match ::argdata::FromArgdata::from_argdata(&*value) {
+						::std::result::Result::Ok(v) => ::std::option::Option::Some(v),
+						::std::result::Result::Err(e) => match ::argdata::Argdata::read_null(&*value) {
+							::std::result::Result::Ok(()) => ::std::option::Option::None,
+							::std::result::Result::Err(_) => return ::std::result::Result::Err(#field_err),
+						},
+					})
+				} else {
+					quote!(::argdata::FromArgdata::from_argdata(&*value).map_err(|e| #field_err)?)
+				});
+				missing.push(match attrs.default {
+					Some(None) => quote!(::std::default::Default::default()),
+					Some(Some(path)) => quote!(#path()),
+					None if is_option(&field.ty) => quote!(::std::option::Option::None),
+					None => {
+						let e = wrap(quote!(#error::MissingField(#name)));
+						quote!(return ::std::result::Result::Err(#e))
+					}
+				});
+				names.push(name);
+			}
+			let unknown = if container.deny_unknown_fields {
+				let e =
+					wrap(quote!(#error::UnknownField(::std::string::ToString::to_string(other))));
+				quote!(other => return ::std::result::Result::Err(#e))
+			} else {
+				quote!(_ => {})
+			};
+			let field_names = named.named.iter().map(|f| &f.ident);
+			quote!({
+				#(let mut #bindings = ::std::option::Option::None;)*
+				for entry in ::argdata::Argdata::read_map(#argdata)#read_err? {
+					let (key, value) = entry#read_err?;
+					match ::argdata::ArgdataExt::read_str(&*key)#read_err? {
+						#(
+							#names => {
+								#bindings = ::std::option::Option::Some(#reads);
+							}
+						)*
+						#unknown
+					}
+				}
+				#path {
+					#(
+						#field_names: match #bindings {
+							::std::option::Option::Some(v) => v,
+							::std::option::Option::None => #missing,
+						},
+					)*
+				}
+			})
+		}
+		Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+			let map_err = variant.map(|variant| quote!(.map_err(|e| e.in_field(#variant))));
+			quote!(#path(::argdata::FromArgdata::from_argdata(#argdata)#map_err?))
+		}
+		Fields::Unnamed(_) => {
+			let names = (0..fields.len())
+				.map(|i| LitStr::new(&i.to_string(), Span::call_site()))
+				.collect::<Vec<_>>();
+			let field_err = names.iter().map(|name| wrap(quote!(e.in_field(#name))));
+			let missing = names
+				.iter()
+				.map(|name| wrap(quote!(#error::MissingField(#name))));
+			let len = fields.len();
+			let extra =
+				wrap(quote!(#error::UnknownField(::std::string::ToString::to_string(&#len))));
+			quote!({
+				let mut it = ::argdata::Argdata::read_seq(#argdata)#read_err?;
+				#(
+					let #bindings = match it.next() {
+						::std::option::Option::Some(value) => {
+							let value = value#read_err?;
+							::argdata::FromArgdata::from_argdata(&*value).map_err(|e| #field_err)?
+						}
+						::std::option::Option::None => return ::std::result::Result::Err(#missing),
+					};
+				)*
+				if it.next().is_some() {
+					return ::std::result::Result::Err(#extra);
+				}
+				#path(#(#bindings),*)
+			})
+		}
+		Fields::Unit => quote!({
+			::argdata::Argdata::read_null(#argdata)#read_err?;
+			#path
+		}),
+	})
+}
+
+/// Generate an expression that converts the bindings from `pattern()` to an `OwnedArgdata`.
+fn write_fields(fields: &Fields) -> Result<TokenStream, Error> {
+	let owned = quote!(::argdata::OwnedArgdata);
+	let bindings = bindings(fields);
+	Ok(match fields {
+		Fields::Named(named) => {
+			let mut entries = Vec::new();
+			for (field, binding) in named.named.iter().zip(&bindings) {
+				let attrs = field_attrs(&field.attrs, true)?;
+				let name = name(field.ident.as_ref().unwrap(), &attrs);
+				let push = quote!(entries.push((
+					#owned::from(#name),
+					::argdata::ToArgdata::to_argdata(#binding),
+				)));
+				entries.push(if is_option(&field.ty) {
+					quote!(if #binding.is_some() { #push })
+				} else {
+					push
+				});
+			}
+			quote!({
+				let mut entries = ::std::vec::Vec::new();
+				#(#entries;)*
+				#owned::Map(entries)
+			})
+		}
+		Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+			quote!(::argdata::ToArgdata::to_argdata(#(#bindings)*))
+		}
+		Fields::Unnamed(_) => quote!(#owned::Seq(::std::vec![
+			#(::argdata::ToArgdata::to_argdata(#bindings)),*
+		])),
+		Fields::Unit => quote!(#owned::Null),
+	})
+}
+
+fn from_argdata(input: &DeriveInput) -> Result<TokenStream, Error> {
+	let container = container_attrs(&input.attrs)?;
+	let error = quote!(::argdata::FromArgdataError);
+
+	let body = match &input.data {
+		Data::Struct(data) => {
+			let value = read_fields(
+				&quote!(Self),
+				&data.fields,
+				&container,
+				&quote!(argdata),
+				None,
+			)?;
+			quote!(::std::result::Result::Ok(#value))
+		}
+		Data::Enum(data) => {
+			let mut unit_names = Vec::new();
+			let mut unit_variants = Vec::new();
+			let mut names = Vec::new();
+			let mut values = Vec::new();
+			for variant in &data.variants {
+				let attrs = field_attrs(&variant.attrs, false)?;
+				let name = name(&variant.ident, &attrs);
+				let ident = &variant.ident;
+				let path = quote!(Self::#ident);
+				if let Fields::Unit = variant.fields {
+					unit_names.push(name.clone());
+					unit_variants.push(path.clone());
+				}
+				values.push(read_fields(
+					&path,
+					&variant.fields,
+					&container,
+					&quote!(value),
+					Some(&name),
+				)?);
+				names.push(name);
+			}
+			let unknown = quote!(other => ::std::result::Result::Err(
+				#error::UnknownVariant(::std::string::ToString::to_string(other))
+			));
+			quote!(match ::argdata::Argdata::read(argdata)? {
+				::argdata::Value::Str(s) => match s.as_str().map_err(::argdata::ReadError::from)? {
+					#(#unit_names => ::std::result::Result::Ok(#unit_variants),)*
+					#unknown,
+				},
+				::argdata::Value::Map(mut it) => {
+					let (key, value) = match it.next() {
+						::std::option::Option::Some(entry) => entry?,
+						::std::option::Option::None => {
+							return ::std::result::Result::Err(#error::InvalidVariant);
+						}
+					};
+					if it.next().is_some() {
+						return ::std::result::Result::Err(#error::InvalidVariant);
+					}
+					let value: &dyn ::argdata::Argdata<'__d> = &*value;
+					match ::argdata::ArgdataExt::read_str(&*key)? {
+						#(#names => ::std::result::Result::Ok(#values),)*
+						#unknown,
+					}
+				}
+				_ => ::std::result::Result::Err(#error::NoFit(::argdata::NoFit::DifferentType)),
+			})
+		}
+		Data::Union(_) => {
+			return Err(Error::new_spanned(
+				input,
+				"FromArgdata can not be derived for unions",
+			));
+		}
+	};
+
+	// The data lifetime needs to outlive all lifetime parameters, since
+	// fields might borrow from the data.
+	let d = Lifetime::new("'__d", Span::call_site());
+	let mut generics = input.generics.clone();
+	let lifetimes = generics
+		.lifetimes()
+		.map(|l| l.lifetime.clone())
+		.collect::<Vec<_>>();
+	let types = generics
+		.type_params()
+		.map(|t| t.ident.clone())
+		.collect::<Vec<_>>();
+	generics
+		.params
+		.insert(0, GenericParam::Lifetime(parse_quote!(#d)));
+	let where_clause = generics.make_where_clause();
+	for lifetime in lifetimes {
+		where_clause.predicates.push(parse_quote!(#d: #lifetime));
+	}
+	for ty in types {
+		where_clause
+			.predicates
+			.push(parse_quote!(#ty: ::argdata::FromArgdata<#d>));
+	}
+	let (impl_generics, _, where_clause) = generics.split_for_impl();
+	let (_, ty_generics, _) = input.generics.split_for_impl();
+	let ident = &input.ident;
+
+	Ok(quote! {
+		impl #impl_generics ::argdata::FromArgdata<#d> for #ident #ty_generics #where_clause {
+			fn from_argdata(
+				argdata: &(dyn ::argdata::Argdata<#d> + '_),
+			) -> ::std::result::Result<Self, #error> {
+				#body
+			}
+		}
+	})
+}
+
+fn to_argdata(input: &DeriveInput) -> Result<TokenStream, Error> {
+	let body = match &input.data {
+		Data::Struct(data) => {
+			let pattern = pattern(&quote!(Self), &data.fields);
+			let value = write_fields(&data.fields)?;
+			quote!(let #pattern = self; #value)
+		}
+		Data::Enum(data) => {
+			let mut patterns = Vec::new();
+			let mut values = Vec::new();
+			for variant in &data.variants {
+				let attrs = field_attrs(&variant.attrs, false)?;
+				let name = name(&variant.ident, &attrs);
+				let ident = &variant.ident;
+				patterns.push(pattern(&quote!(Self::#ident), &variant.fields));
+				values.push(match variant.fields {
+					Fields::Unit => quote!(::argdata::OwnedArgdata::from(#name)),
+					_ => {
+						let value = write_fields(&variant.fields)?;
+						quote!(::argdata::OwnedArgdata::Map(::std::vec![
+							(::argdata::OwnedArgdata::from(#name), #value)
+						]))
+					}
+				});
+			}
+			quote!(match self {
+				#(#patterns => #values,)*
+			})
+		}
+		Data::Union(_) => {
+			return Err(Error::new_spanned(
+				input,
+				"ToArgdata can not be derived for unions",
+			));
+		}
+	};
+
+	let mut generics = input.generics.clone();
+	let types = generics
+		.type_params()
+		.map(|t| t.ident.clone())
+		.collect::<Vec<_>>();
+	let where_clause = generics.make_where_clause();
+	for ty in types {
+		where_clause
+			.predicates
+			.push(parse_quote!(#ty: ::argdata::ToArgdata));
+	}
+	let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+	let ident = &input.ident;
+
+	Ok(quote! {
+		impl #impl_generics ::argdata::ToArgdata for #ident #ty_generics #where_clause {
+			fn to_argdata(&self) -> ::argdata::OwnedArgdata {
+				#body
+			}
+		}
+	})
+}
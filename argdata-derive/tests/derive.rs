@@ -0,0 +1,218 @@
+use argdata::{fd, Argdata, FromArgdata, FromArgdataError, OwnedArgdata, Timespec, ToArgdata};
+
+#[derive(FromArgdata, ToArgdata, Debug, PartialEq)]
+struct Config<'a> {
+	name: &'a str,
+	#[argdata(rename = "listen")]
+	socket: fd::Fd,
+	started: Timespec,
+	comment: Option<String>,
+	#[argdata(default)]
+	verbose: bool,
+	#[argdata(default = "default_port")]
+	port: u16,
+	mode: Mode,
+	point: Point,
+	id: Id,
+}
+
+fn default_port() -> u16 {
+	80
+}
+
+#[derive(FromArgdata, ToArgdata, Debug, PartialEq)]
+#[argdata(deny_unknown_fields)]
+enum Mode {
+	#[argdata(rename = "plain")]
+	Plain,
+	Tls(String),
+	Both(i32, i32),
+	Named {
+		x: i32,
+	},
+}
+
+#[derive(FromArgdata, ToArgdata, Debug, PartialEq)]
+struct Point(i32, i32);
+
+#[derive(FromArgdata, ToArgdata, Debug, PartialEq)]
+struct Id(u64);
+
+#[derive(FromArgdata, ToArgdata, Debug, PartialEq)]
+struct Empty;
+
+#[derive(FromArgdata, ToArgdata, Debug, PartialEq)]
+#[argdata(deny_unknown_fields)]
+struct Strict<T> {
+	value: T,
+}
+
+fn config<'a>() -> Config<'a> {
+	Config {
+		name: "server",
+		socket: fd::Fd(3),
+		started: Timespec { sec: 5, nsec: 6 },
+		comment: None,
+		verbose: false,
+		port: 80,
+		mode: Mode::Named { x: 1 },
+		point: Point(1, -1),
+		id: Id(7),
+	}
+}
+
+#[test]
+fn roundtrip_test() {
+	let owned = config().to_argdata();
+	assert_eq!(
+		format!("{:?}", owned),
+		"{\"name\": \"server\", \"listen\": fd(3), \"started\": timestamp(5, 6), \
+		 \"verbose\": false, \"port\": 80, \"mode\": {\"Named\": {\"x\": 1}}, \
+		 \"point\": [1, -1], \"id\": 7}"
+	);
+	assert_eq!(Config::from_argdata(owned.argdata()), Ok(config()));
+
+	for mode in [
+		Mode::Plain,
+		Mode::Tls("x".to_string()),
+		Mode::Both(1, 2),
+		Mode::Named { x: 3 },
+	] {
+		let owned = mode.to_argdata();
+		assert_eq!(Mode::from_argdata(owned.argdata()), Ok(mode));
+	}
+	assert_eq!(format!("{:?}", Mode::Plain.to_argdata()), "\"plain\"");
+
+	let owned = Empty.to_argdata();
+	assert_eq!(Empty::from_argdata(owned.argdata()), Ok(Empty));
+}
+
+#[test]
+fn defaults_test() {
+	let owned = OwnedArgdata::Map(vec![
+		("name".into(), "x".into()),
+		("listen".into(), fd::Fd(1).into()),
+		("started".into(), Timespec { sec: 0, nsec: 0 }.into()),
+		("mode".into(), "plain".into()),
+		("point".into(), OwnedArgdata::Seq(vec![1.into(), 2.into()])),
+		("id".into(), 0.into()),
+		("ignored".into(), OwnedArgdata::Null),
+	]);
+	let config = Config::from_argdata(owned.argdata()).unwrap();
+	assert_eq!(config.comment, None);
+	assert!(!config.verbose);
+	assert_eq!(config.port, 80);
+}
+
+#[test]
+fn option_test() {
+	#[derive(FromArgdata, ToArgdata, Debug, PartialEq)]
+	struct Optional {
+		a: Option<u8>,
+		b: Option<Option<u8>>,
+	}
+
+	for (value, text) in [
+		(Optional { a: None, b: None }, "{}"),
+		(
+			Optional {
+				a: Some(1),
+				b: Some(None),
+			},
+			"{\"a\": 1, \"b\": null}",
+		),
+		(
+			Optional {
+				a: None,
+				b: Some(Some(2)),
+			},
+			"{\"b\": 2}",
+		),
+	] {
+		let owned = value.to_argdata();
+		assert_eq!(format!("{:?}", owned), text);
+		assert_eq!(Optional::from_argdata(owned.argdata()), Ok(value));
+	}
+
+	let owned = OwnedArgdata::Map(vec![("a".into(), OwnedArgdata::Null)]);
+	assert_eq!(
+		Optional::from_argdata(owned.argdata()),
+		Ok(Optional { a: None, b: None })
+	);
+	let owned = OwnedArgdata::Map(vec![("a".into(), "x".into())]);
+	assert_eq!(
+		Optional::from_argdata(owned.argdata()),
+		Err(FromArgdataError::NoFit(argdata::NoFit::DifferentType).in_field("a"))
+	);
+	assert_eq!(Some(None::<u8>).to_argdata(), OwnedArgdata::Null);
+}
+
+#[test]
+fn error_test() {
+	fn read<'d, T: FromArgdata<'d>>(owned: &'d OwnedArgdata) -> Result<T, FromArgdataError> {
+		T::from_argdata(owned.argdata())
+	}
+
+	let owned = OwnedArgdata::Map(vec![("name".into(), 1.into())]);
+	assert_eq!(
+		read::<Config>(&owned),
+		Err(FromArgdataError::NoFit(argdata::NoFit::DifferentType).in_field("name"))
+	);
+	assert_eq!(
+		read::<Config>(&owned).unwrap_err().to_string(),
+		"In `name`: Value of unexpected type"
+	);
+
+	let owned = OwnedArgdata::Map(vec![("name".into(), "x".into())]);
+	assert_eq!(
+		read::<Config>(&owned),
+		Err(FromArgdataError::MissingField("listen"))
+	);
+
+	let owned = OwnedArgdata::Map(vec![("Named".into(), OwnedArgdata::Map(vec![]))]);
+	assert_eq!(
+		read::<Mode>(&owned),
+		Err(FromArgdataError::MissingField("x").in_field("Named"))
+	);
+
+	let owned = OwnedArgdata::Map(vec![(
+		"Named".into(),
+		OwnedArgdata::Map(vec![("x".into(), 1.into()), ("y".into(), 2.into())]),
+	)]);
+	assert_eq!(
+		read::<Mode>(&owned),
+		Err(FromArgdataError::UnknownField("y".to_string()).in_field("Named"))
+	);
+
+	assert_eq!(
+		read::<Mode>(&"Plain".into()),
+		Err(FromArgdataError::UnknownVariant("Plain".to_string()))
+	);
+
+	let owned = OwnedArgdata::Seq(vec![1.into(), 2.into(), 3.into()]);
+	assert_eq!(
+		read::<Point>(&owned),
+		Err(FromArgdataError::UnknownField("2".to_string()))
+	);
+
+	let owned = OwnedArgdata::Map(vec![("value".into(), 300.into())]);
+	assert_eq!(
+		read::<Strict<u8>>(&owned),
+		Err(FromArgdataError::NoFit(argdata::NoFit::OutOfRange).in_field("value"))
+	);
+	assert_eq!(read::<Strict<u16>>(&owned), Ok(Strict { value: 300 }));
+}
+
+#[test]
+fn borrow_test() {
+	let data = b"\x06\x86\x08name\x00\x87\x08hello\x00";
+	let encoded = argdata::encoded(&data[..]);
+
+	#[derive(FromArgdata)]
+	struct Borrowed<'a> {
+		name: &'a str,
+	}
+	let value = Borrowed::from_argdata(&encoded as &dyn Argdata).unwrap();
+	assert_eq!(value.name, "hello");
+	assert_eq!(value.name.as_ptr(), data[10..].as_ptr());
+}
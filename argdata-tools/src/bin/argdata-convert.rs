@@ -0,0 +1,232 @@
+//! Converts between encoded argdata and the human-readable formats: JSON,
+//! YAML and the text notation used by `Debug`.
+//!
+//! Usage: `argdata-convert --from FORMAT --to FORMAT [OPTIONS] [FILE]`
+//!
+//! Reads from standard input if no file (or `-`) is given, and writes to
+//! standard output. The formats are `argdata`, `json`, `yaml` and `text`.
+//!
+//! The options select how types that JSON doesn't have are represented, both
+//! when writing and when parsing JSON. See `argdata::json::Options`.
+//!
+//! Exits with status 1 if the input is invalid. Argdata nested more than 256
+//! levels deep is not converted to the other formats, just like those formats
+//! are not parsed when nested that deep.
+
+use argdata::json::{self, BigIntFormat, BinaryFormat, FdFormat, MapKeyFormat, TimestampFormat};
+use argdata::{text, yaml, Argdata};
+use std::io::{self, Read, Write};
+use std::process::exit;
+
+const USAGE: &str = "\
+Usage: argdata-convert --from FORMAT --to FORMAT [OPTIONS] [FILE]
+
+Formats: argdata, json, yaml, text
+
+JSON options:
+  --binary base64|tagged|int-array   (default: tagged)
+  --timestamp rfc3339|tagged         (default: tagged)
+  --fd tagged|number                 (default: tagged)
+  --big-int number|string            (default: number)
+  --map-keys stringify|entries       (default: stringify)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+	Argdata,
+	Json,
+	Yaml,
+	Text,
+}
+
+impl Format {
+	fn parse(s: &str) -> Option<Format> {
+		match s {
+			"argdata" => Some(Format::Argdata),
+			"json" => Some(Format::Json),
+			"yaml" => Some(Format::Yaml),
+			"text" => Some(Format::Text),
+			_ => None,
+		}
+	}
+}
+
+/// Convert the input from one format to another.
+///
+/// Argdata is copied without being checked if both formats are `argdata`.
+fn convert(
+	input: &[u8],
+	from: Format,
+	to: Format,
+	options: &json::Options,
+) -> Result<Vec<u8>, String> {
+	let owned;
+	let encoded;
+	let value: &dyn Argdata = if from == Format::Argdata {
+		encoded = argdata::encoded(input);
+		&encoded
+	} else {
+		let s = std::str::from_utf8(input).map_err(|_| "Input is not valid UTF-8".to_string())?;
+		owned = match from {
+			Format::Json => json::from_str(s, options).map_err(|e| e.to_string())?,
+			Format::Yaml => yaml::from_str(s).map_err(|e| e.to_string())?,
+			Format::Text => text::parse(s).map_err(|e| e.to_string())?,
+			Format::Argdata => unreachable!(),
+		};
+		owned.argdata()
+	};
+
+	let mut out = match to {
+		Format::Argdata => {
+			let mut out = Vec::with_capacity(value.serialized_length());
+			value.serialize(&mut out, None).unwrap();
+			return Ok(out);
+		}
+		Format::Json => json::to_string(value, options).map_err(|e| e.to_string())?,
+		Format::Yaml => {
+			// The YAML output contains read errors instead of failing on
+			// them, so check for those first.
+			text::to_string(value).map_err(|e| e.to_string())?;
+			return Ok(yaml::Yaml(value).to_string().into_bytes());
+		}
+		Format::Text => text::to_string(value).map_err(|e| e.to_string())?,
+	};
+	out.push('\n');
+	Ok(out.into_bytes())
+}
+
+fn usage_error() -> ! {
+	eprintln!("{}", USAGE);
+	exit(2);
+}
+
+fn main() {
+	let mut from = None;
+	let mut to = None;
+	let mut options = json::Options::default();
+	let mut file = None;
+	let mut args = std::env::args().skip(1);
+	while let Some(arg) = args.next() {
+		if arg == "--help" || arg == "-h" {
+			println!("{}", USAGE);
+			return;
+		}
+		if file.is_none() && (arg == "-" || !arg.starts_with('-')) {
+			file = Some(arg);
+			continue;
+		}
+		let value = args.next().unwrap_or_else(|| usage_error());
+		match (&arg[..], &value[..]) {
+			("--from", f) => from = Some(Format::parse(f).unwrap_or_else(|| usage_error())),
+			("--to", f) => to = Some(Format::parse(f).unwrap_or_else(|| usage_error())),
+			("--binary", "base64") => options.binary = BinaryFormat::Base64,
+			("--binary", "tagged") => options.binary = BinaryFormat::Tagged,
+			("--binary", "int-array") => options.binary = BinaryFormat::IntArray,
+			("--timestamp", "rfc3339") => options.timestamp = TimestampFormat::Rfc3339,
+			("--timestamp", "tagged") => options.timestamp = TimestampFormat::Tagged,
+			("--fd", "tagged") => options.fd = FdFormat::Tagged,
+			("--fd", "number") => options.fd = FdFormat::Number,
+			("--big-int", "number") => options.big_int = BigIntFormat::Number,
+			("--big-int", "string") => options.big_int = BigIntFormat::String,
+			("--map-keys", "stringify") => options.map_keys = MapKeyFormat::Stringify,
+			("--map-keys", "entries") => options.map_keys = MapKeyFormat::Entries,
+			_ => usage_error(),
+		}
+	}
+	let (from, to) = match (from, to) {
+		(Some(from), Some(to)) => (from, to),
+		_ => usage_error(),
+	};
+
+	let name = file.as_deref().unwrap_or("stdin");
+	let mut input = Vec::new();
+	let result = match file.as_deref() {
+		None | Some("-") => io::stdin().read_to_end(&mut input),
+		Some(path) => std::fs::File::open(path).and_then(|mut f| f.read_to_end(&mut input)),
+	};
+	if let Err(e) = result {
+		eprintln!("argdata-convert: {}: {}", name, e);
+		exit(2);
+	}
+
+	match convert(&input, from, to, &options) {
+		Ok(out) => io::stdout().write_all(&out).unwrap(),
+		Err(e) => {
+			eprintln!("argdata-convert: {}: {}", name, e);
+			exit(1);
+		}
+	}
+}
+
+#[test]
+fn convert_test() {
+	let options = json::Options::default();
+	let text = b"{\"a\": [1, true, fd(3)], \"b\": binary([1, 2])}\n";
+	let data = convert(text, Format::Text, Format::Argdata, &options).unwrap();
+	assert_eq!(
+		convert(&data, Format::Argdata, Format::Text, &options).unwrap(),
+		&text[..]
+	);
+	assert_eq!(
+		convert(&data, Format::Argdata, Format::Json, &options).unwrap(),
+		&b"{\"a\":[1,true,{\"fd\":3}],\"b\":{\"binary\":\"AQI=\"}}\n"[..]
+	);
+	let yaml = convert(&data, Format::Argdata, Format::Yaml, &options).unwrap();
+	assert_eq!(
+		convert(&yaml, Format::Yaml, Format::Argdata, &options).unwrap(),
+		data
+	);
+
+	let options = json::Options {
+		binary: BinaryFormat::IntArray,
+		fd: FdFormat::Number,
+		..options
+	};
+	assert_eq!(
+		convert(&data, Format::Argdata, Format::Json, &options).unwrap(),
+		&b"{\"a\":[1,true,3],\"b\":[1,2]}\n"[..]
+	);
+}
+
+#[test]
+fn convert_error_test() {
+	let options = json::Options::default();
+	assert_eq!(
+		convert(b"[1,]", Format::Json, Format::Argdata, &options).unwrap_err(),
+		"Invalid JSON value at byte 3"
+	);
+	assert_eq!(
+		convert(b"\x07\x82\x08a", Format::Argdata, Format::Yaml, &options).unwrap_err(),
+		"Argdata contains a string without nul terminator"
+	);
+	assert_eq!(
+		convert(b"\xFF", Format::Text, Format::Argdata, &options).unwrap_err(),
+		"Input is not valid UTF-8"
+	);
+}
+
+#[test]
+fn convert_depth_test() {
+	let options = json::Options::default();
+	// The deepest nesting that can be parsed from text.
+	let text = "[".repeat(257) + &"]".repeat(257);
+	let data = convert(text.as_bytes(), Format::Text, Format::Argdata, &options).unwrap();
+	for &to in &[Format::Json, Format::Yaml, Format::Text] {
+		assert!(convert(&data, Format::Argdata, to, &options).is_ok());
+	}
+
+	let elements = vec![argdata::encoded(&data)];
+	let mut deeper = Vec::new();
+	argdata::seq(&elements)
+		.serialize(&mut deeper, None)
+		.unwrap();
+	for &to in &[Format::Json, Format::Yaml, Format::Text] {
+		assert_eq!(
+			convert(&deeper, Format::Argdata, to, &options).unwrap_err(),
+			"Argdata is nested too deeply"
+		);
+	}
+	assert_eq!(
+		convert(&deeper, Format::Argdata, Format::Argdata, &options).unwrap(),
+		deeper
+	);
+}
@@ -0,0 +1,374 @@
+//! Prints an encoded argdata value as a tree, showing the type and byte
+//! offset of every value.
+//!
+//! Usage: `argdata-inspect [--bytes] [FILE]`
+//!
+//! Reads from standard input if no file (or `-`) is given. With `--bytes`,
+//! the raw bytes are shown as well: the tag byte of every value, the length
+//! prefix of every element of a map or seq, and the payload.
+//!
+//! Values nested more than 32 levels deep are not indented any further, but
+//! shown with their depth.
+//!
+//! Exits with status 1 if the data is invalid, after printing everything up to
+//! and including the error.
+
+use argdata::{Argdata, ReadError, Value};
+use std::fmt::Write as _;
+use std::io::{self, Read, Write as _};
+use std::process::exit;
+
+const USAGE: &str = "Usage: argdata-inspect [--bytes] [FILE]";
+
+/// The number of payload bytes shown per line.
+const BYTES_PER_LINE: usize = 8;
+
+/// The deepest level of nesting that is shown by indentation. Deeper values
+/// are shown with their depth instead.
+const MAX_INDENT: usize = 32;
+
+struct Inspector {
+	/// Whether to show the raw bytes.
+	bytes: bool,
+	out: String,
+}
+
+impl Inspector {
+	fn line(&mut self, offset: usize, raw: &[u8], depth: usize, text: &str) {
+		write!(self.out, "{:08x}  ", offset).unwrap();
+		if self.bytes {
+			let hex: Vec<String> = raw.iter().map(|b| format!("{:02x}", b)).collect();
+			write!(self.out, "{:<1$}", hex.join(" "), BYTES_PER_LINE * 3).unwrap();
+		}
+		if depth <= MAX_INDENT {
+			writeln!(self.out, "{:2$}{}", "", text, depth * 2).unwrap();
+		} else {
+			let indent = MAX_INDENT * 2;
+			writeln!(self.out, "{:3$}[depth {}] {}", "", depth, text, indent).unwrap();
+		}
+	}
+
+	fn error(&mut self, offset: usize, depth: usize, error: ReadError) {
+		self.line(offset, &[], depth, &format!("error: {}", error));
+	}
+
+	/// Print the payload of a value, if showing raw bytes.
+	fn payload(&mut self, offset: usize, payload: &[u8], depth: usize) {
+		if self.bytes {
+			for (i, chunk) in payload.chunks(BYTES_PER_LINE).enumerate() {
+				let text = if i == 0 { "payload" } else { "" };
+				self.line(offset + i * BYTES_PER_LINE, chunk, depth + 1, text);
+			}
+		}
+	}
+
+	/// Print the value encoded in `data`, which starts at `offset` in the input.
+	///
+	/// Returns false if the value (or anything inside it) is invalid.
+	///
+	/// Doesn't recurse, so arbitrarily deeply nested values can be inspected.
+	fn value(&mut self, data: &[u8], offset: usize) -> bool {
+		// The maps and seqs whose elements are being printed, innermost last.
+		let mut stack = Vec::new();
+		let mut next = Some((data, offset, 0, ""));
+		loop {
+			if let Some((data, offset, depth, label)) = next.take() {
+				match self.single(data, offset, depth, label) {
+					Step::Container(container) => stack.push(container),
+					Step::Done => {}
+					Step::Invalid => return false,
+				}
+			}
+			let container = match stack.last_mut() {
+				Some(container) => container,
+				None => return true,
+			};
+			match self.next_element(container) {
+				Some(Ok(element)) => next = Some(element),
+				Some(Err(())) => return false,
+				None => {
+					stack.pop();
+				}
+			}
+		}
+	}
+
+	/// Print a single value, without the elements of a map or seq.
+	fn single<'d>(&mut self, data: &'d [u8], offset: usize, depth: usize, label: &str) -> Step<'d> {
+		let tag = &data[..data.len().min(1)];
+		match data.first() {
+			Some(&tag @ (6 | 7)) => {
+				let is_map = tag == 6;
+				let n = subfields(&data[1..]);
+				let text = if is_map {
+					format!("{}map, {} entries", label, n / 2)
+				} else {
+					format!("{}seq, {} elements", label, n)
+				};
+				self.line(offset, &data[..1], depth, &text);
+				Step::Container(Container {
+					data: &data[1..],
+					offset: offset + 1,
+					depth: depth + 1,
+					is_map,
+					pos: 0,
+					n: 0,
+				})
+			}
+			_ => match argdata::encoded(data).read().and_then(|v| describe(&v)) {
+				Ok(description) => {
+					let text = format!("{}{}", label, description);
+					self.line(offset, tag, depth, &text);
+					self.payload(offset + 1, data.get(1..).unwrap_or(&[]), depth);
+					Step::Done
+				}
+				Err(e) => {
+					self.line(offset, tag, depth, &format!("{}(invalid)", label));
+					self.error(offset, depth + 1, e);
+					self.payload(offset + 1, data.get(1..).unwrap_or(&[]), depth);
+					Step::Invalid
+				}
+			},
+		}
+	}
+
+	/// Print the length of the next element of a map or seq, and return the
+	/// element, with its offset, depth and label.
+	///
+	/// Returns None after the last element, or an error if the rest of the
+	/// map or seq is invalid.
+	#[allow(clippy::type_complexity)]
+	fn next_element<'d>(
+		&mut self,
+		c: &mut Container<'d>,
+	) -> Option<Result<(&'d [u8], usize, usize, &'static str), ()>> {
+		let data = c.data;
+		let (offset, depth, pos) = (c.offset, c.depth, c.pos);
+		if pos == data.len() {
+			if c.is_map && !c.n.is_multiple_of(2) {
+				self.error(offset + pos, depth, ReadError::InvalidKeyValuePair);
+				return Some(Err(()));
+			}
+			return None;
+		}
+		let (len, len_bytes) = match subfield_length(&data[pos..]) {
+			Some(v) => v,
+			None => {
+				self.line(offset + pos, &data[pos..], depth, "length (invalid)");
+				self.error(offset + pos, depth + 1, ReadError::InvalidSubfield);
+				return Some(Err(()));
+			}
+		};
+		if self.bytes {
+			let text = format!("length {}", len);
+			self.line(offset + pos, &data[pos..][..len_bytes], depth, &text);
+		}
+		let start = pos + len_bytes;
+		let label = match (c.is_map, c.n % 2) {
+			(false, _) => "",
+			(true, 0) => "key: ",
+			(true, _) => "value: ",
+		};
+		c.pos = start + len;
+		c.n += 1;
+		Some(Ok((&data[start..][..len], offset + start, depth, label)))
+	}
+}
+
+/// The result of printing a single value.
+enum Step<'d> {
+	/// A valid value, other than a map or seq.
+	Done,
+	/// A map or seq, of which the elements still need to be printed.
+	Container(Container<'d>),
+	/// An invalid value.
+	Invalid,
+}
+
+/// A map or seq whose elements are being printed.
+struct Container<'d> {
+	/// The encoded elements.
+	data: &'d [u8],
+	/// The offset of `data` in the input.
+	offset: usize,
+	/// The depth of the elements.
+	depth: usize,
+	is_map: bool,
+	/// The position of the next element in `data`.
+	pos: usize,
+	/// The number of elements so far.
+	n: usize,
+}
+
+/// Decode the length prefix of a subfield, returning the length and the size
+/// of the prefix. Returns None if the prefix is incomplete or the length
+/// exceeds the remaining data.
+fn subfield_length(data: &[u8]) -> Option<(usize, usize)> {
+	let mut len: usize = 0;
+	for (i, &byte) in data.iter().enumerate() {
+		if len > usize::MAX >> 7 {
+			return None;
+		}
+		len = len << 7 | (byte & 0x7F) as usize;
+		if byte >= 0x80 {
+			let prefix = i + 1;
+			return Some((len, prefix)).filter(|_| len <= data.len() - prefix);
+		}
+	}
+	None
+}
+
+/// Count the valid subfields at the start of the data.
+fn subfields(mut data: &[u8]) -> usize {
+	let mut n = 0;
+	while let Some((len, len_bytes)) = subfield_length(data) {
+		data = &data[len_bytes + len..];
+		n += 1;
+	}
+	n
+}
+
+/// Describe a value other than a map or seq.
+fn describe(value: &Value) -> Result<String, ReadError> {
+	Ok(match value {
+		Value::Null => "null".to_string(),
+		Value::Binary(v) => format!("binary, {} bytes", v.len()),
+		Value::Bool(v) => format!("bool {}", v),
+		Value::Fd(v) => format!("fd {}", v.raw_encoded_number()),
+		Value::Float(v) => format!("float {:?}", v),
+		Value::Int(v) => format!("int {}", v),
+		Value::Str(v) => format!("str {:?}", v.as_str()?),
+		Value::Timestamp(v) => format!("timestamp {}s {}ns", v.sec, v.nsec),
+		Value::Map(_) | Value::Seq(_) => unreachable!(),
+	})
+}
+
+/// Inspect the data, returning the output and whether the data was valid.
+fn inspect(data: &[u8], bytes: bool) -> (String, bool) {
+	let mut inspector = Inspector {
+		bytes,
+		out: String::new(),
+	};
+	let valid = inspector.value(data, 0);
+	(inspector.out, valid)
+}
+
+fn main() {
+	let mut bytes = false;
+	let mut file = None;
+	for arg in std::env::args().skip(1) {
+		match &arg[..] {
+			"--bytes" | "-b" => bytes = true,
+			"--help" | "-h" => {
+				println!("{}", USAGE);
+				return;
+			}
+			_ if file.is_none() && (arg == "-" || !arg.starts_with('-')) => file = Some(arg),
+			_ => {
+				eprintln!("{}", USAGE);
+				exit(2);
+			}
+		}
+	}
+
+	let mut data = Vec::new();
+	let result = match file.as_deref() {
+		None | Some("-") => io::stdin().read_to_end(&mut data),
+		Some(path) => std::fs::File::open(path).and_then(|mut f| f.read_to_end(&mut data)),
+	};
+	if let Err(e) = result {
+		eprintln!(
+			"argdata-inspect: {}: {}",
+			file.as_deref().unwrap_or("stdin"),
+			e
+		);
+		exit(2);
+	}
+
+	let (out, valid) = inspect(&data, bytes);
+	io::stdout().write_all(out.as_bytes()).unwrap();
+	if !valid {
+		exit(1);
+	}
+}
+
+#[test]
+fn inspect_test() {
+	let data = b"\x06\x87\x08Hello\x00\x83\x07\x81\x02";
+	let (out, valid) = inspect(data, false);
+	assert!(valid);
+	assert_eq!(
+		out,
+		"00000000  map, 1 entries\n\
+		 00000002    key: str \"Hello\"\n\
+		 0000000a    value: seq, 1 elements\n\
+		 0000000c      bool false\n"
+	);
+
+	let (out, valid) = inspect(&data[..], true);
+	assert!(valid);
+	assert_eq!(
+		out,
+		"00000000  06                      map, 1 entries\n\
+		 00000001  87                        length 7\n\
+		 00000002  08                        key: str \"Hello\"\n\
+		 00000003  48 65 6c 6c 6f 00           payload\n\
+		 00000009  83                        length 3\n\
+		 0000000a  07                        value: seq, 1 elements\n\
+		 0000000b  81                          length 1\n\
+		 0000000c  02                          bool false\n"
+	);
+}
+
+#[test]
+fn inspect_error_test() {
+	let (out, valid) = inspect(b"\x07\x81\x02\x82\x08a\x81\x02", false);
+	assert!(!valid);
+	assert_eq!(
+		out,
+		"00000000  seq, 3 elements\n\
+		 00000002    bool false\n\
+		 00000004    (invalid)\n\
+		 00000004      error: Argdata contains a string without nul terminator\n"
+	);
+
+	let (out, valid) = inspect(b"\x06\x81\x02\x05", false);
+	assert!(!valid);
+	assert_eq!(
+		out,
+		"00000000  map, 0 entries\n\
+		 00000002    key: bool false\n\
+		 00000003    length (invalid)\n\
+		 00000003      error: Argdata has an incomplete subfield\n"
+	);
+}
+
+#[test]
+fn inspect_invalid_test() {
+	let (out, valid) = inspect(b"\x08\xff\x00", false);
+	assert!(!valid);
+	assert_eq!(
+		out,
+		"00000000  (invalid)\n\
+		 00000000    error: Argdata contains invalid UTF-8\n"
+	);
+
+	// 200000 nested seqs: The prefixes of the outer seqs, innermost first.
+	let mut prefixes = vec![];
+	let mut len = 1;
+	for _ in 1..200_000 {
+		let mut prefix = vec![7];
+		let groups = (0..).take_while(|i| i == &0 || len >> (7 * i) != 0).count();
+		for i in (0..groups).rev() {
+			let last = if i == 0 { 0x80 } else { 0 };
+			prefix.push((len >> (7 * i)) as u8 & 0x7F | last);
+		}
+		len += prefix.len();
+		prefixes.push(prefix);
+	}
+	let mut data: Vec<u8> = prefixes.into_iter().rev().flatten().collect();
+	data.push(7);
+	let (out, valid) = inspect(&data, false);
+	assert!(valid);
+	assert_eq!(out.lines().count(), 200_000);
+}
@@ -0,0 +1,83 @@
+//! Base64 encoding as described in RFC 4648, with the standard alphabet.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode the data as base64, with padding.
+pub(crate) fn encode(data: &[u8]) -> String {
+	let mut s = String::with_capacity(data.len().div_ceil(3) * 4);
+	for chunk in data.chunks(3) {
+		let b = [
+			chunk[0],
+			*chunk.get(1).unwrap_or(&0),
+			*chunk.get(2).unwrap_or(&0),
+		];
+		let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+		for i in 0..4 {
+			if i <= chunk.len() {
+				s.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+			} else {
+				s.push('=');
+			}
+		}
+	}
+	s
+}
+
+/// Decode base64 data. Padding is optional, but whitespace is not accepted.
+pub(crate) fn decode(s: &str) -> Option<Vec<u8>> {
+	// If there is padding, it must pad to a multiple of 4 characters.
+	let unpadded = s.trim_end_matches('=');
+	let padding = s.len() - unpadded.len();
+	if padding != 0 && (padding > 2 || !s.len().is_multiple_of(4)) {
+		return None;
+	}
+	let s = unpadded;
+	let mut data = Vec::with_capacity(s.len() * 3 / 4);
+	let mut n = 0u32;
+	let mut bits = 0;
+	for c in s.bytes() {
+		let v = ALPHABET.iter().position(|&a| a == c)? as u32;
+		n = n << 6 | v;
+		bits += 6;
+		if bits >= 8 {
+			bits -= 8;
+			data.push((n >> bits) as u8);
+		}
+	}
+	// Leftover bits must be zero, and there can't be a single leftover character.
+	if bits >= 6 || n & ((1 << bits) - 1) != 0 {
+		return None;
+	}
+	Some(data)
+}
+
+#[test]
+fn base64_test() {
+	let cases: &[(&[u8], &str)] = &[
+		(b"", ""),
+		(b"f", "Zg=="),
+		(b"fo", "Zm8="),
+		(b"foo", "Zm9v"),
+		(b"foob", "Zm9vYg=="),
+		(b"fooba", "Zm9vYmE="),
+		(b"foobar", "Zm9vYmFy"),
+		(b"\xFF\xFE", "//4="),
+	];
+	for &(data, text) in cases {
+		assert_eq!(encode(data), text);
+		assert_eq!(decode(text).as_ref().map(|v| &v[..]), Some(data));
+		assert_eq!(
+			decode(text.trim_end_matches('=')).as_ref().map(|v| &v[..]),
+			Some(data)
+		);
+	}
+	assert_eq!(decode("Zg"), Some(b"f".to_vec()));
+	assert_eq!(decode("Zh"), None);
+	assert_eq!(decode("Z"), None);
+	assert_eq!(decode("Zm9v!"), None);
+	assert_eq!(decode("Zg="), None);
+	assert_eq!(decode("Zm8=="), None);
+	assert_eq!(decode("QQ======"), None);
+	assert_eq!(decode("Zm9v===="), None);
+	assert_eq!(decode("===="), None);
+}
@@ -0,0 +1,323 @@
+//! A value has only one canonical encoding: integers and timestamps are
+//! encoded without redundant leading bytes, subfield lengths are minimally
+//! encoded, and (by default) the entries of maps are sorted by the bytes of
+//! their canonically encoded keys, without duplicate keys. Such data is
+//! accepted in [strict](crate::values::EncodedArgdata::strict) mode, and its
+//! maps can be indexed with
+//! [`EncodedIndex::new_sorted`](crate::EncodedIndex::new_sorted).
+//!
+//! The value is read through the `Argdata` trait, so any implementation can
+//! be canonicalized, including non-canonical encoded data. File descriptors
+//! are written with their raw encoded number.
+
+use crate::{
+	parse::MAX_DEPTH,
+	subfield::{read_canonical_subfield, write_subfield_length},
+	Argdata, ReadError, Value,
+};
+use std::collections::HashSet;
+use std::io;
+
+/// How to write maps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+	/// Whether to sort the entries of maps by the bytes of their encoded keys.
+	///
+	/// Entries with the same key keep their order.
+	pub sort_maps: bool,
+	pub duplicate_keys: DuplicateKeys,
+}
+
+/// What to do with maps that contain the same key more than once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKeys {
+	/// Keep all entries.
+	Keep,
+	/// Keep only the first entry with each key, which is the one that is
+	/// found when looking up the key.
+	Drop,
+	/// Fail with `ReadError::DuplicateMapKey`.
+	Reject,
+}
+
+/// Sorts maps, and rejects duplicate keys.
+impl Default for Options {
+	fn default() -> Self {
+		Options {
+			sort_maps: true,
+			duplicate_keys: DuplicateKeys::Reject,
+		}
+	}
+}
+
+/// Write the canonical encoding of a value, with the default [`Options`].
+pub fn canonicalize(value: &dyn Argdata, writer: &mut dyn io::Write) -> io::Result<()> {
+	canonicalize_with(value, writer, &Options::default())
+}
+
+/// Write the canonical encoding of a value.
+///
+/// Fails with an `io::ErrorKind::InvalidData` error containing the
+/// `ReadError` if the value can't be read, if it is nested more than 256
+/// levels deep, or if a map contains duplicate keys while using
+/// `DuplicateKeys::Reject`. In that case, nothing is written.
+pub fn canonicalize_with(
+	value: &dyn Argdata,
+	writer: &mut dyn io::Write,
+	options: &Options,
+) -> io::Result<()> {
+	let data =
+		encode(value, options, 0).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+	writer.write_all(&data)
+}
+
+/// Encode a value that is nested `depth` levels deep.
+pub(crate) fn encode(
+	value: &dyn Argdata,
+	options: &Options,
+	depth: usize,
+) -> Result<Vec<u8>, ReadError> {
+	let mut data = Vec::new();
+	let value = value.read()?;
+	if depth > MAX_DEPTH && matches!(value, Value::Map(_) | Value::Seq(_)) {
+		return Err(ReadError::NestedTooDeeply);
+	}
+	match value {
+		Value::Null => {}
+		Value::Binary(v) => crate::binary(v).serialize(&mut data, None).unwrap(),
+		Value::Bool(v) => crate::bool(v).serialize(&mut data, None).unwrap(),
+		Value::Fd(v) => v.serialize(&mut data, None).unwrap(),
+		Value::Float(v) => crate::float(v).serialize(&mut data, None).unwrap(),
+		Value::Int(v) => {
+			data.push(5);
+			v.serialize(&mut data).unwrap();
+		}
+		Value::Str(v) => crate::str(v.as_str()?).serialize(&mut data, None).unwrap(),
+		Value::Timestamp(v) => crate::timestamp(v).serialize(&mut data, None).unwrap(),
+		Value::Map(map) => {
+			let mut entries = Vec::new();
+			for entry in map {
+				let (key, value) = entry?;
+				entries.push((
+					encode(&*key, options, depth + 1)?,
+					encode(&*value, options, depth + 1)?,
+				));
+			}
+			if options.duplicate_keys != DuplicateKeys::Keep {
+				let mut keys = HashSet::new();
+				let first: Vec<bool> = entries
+					.iter()
+					.map(|(key, _)| keys.insert(key.clone()))
+					.collect();
+				if first.contains(&false) {
+					if options.duplicate_keys == DuplicateKeys::Reject {
+						return Err(ReadError::DuplicateMapKey);
+					}
+					let mut first = first.into_iter();
+					entries.retain(|_| first.next().unwrap());
+				}
+			}
+			if options.sort_maps {
+				entries.sort_by(|a, b| a.0.cmp(&b.0));
+			}
+			data.push(6);
+			for (key, value) in &entries {
+				write_element(&mut data, key);
+				write_element(&mut data, value);
+			}
+		}
+		Value::Seq(seq) => {
+			data.push(7);
+			for element in seq {
+				write_element(&mut data, &encode(&*element?, options, depth + 1)?);
+			}
+		}
+	}
+	Ok(data)
+}
+
+/// Whether encoded data nested `depth` levels deep is already in canonical
+/// form with the default [`Options`]: whether [`encode`] would return the
+/// same bytes. Checked without copying the data.
+pub(crate) fn is_canonical(data: &[u8], depth: usize) -> bool {
+	let encoded = crate::encoded(data);
+	let value = match encoded.read() {
+		Ok(value) => value,
+		Err(_) => return false,
+	};
+	match value {
+		// Only integers, timestamps, and the subfield lengths in maps and
+		// seqs have more than one encoding, which can only differ in length.
+		Value::Int(v) => data.len() == 1 + v.serialized_length(),
+		Value::Str(v) => v.as_str().is_ok(),
+		Value::Timestamp(v) => data.len() == crate::timestamp(v).serialized_length(),
+		Value::Map(_) | Value::Seq(_) if depth > MAX_DEPTH => false,
+		Value::Map(_) | Value::Seq(_) => {
+			let is_map = data[0] == 6;
+			let mut previous_key = None;
+			let mut offset = 1;
+			let mut n_elements = 0;
+			while offset < data.len() {
+				let element = match read_canonical_subfield(&data[offset..]) {
+					(Some(Ok(element)), n) => {
+						offset += n;
+						element
+					}
+					_ => return false,
+				};
+				if !is_canonical(element, depth + 1) {
+					return false;
+				}
+				if is_map && n_elements % 2 == 0 {
+					// Sorted, without duplicates.
+					if previous_key.is_some_and(|key| key >= element) {
+						return false;
+					}
+					previous_key = Some(element);
+				}
+				n_elements += 1;
+			}
+			!is_map || n_elements % 2 == 0
+		}
+		_ => true,
+	}
+}
+
+fn write_element(data: &mut Vec<u8>, element: &[u8]) {
+	write_subfield_length(element.len(), data).unwrap();
+	data.extend_from_slice(element);
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{encoded, OwnedArgdata};
+
+	fn canonical(value: &dyn Argdata, options: &Options) -> Result<Vec<u8>, String> {
+		let mut data = Vec::new();
+		canonicalize_with(value, &mut data, options).map_err(|e| e.to_string())?;
+		Ok(data)
+	}
+
+	#[test]
+	fn canonicalize_test() {
+		let options = Options::default();
+		// Non-minimal ints, timestamps and subfield lengths.
+		let value = encoded(b"\x07\x00\x83\x05\x00\x01\x81\x05\x82\x05\x00\x83\x09\xFF\xFF");
+		assert_eq!(
+			canonical(&value, &options),
+			Ok(b"\x07\x82\x05\x01\x81\x05\x81\x05\x82\x09\xFF".to_vec())
+		);
+		assert_eq!(
+			canonical(&crate::bigint(b"\xFF\xFF\x80"), &options),
+			Ok(b"\x05\x80".to_vec())
+		);
+
+		let canonical_value = canonical(&value, &options).unwrap();
+		assert!(encoded(&canonical_value)
+			.strict(true)
+			.validate_deep()
+			.is_ok());
+		assert_eq!(
+			canonical(&encoded(&canonical_value), &options),
+			Ok(canonical_value)
+		);
+
+		assert_eq!(
+			canonical(&encoded(b"\x08\xFF\x00"), &options),
+			Err("Argdata contains invalid UTF-8".to_string())
+		);
+	}
+
+	#[test]
+	fn is_canonical_test() {
+		let cases: &[&[u8]] = &[
+			b"",
+			b"\x05",
+			b"\x05\x00",
+			b"\x05\xFF\x80",
+			b"\x09\xFF",
+			b"\x09\xFF\xFF",
+			b"\x08a\x00",
+			b"\x08\xFF\x00",
+			b"\x02\x00",
+			b"\x07\x82\x05\x01\x81\x05",
+			b"\x07\x00\x82\x05\x01",
+			b"\x07\x82\x05\x00",
+			b"\x06\x81\x05\x80\x82\x05\x01\x80",
+			b"\x06\x82\x05\x01\x80\x81\x05\x80",
+			b"\x06\x81\x05\x80\x81\x05\x80",
+			b"\x06\x81\x05\x80",
+			b"\x06\x81\x05",
+		];
+		for &data in cases {
+			let canonical = encode(&encoded(data), &Options::default(), 0);
+			assert_eq!(
+				is_canonical(data, 0),
+				canonical.as_deref() == Ok(data),
+				"{:?}",
+				data
+			);
+		}
+		assert!(is_canonical(&crate::parse::nested_seqs(MAX_DEPTH + 1), 0));
+		assert!(!is_canonical(&crate::parse::nested_seqs(MAX_DEPTH + 2), 0));
+	}
+
+	#[test]
+	fn canonicalize_deep_nesting_test() {
+		let options = Options::default();
+		let data = crate::parse::nested_seqs(MAX_DEPTH + 1);
+		assert_eq!(canonical(&encoded(&data), &options), Ok(data));
+		let data = crate::parse::nested_seqs(MAX_DEPTH + 2);
+		assert_eq!(
+			canonical(&encoded(&data), &options),
+			Err("Argdata is nested too deeply".to_string())
+		);
+		let data = crate::parse::nested_seqs(200_000);
+		assert!(encoded(&data).validate_deep().is_ok());
+		assert_eq!(
+			canonical(&encoded(&data), &options),
+			Err("Argdata is nested too deeply".to_string())
+		);
+	}
+
+	#[test]
+	fn canonicalize_map_test() {
+		let map = OwnedArgdata::Map(vec![
+			("b".into(), 1.into()),
+			("a".into(), 2.into()),
+			("b".into(), 3.into()),
+		]);
+		let with = |sort_maps, duplicate_keys| {
+			canonical(
+				map.argdata(),
+				&Options {
+					sort_maps,
+					duplicate_keys,
+				},
+			)
+		};
+		assert_eq!(
+			with(false, DuplicateKeys::Keep),
+			Ok(
+				b"\x06\x83\x08b\x00\x82\x05\x01\x83\x08a\x00\x82\x05\x02\x83\x08b\x00\x82\x05\x03"
+					.to_vec()
+			)
+		);
+		assert_eq!(
+			with(true, DuplicateKeys::Keep),
+			Ok(
+				b"\x06\x83\x08a\x00\x82\x05\x02\x83\x08b\x00\x82\x05\x01\x83\x08b\x00\x82\x05\x03"
+					.to_vec()
+			)
+		);
+		assert_eq!(
+			with(true, DuplicateKeys::Drop),
+			Ok(b"\x06\x83\x08a\x00\x82\x05\x02\x83\x08b\x00\x82\x05\x01".to_vec())
+		);
+		assert_eq!(
+			with(true, DuplicateKeys::Reject),
+			Err("Argdata map contains a duplicate key".to_string())
+		);
+	}
+}
@@ -0,0 +1,551 @@
+//! The argdata types are mapped onto CBOR as follows:
+//!
+//!  - Null, booleans, strings, maps and seqs to their CBOR counterparts.
+//!  - Binary blobs to byte strings.
+//!  - Floats to double precision floats. Half and single precision floats are
+//!    accepted when decoding.
+//!  - Integers to CBOR integers, or to bignums (tags 2 and 3) if they don't fit
+//!    in 64 bits.
+//!  - Timestamps to epoch-based date/times (tag 1) if they have no fractional
+//!    seconds, and to extended times with nanoseconds (tag 1001) otherwise.
+//!    When decoding, tag 1 with a float, and tag 1001 with milli-, micro-, or
+//!    nanoseconds are accepted as well.
+//!  - File descriptors to an unsigned integer tagged with [`FD_TAG`].
+//!
+//! Other tags are ignored when decoding, and their content is decoded as if
+//! it wasn't tagged. The simple value `undefined` is not supported.
+
+use crate::{
+	fd,
+	parse::{self, invalid_data, Input, MAX_DEPTH},
+	Argdata, ArgdataExt, IntValue, OwnedArgdata, ParseError, ReadError, Timespec, Value,
+};
+use std::io;
+
+/// The tag used for file descriptors, whose content is the encoded fd number.
+///
+/// This tag is not registered with IANA, so it only has meaning between
+/// applications that agree on it.
+pub const FD_TAG: u64 = 0x6664_0000;
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+
+const TAG_EPOCH_TIME: u64 = 1;
+const TAG_POSITIVE_BIGNUM: u64 = 2;
+const TAG_NEGATIVE_BIGNUM: u64 = 3;
+const TAG_EXTENDED_TIME: u64 = 1001;
+
+/// Write an argdata value as CBOR.
+///
+/// Errors while reading the argdata are returned as an `io::Error` of kind
+/// `InvalidData` containing the [`ReadError`]. Maps and seqs nested more than
+/// 256 levels deep give a `ReadError::NestedTooDeeply`.
+pub fn to_writer(writer: &mut dyn io::Write, value: &dyn Argdata) -> io::Result<()> {
+	write_value(writer, value, 0)
+}
+
+/// Convert an argdata value to CBOR.
+///
+/// See [`to_writer`].
+pub fn to_vec(value: &dyn Argdata) -> Result<Vec<u8>, ReadError> {
+	parse::to_vec(|buf| write_value(buf, value, 0))
+}
+
+/// Decode a single CBOR data item into an argdata value.
+pub fn from_slice(data: &[u8]) -> Result<OwnedArgdata, ParseError> {
+	let mut decoder = Decoder {
+		input: Input::new(data),
+	};
+	let value = decoder.item(0)?;
+	if decoder.input.pos != data.len() {
+		return Err(decoder.input.error("Trailing bytes after CBOR data item"));
+	}
+	Ok(value)
+}
+
+fn write_head(w: &mut dyn io::Write, major: u8, n: u64) -> io::Result<()> {
+	let major = major << 5;
+	if n < 24 {
+		w.write_all(&[major | n as u8])
+	} else if n <= 0xFF {
+		w.write_all(&[major | 24, n as u8])
+	} else if n <= 0xFFFF {
+		w.write_all(&[major | 25])?;
+		w.write_all(&(n as u16).to_be_bytes())
+	} else if n <= 0xFFFF_FFFF {
+		w.write_all(&[major | 26])?;
+		w.write_all(&(n as u32).to_be_bytes())
+	} else {
+		w.write_all(&[major | 27])?;
+		w.write_all(&n.to_be_bytes())
+	}
+}
+
+fn write_int(w: &mut dyn io::Write, v: IntValue) -> io::Result<()> {
+	// CBOR stores negative numbers as -1 - n, so get n by flipping all bits
+	// of the 2's complement representation.
+	let mut bytes = Vec::with_capacity(v.serialized_length());
+	v.serialize(&mut bytes)?;
+	let negative = v.is_negative();
+	if negative {
+		bytes.iter_mut().for_each(|b| *b = !*b);
+	}
+	let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+	let magnitude = &bytes[start..];
+	if magnitude.len() <= 8 {
+		let n = magnitude.iter().fold(0, |n, &b| n << 8 | b as u64);
+		let major = if negative {
+			MAJOR_NEGATIVE
+		} else {
+			MAJOR_UNSIGNED
+		};
+		write_head(w, major, n)
+	} else {
+		let tag = if negative {
+			TAG_NEGATIVE_BIGNUM
+		} else {
+			TAG_POSITIVE_BIGNUM
+		};
+		write_head(w, MAJOR_TAG, tag)?;
+		write_head(w, MAJOR_BYTES, magnitude.len() as u64)?;
+		w.write_all(magnitude)
+	}
+}
+
+/// Write a value that is nested `depth` levels deep.
+fn write_value(w: &mut dyn io::Write, value: &dyn Argdata, depth: usize) -> io::Result<()> {
+	match parse::read_nested(value, depth)? {
+		Value::Null => w.write_all(&[MAJOR_SIMPLE << 5 | 22]),
+		Value::Binary(v) => {
+			write_head(w, MAJOR_BYTES, v.len() as u64)?;
+			w.write_all(v)
+		}
+		Value::Bool(v) => w.write_all(&[MAJOR_SIMPLE << 5 | if v { 21 } else { 20 }]),
+		Value::Fd(v) => {
+			write_head(w, MAJOR_TAG, FD_TAG)?;
+			write_head(w, MAJOR_UNSIGNED, v.raw_encoded_number() as u64)
+		}
+		Value::Float(v) => {
+			w.write_all(&[MAJOR_SIMPLE << 5 | 27])?;
+			w.write_all(&v.to_bits().to_be_bytes())
+		}
+		Value::Int(v) => write_int(w, v),
+		Value::Str(v) => {
+			let s = v.as_str().map_err(|e| invalid_data(e.into()))?;
+			write_head(w, MAJOR_TEXT, s.len() as u64)?;
+			w.write_all(s.as_bytes())
+		}
+		Value::Timestamp(t) if t.nsec == 0 => {
+			write_head(w, MAJOR_TAG, TAG_EPOCH_TIME)?;
+			write_int(w, t.sec.into())
+		}
+		Value::Timestamp(t) => {
+			// An extended time with the keys 1 (seconds) and -9 (nanoseconds).
+			write_head(w, MAJOR_TAG, TAG_EXTENDED_TIME)?;
+			write_head(w, MAJOR_MAP, 2)?;
+			write_head(w, MAJOR_UNSIGNED, 1)?;
+			write_int(w, t.sec.into())?;
+			write_head(w, MAJOR_NEGATIVE, 8)?;
+			write_head(w, MAJOR_UNSIGNED, t.nsec as u64)
+		}
+		Value::Map(it) => {
+			let mut len = 0;
+			for entry in it {
+				entry.map_err(invalid_data)?;
+				len += 1;
+			}
+			write_head(w, MAJOR_MAP, len)?;
+			for entry in value.read_map().unwrap() {
+				let (key, val) = entry.map_err(invalid_data)?;
+				write_value(w, &*key, depth + 1)?;
+				write_value(w, &*val, depth + 1)?;
+			}
+			Ok(())
+		}
+		Value::Seq(it) => {
+			let mut len = 0;
+			for entry in it {
+				entry.map_err(invalid_data)?;
+				len += 1;
+			}
+			write_head(w, MAJOR_ARRAY, len)?;
+			for val in value.read_seq().unwrap() {
+				write_value(w, &*val.map_err(invalid_data)?, depth + 1)?;
+			}
+			Ok(())
+		}
+	}
+}
+
+/// The length of a string, array, or map.
+enum Length {
+	Definite(u64),
+	Indefinite,
+}
+
+struct Decoder<'a> {
+	input: Input<'a>,
+}
+
+impl<'a> Decoder<'a> {
+	fn bytes(&mut self, n: u64) -> Result<&'a [u8], ParseError> {
+		self.input.bytes(n, "Unexpected end of CBOR data")
+	}
+
+	fn uint(&mut self, n: u64) -> Result<u64, ParseError> {
+		Ok(self.bytes(n)?.iter().fold(0, |n, &b| n << 8 | b as u64))
+	}
+
+	/// Read the initial byte and argument of a data item.
+	///
+	/// Returns the major type, the additional information, and the argument.
+	fn head(&mut self) -> Result<(u8, u8, Length), ParseError> {
+		let initial = self.bytes(1)?[0];
+		let (major, info) = (initial >> 5, initial & 0x1F);
+		let arg = match info {
+			0..=23 => Length::Definite(info as u64),
+			24..=27 => Length::Definite(self.uint(1 << (info - 24))?),
+			31 if major != MAJOR_UNSIGNED && major != MAJOR_NEGATIVE && major != MAJOR_TAG => {
+				Length::Indefinite
+			}
+			_ => {
+				self.input.pos -= 1;
+				return Err(self.input.error("Invalid additional information"));
+			}
+		};
+		Ok((major, info, arg))
+	}
+
+	/// Read a byte or text string, possibly consisting of indefinite-length chunks.
+	fn string(&mut self, major: u8, len: Length) -> Result<Vec<u8>, ParseError> {
+		match len {
+			Length::Definite(n) => Ok(self.bytes(n)?.to_vec()),
+			Length::Indefinite => {
+				let mut s = Vec::new();
+				while !self.eat_break() {
+					let start = self.input.pos;
+					match self.head()? {
+						(m, _, Length::Definite(n)) if m == major => {
+							s.extend_from_slice(self.bytes(n)?)
+						}
+						_ => {
+							self.input.pos = start;
+							return Err(self
+								.input
+								.error("Invalid chunk in indefinite-length string"));
+						}
+					}
+				}
+				Ok(s)
+			}
+		}
+	}
+
+	/// Consume a break marker, if there is one.
+	fn eat_break(&mut self) -> bool {
+		if self.input.data.get(self.input.pos) == Some(&0xFF) {
+			self.input.pos += 1;
+			true
+		} else {
+			false
+		}
+	}
+
+	/// Whether there are more elements in an array or map with the given
+	/// length, of which `i` elements have already been read.
+	fn has_next(&mut self, len: &Length, i: u64) -> bool {
+		match *len {
+			Length::Definite(n) => i < n,
+			Length::Indefinite => !self.eat_break(),
+		}
+	}
+
+	fn item(&mut self, depth: usize) -> Result<OwnedArgdata, ParseError> {
+		if depth > MAX_DEPTH {
+			return Err(self.input.error("CBOR nested too deeply"));
+		}
+		let start = self.input.pos;
+		let (major, info, arg) = self.head()?;
+		let value = match (major, arg) {
+			(MAJOR_UNSIGNED, Length::Definite(n)) => n.into(),
+			(MAJOR_NEGATIVE, Length::Definite(n)) => negative_int(&n.to_be_bytes()),
+			(MAJOR_BYTES, len) => OwnedArgdata::Binary(self.string(major, len)?),
+			(MAJOR_TEXT, len) => {
+				let s = self.string(major, len)?;
+				match String::from_utf8(s) {
+					Ok(s) => OwnedArgdata::Str(s),
+					Err(_) => {
+						self.input.pos = start;
+						return Err(self.input.error("Invalid UTF-8 in text string"));
+					}
+				}
+			}
+			(MAJOR_ARRAY, len) => {
+				let mut elements = Vec::new();
+				while self.has_next(&len, elements.len() as u64) {
+					elements.push(self.item(depth + 1)?);
+				}
+				OwnedArgdata::Seq(elements)
+			}
+			(MAJOR_MAP, len) => {
+				let mut entries = Vec::new();
+				while self.has_next(&len, entries.len() as u64) {
+					let key = self.item(depth + 1)?;
+					let value = self.item(depth + 1)?;
+					entries.push((key, value));
+				}
+				OwnedArgdata::Map(entries)
+			}
+			(MAJOR_TAG, Length::Definite(tag)) => {
+				let content_start = self.input.pos;
+				let content = self.item(depth + 1)?;
+				let value = match tag {
+					TAG_POSITIVE_BIGNUM | TAG_NEGATIVE_BIGNUM => match content {
+						OwnedArgdata::Binary(b) if tag == TAG_POSITIVE_BIGNUM => {
+							let mut bytes = vec![0];
+							bytes.extend_from_slice(&b);
+							Some(IntValue::from_bigint(&bytes).into())
+						}
+						OwnedArgdata::Binary(b) => Some(negative_int(&b)),
+						_ => None,
+					},
+					TAG_EPOCH_TIME => epoch_time(&content),
+					TAG_EXTENDED_TIME => extended_time(&content),
+					FD_TAG => content
+						.argdata()
+						.read_int::<u32>()
+						.ok()
+						.map(|n| fd::Fd(n as i32).into()),
+					_ => Some(content),
+				};
+				match value {
+					Some(value) => value,
+					None => {
+						self.input.pos = content_start;
+						return Err(self.input.error("Invalid content for tag"));
+					}
+				}
+			}
+			(MAJOR_SIMPLE, Length::Definite(n)) => match info {
+				20 => false.into(),
+				21 => true.into(),
+				22 => OwnedArgdata::Null,
+				25 => half_to_f64(n as u16).into(),
+				26 => (f32::from_bits(n as u32) as f64).into(),
+				27 => f64::from_bits(n).into(),
+				_ => {
+					self.input.pos = start;
+					return Err(self.input.error("Unsupported simple value"));
+				}
+			},
+			(MAJOR_SIMPLE, Length::Indefinite) => {
+				self.input.pos = start;
+				return Err(self.input.error("Unexpected break"));
+			}
+			_ => unreachable!(),
+		};
+		Ok(value)
+	}
+}
+
+/// The integer -1 - n, where n is a big-endian unsigned integer.
+fn negative_int(n: &[u8]) -> OwnedArgdata {
+	let mut bytes = vec![0xFF];
+	bytes.extend(n.iter().map(|b| !b));
+	IntValue::from_bigint(&bytes).into()
+}
+
+/// Convert the content of an epoch-based date/time (tag 1).
+fn epoch_time(content: &OwnedArgdata) -> Option<OwnedArgdata> {
+	let t = match *content {
+		OwnedArgdata::Float(f) => {
+			let sec = f.floor();
+			if !(-9.2e18..9.2e18).contains(&sec) {
+				return None;
+			}
+			let nsec = ((f - sec) * 1e9).round() as u32;
+			if nsec >= 1_000_000_000 {
+				Timespec {
+					sec: sec as i64 + 1,
+					nsec: 0,
+				}
+			} else {
+				Timespec {
+					sec: sec as i64,
+					nsec,
+				}
+			}
+		}
+		ref content => Timespec {
+			sec: content.argdata().read_int().ok()?,
+			nsec: 0,
+		},
+	};
+	Some(t.into())
+}
+
+/// Convert the content of an extended time (tag 1001).
+///
+/// Only the base time (key 1) and a fraction (key -3, -6, or -9) are supported.
+fn extended_time(content: &OwnedArgdata) -> Option<OwnedArgdata> {
+	let entries = match content {
+		OwnedArgdata::Map(entries) => entries,
+		_ => return None,
+	};
+	let mut sec = None;
+	let mut nsec = 0;
+	for (key, value) in entries {
+		let value = value.argdata();
+		match key.argdata().read_int::<i64>().ok()? {
+			1 => sec = Some(value.read_int::<i64>().ok()?),
+			-3 => nsec = value.read_int::<u32>().ok().filter(|&n| n < 1_000)? * 1_000_000,
+			-6 => nsec = value.read_int::<u32>().ok().filter(|&n| n < 1_000_000)? * 1_000,
+			-9 => {
+				nsec = value
+					.read_int::<u32>()
+					.ok()
+					.filter(|&n| n < 1_000_000_000)?
+			}
+			_ => return None,
+		}
+	}
+	Some(Timespec { sec: sec?, nsec }.into())
+}
+
+fn half_to_f64(h: u16) -> f64 {
+	let exponent = (h >> 10) & 0x1F;
+	let mantissa = (h & 0x3FF) as f64;
+	let value = match exponent {
+		0 => mantissa * 2f64.powi(-24),
+		31 if mantissa == 0.0 => f64::INFINITY,
+		31 => f64::NAN,
+		_ => (mantissa + 1024.0) * 2f64.powi(exponent as i32 - 25),
+	};
+	if h & 0x8000 != 0 {
+		-value
+	} else {
+		value
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn roundtrip(value: OwnedArgdata, cbor: &[u8]) {
+		assert_eq!(to_vec(value.argdata()).unwrap(), cbor);
+		let decoded = from_slice(cbor).unwrap();
+		assert_eq!(decoded, value);
+	}
+
+	#[test]
+	fn cbor_roundtrip_test() {
+		roundtrip(OwnedArgdata::Null, b"\xF6");
+		roundtrip(true.into(), b"\xF5");
+		roundtrip(0.into(), b"\x00");
+		roundtrip(500.into(), b"\x19\x01\xF4");
+		roundtrip((-500).into(), b"\x39\x01\xF3");
+		roundtrip(u64::MAX.into(), b"\x1B\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF");
+		roundtrip(
+			IntValue::from_bigint(b"\xFF\0\0\0\0\0\0\0\0").into(),
+			b"\x3B\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF",
+		);
+		roundtrip(
+			IntValue::from_bigint(b"\x01\0\0\0\0\0\0\0\0").into(),
+			b"\xC2\x49\x01\0\0\0\0\0\0\0\0",
+		);
+		roundtrip(
+			IntValue::from_bigint(b"\xFE\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF").into(),
+			b"\xC3\x49\x01\0\0\0\0\0\0\0\0",
+		);
+		roundtrip(1.5.into(), b"\xFB\x3F\xF8\0\0\0\0\0\0");
+		roundtrip("é".into(), b"\x62\xC3\xA9");
+		roundtrip(b"\x01\x02"[..].into(), b"\x42\x01\x02");
+		roundtrip(fd::Fd(3).into(), b"\xDA\x66\x64\0\0\x03");
+		roundtrip(Timespec { sec: -1, nsec: 0 }.into(), b"\xC1\x20");
+		roundtrip(
+			Timespec { sec: 10, nsec: 5 }.into(),
+			b"\xD9\x03\xE9\xA2\x01\x0A\x28\x05",
+		);
+		roundtrip(
+			OwnedArgdata::Map(vec![(
+				"a".into(),
+				OwnedArgdata::Seq(vec![1.into(), OwnedArgdata::Seq(vec![])]),
+			)]),
+			b"\xA1\x61a\x82\x01\x80",
+		);
+	}
+
+	#[test]
+	fn cbor_encoded_test() {
+		let argdata = crate::encoded(&b"\x07\x81\x02\x82\x02\x01\x87\x08Hello\x00"[..]);
+		assert_eq!(to_vec(&argdata).unwrap(), b"\x83\xF4\xF5\x65Hello");
+
+		let invalid = crate::encoded(&b"\x07\x82\x08a"[..]);
+		assert_eq!(to_vec(&invalid), Err(ReadError::MissingNullTerminator));
+	}
+
+	#[test]
+	fn cbor_decode_test() {
+		let decode = |data: &[u8]| from_slice(data).map(|v| format!("{:?}", v));
+		assert_eq!(
+			decode(b"\x9F\x7F\x61a\x61b\xFF\xBF\x01\xF9\x3C\x00\xFF\xFA\x3F\xC0\0\0\xFF").unwrap(),
+			"[\"ab\", {1: 1.0}, 1.5]"
+		);
+		assert_eq!(
+			decode(b"\xC1\xFB\x3F\xF8\0\0\0\0\0\0").unwrap(),
+			"timestamp(1, 500000000)"
+		);
+		assert_eq!(
+			decode(b"\xD9\x03\xE9\xA2\x01\x00\x22\x19\x01\xF4").unwrap(),
+			"timestamp(0, 500000000)"
+		);
+		assert_eq!(decode(b"\xD8\x20\x61a").unwrap(), "\"a\"");
+
+		let error = |data: &[u8]| from_slice(data).unwrap_err().to_string();
+		assert_eq!(error(b""), "Unexpected end of CBOR data at byte 0");
+		assert_eq!(error(b"\x82\x01"), "Unexpected end of CBOR data at byte 2");
+		assert_eq!(
+			error(b"\x01\x02"),
+			"Trailing bytes after CBOR data item at byte 1"
+		);
+		assert_eq!(error(b"\x1C"), "Invalid additional information at byte 0");
+		assert_eq!(
+			error(b"\x62\xC3\x28"),
+			"Invalid UTF-8 in text string at byte 0"
+		);
+		assert_eq!(error(b"\xF7"), "Unsupported simple value at byte 0");
+		assert_eq!(error(b"\xFF"), "Unexpected break at byte 0");
+		assert_eq!(error(b"\xC1\x61a"), "Invalid content for tag at byte 1");
+		assert_eq!(
+			error(b"\x7F\x41a\xFF"),
+			"Invalid chunk in indefinite-length string at byte 1"
+		);
+		assert_eq!(error(&[0x81; 1000]), "CBOR nested too deeply at byte 257");
+	}
+
+	#[test]
+	fn cbor_deep_nesting_test() {
+		let data = parse::nested_seqs(MAX_DEPTH + 1);
+		let mut cbor = vec![0x81; MAX_DEPTH + 1];
+		cbor.push(0xF6);
+		assert_eq!(to_vec(&crate::encoded(&data)), Ok(cbor));
+		let data = parse::nested_seqs(MAX_DEPTH + 2);
+		assert_eq!(
+			to_vec(&crate::encoded(&data)),
+			Err(ReadError::NestedTooDeeply)
+		);
+		let data = parse::nested_seqs(200_000);
+		assert_eq!(
+			to_vec(&crate::encoded(&data)),
+			Err(ReadError::NestedTooDeeply)
+		);
+	}
+}
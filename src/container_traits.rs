@@ -3,6 +3,7 @@ use std::cmp::min;
 use std::sync::Arc;
 
 /// A random-access container.
+#[allow(clippy::len_without_is_empty)]
 pub trait Container: Sync {
 	type Item;
 	fn get(&self, index: usize) -> Option<&Self::Item>;
@@ -13,6 +14,7 @@ pub trait Container: Sync {
 ///
 /// Both a tuple of two Containers and a Container of tuples are considered MapContainers:
 /// Examples are `(Vec<Key>, &[Val])` and `Box<[(Key, Val)]>`.
+#[allow(clippy::len_without_is_empty)]
 pub trait MapContainer: Sync {
 	type Key;
 	type Value;
@@ -47,7 +49,7 @@ where
 	type Value = V;
 
 	fn get(&self, index: usize) -> Option<(&Self::Key, &Self::Value)> {
-		Container::get(self, index).map(|&(ref k, ref v)| (k, v))
+		Container::get(self, index).map(|(k, v)| (k, v))
 	}
 
 	fn len(&self) -> usize {
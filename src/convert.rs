@@ -0,0 +1,365 @@
+use crate::{fd, Argdata, ArgdataExt, NoFit, NotRead, OwnedArgdata, ReadError, Timespec};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+/// A type that can be read from an argdata value.
+///
+/// Implement this for your own structs and enums using `#[derive(FromArgdata)]`,
+/// which is available with the `derive` feature.
+///
+/// Structs with named fields are read from maps with string keys, tuple
+/// structs from seqs, and unit structs from null. Newtype structs are read
+/// as the value they contain.
+/// Unit variants of enums are read from a string with the variant name,
+/// other variants from a map with the variant name as its only key.
+///
+/// The derive macro understands the following attributes:
+///
+///  - `#[argdata(deny_unknown_fields)]` on a struct or enum: Give an
+///    `UnknownField` error for map keys that don't match any field.
+///  - `#[argdata(rename = "name")]` on a field or variant: Use a
+///    different key or variant name.
+///  - `#[argdata(default)]` on a field: Use `Default::default()` if the
+///    field is missing.
+///  - `#[argdata(default = "path")]` on a field: Call the given function if
+///    the field is missing.
+///
+/// Fields of type `Option<T>` are `None` if they are missing. Otherwise they
+/// are read as `T`, and only if that fails on a null value are they `None`.
+/// This way, a field of type `Option<Option<T>>` holding `Some(None)` is
+/// written as null and read back as `Some(None)`.
+pub trait FromArgdata<'d>: Sized {
+	/// Read the value.
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<Self, FromArgdataError>;
+}
+
+/// A type that can be converted to an argdata value.
+///
+/// Implement this for your own structs and enums using `#[derive(ToArgdata)]`,
+/// which is available with the `derive` feature.
+/// Values are represented the same way as [`FromArgdata`] reads them.
+/// Fields of type `Option<T>` are left out if they are `None`, and written as
+/// the `T` they contain otherwise. Elsewhere, `None` is written as null.
+///
+/// The result is an [`OwnedArgdata`] rather than a value borrowing from
+/// `self` like the ones made by [`map`](crate::map) and [`seq`](crate::seq),
+/// since those borrow their elements from slices that must outlive them,
+/// which nested values would have nowhere to keep. This means strings and
+/// binary blobs are copied.
+pub trait ToArgdata {
+	/// Convert the value to argdata.
+	fn to_argdata(&self) -> OwnedArgdata;
+}
+
+/// The reason why [`FromArgdata::from_argdata`] failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FromArgdataError {
+	/// The argdata could not be read.
+	Read(ReadError),
+
+	/// The argdata value is of the wrong type, or doesn't fit in the requested type.
+	NoFit(NoFit),
+
+	/// A map is missing a required field.
+	MissingField(&'static str),
+
+	/// A map contains a field that isn't expected.
+	UnknownField(String),
+
+	/// A string or map key doesn't name any of the expected enum variants.
+	UnknownVariant(String),
+
+	/// A map representing an enum variant didn't contain exactly one key.
+	InvalidVariant,
+
+	/// The given field, element, or variant could not be read.
+	Field(&'static str, Box<FromArgdataError>),
+}
+
+impl FromArgdataError {
+	/// Wrap the error to indicate it occurred in the given field.
+	pub fn in_field(self, name: &'static str) -> FromArgdataError {
+		FromArgdataError::Field(name, Box::new(self))
+	}
+}
+
+impl Error for FromArgdataError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			FromArgdataError::Read(e) => Some(e),
+			FromArgdataError::Field(_, e) => Some(&**e),
+			_ => None,
+		}
+	}
+}
+
+impl Display for FromArgdataError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			FromArgdataError::Read(e) => write!(f, "{}", e),
+			FromArgdataError::NoFit(NoFit::DifferentType) => write!(f, "Value of unexpected type"),
+			FromArgdataError::NoFit(NoFit::OutOfRange) => write!(f, "Value out of range"),
+			FromArgdataError::MissingField(name) => write!(f, "Missing field `{}`", name),
+			FromArgdataError::UnknownField(name) => write!(f, "Unknown field `{}`", name),
+			FromArgdataError::UnknownVariant(name) => write!(f, "Unknown variant `{}`", name),
+			FromArgdataError::InvalidVariant => write!(f, "Expected a map with exactly one entry"),
+			FromArgdataError::Field(name, e) => write!(f, "In `{}`: {}", name, e),
+		}
+	}
+}
+
+impl From<ReadError> for FromArgdataError {
+	fn from(e: ReadError) -> FromArgdataError {
+		FromArgdataError::Read(e)
+	}
+}
+
+impl From<NoFit> for FromArgdataError {
+	fn from(e: NoFit) -> FromArgdataError {
+		FromArgdataError::NoFit(e)
+	}
+}
+
+impl From<NotRead> for FromArgdataError {
+	fn from(e: NotRead) -> FromArgdataError {
+		match e {
+			NotRead::Error(e) => FromArgdataError::Read(e),
+			NotRead::NoFit(e) => FromArgdataError::NoFit(e),
+		}
+	}
+}
+
+macro_rules! impl_int {
+	($($t:ident)*) => {
+		$(
+			impl<'d> FromArgdata<'d> for $t {
+				fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<$t, FromArgdataError> {
+					Ok(argdata.read_int()?)
+				}
+			}
+
+			impl ToArgdata for $t {
+				fn to_argdata(&self) -> OwnedArgdata {
+					(*self).into()
+				}
+			}
+		)*
+	};
+}
+
+impl_int!(i8 i16 i32 i64 u8 u16 u32 u64);
+
+impl<'d> FromArgdata<'d> for () {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<(), FromArgdataError> {
+		Ok(argdata.read_null()?)
+	}
+}
+
+impl<'d> FromArgdata<'d> for bool {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<bool, FromArgdataError> {
+		Ok(argdata.read_bool()?)
+	}
+}
+
+impl<'d> FromArgdata<'d> for f64 {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<f64, FromArgdataError> {
+		Ok(argdata.read_float()?)
+	}
+}
+
+impl<'d> FromArgdata<'d> for &'d str {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<&'d str, FromArgdataError> {
+		Ok(argdata.read_str()?)
+	}
+}
+
+impl<'d> FromArgdata<'d> for String {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<String, FromArgdataError> {
+		Ok(argdata.read_str()?.to_string())
+	}
+}
+
+impl<'d> FromArgdata<'d> for &'d [u8] {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<&'d [u8], FromArgdataError> {
+		Ok(argdata.read_binary()?)
+	}
+}
+
+impl<'d> FromArgdata<'d> for fd::Fd {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<fd::Fd, FromArgdataError> {
+		Ok(argdata.read_fd()?)
+	}
+}
+
+impl<'d> FromArgdata<'d> for Timespec {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<Timespec, FromArgdataError> {
+		Ok(argdata.read_timestamp()?)
+	}
+}
+
+impl<'d> FromArgdata<'d> for OwnedArgdata {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<OwnedArgdata, FromArgdataError> {
+		Ok(OwnedArgdata::from_argdata(argdata)?)
+	}
+}
+
+/// Null is read as `None`.
+impl<'d, T: FromArgdata<'d>> FromArgdata<'d> for Option<T> {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<Option<T>, FromArgdataError> {
+		match argdata.read_null() {
+			Ok(()) => Ok(None),
+			Err(NotRead::Error(e)) => Err(e.into()),
+			Err(NotRead::NoFit(_)) => T::from_argdata(argdata).map(Some),
+		}
+	}
+}
+
+impl<'d, T: FromArgdata<'d>> FromArgdata<'d> for Box<T> {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<Box<T>, FromArgdataError> {
+		T::from_argdata(argdata).map(Box::new)
+	}
+}
+
+impl<'d, T: FromArgdata<'d>> FromArgdata<'d> for Vec<T> {
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<Vec<T>, FromArgdataError> {
+		let mut result = Vec::new();
+		for value in argdata.read_seq()? {
+			result.push(T::from_argdata(&*value?)?);
+		}
+		Ok(result)
+	}
+}
+
+impl<'d, K, V> FromArgdata<'d> for BTreeMap<K, V>
+where
+	K: FromArgdata<'d> + Ord,
+	V: FromArgdata<'d>,
+{
+	fn from_argdata(argdata: &(dyn Argdata<'d> + '_)) -> Result<BTreeMap<K, V>, FromArgdataError> {
+		let mut result = BTreeMap::new();
+		for entry in argdata.read_map()? {
+			let (key, value) = entry?;
+			result.insert(K::from_argdata(&*key)?, V::from_argdata(&*value)?);
+		}
+		Ok(result)
+	}
+}
+
+impl ToArgdata for () {
+	fn to_argdata(&self) -> OwnedArgdata {
+		OwnedArgdata::Null
+	}
+}
+
+impl ToArgdata for bool {
+	fn to_argdata(&self) -> OwnedArgdata {
+		OwnedArgdata::Bool(*self)
+	}
+}
+
+impl ToArgdata for f64 {
+	fn to_argdata(&self) -> OwnedArgdata {
+		OwnedArgdata::Float(*self)
+	}
+}
+
+impl ToArgdata for str {
+	fn to_argdata(&self) -> OwnedArgdata {
+		OwnedArgdata::Str(self.to_string())
+	}
+}
+
+impl ToArgdata for String {
+	fn to_argdata(&self) -> OwnedArgdata {
+		OwnedArgdata::Str(self.clone())
+	}
+}
+
+/// A byte slice is represented as a binary blob.
+///
+/// Note that a `Vec<u8>` is represented as a seq of integers instead.
+impl ToArgdata for [u8] {
+	fn to_argdata(&self) -> OwnedArgdata {
+		OwnedArgdata::Binary(self.to_vec())
+	}
+}
+
+impl ToArgdata for fd::Fd {
+	fn to_argdata(&self) -> OwnedArgdata {
+		OwnedArgdata::Fd(*self)
+	}
+}
+
+impl ToArgdata for Timespec {
+	fn to_argdata(&self) -> OwnedArgdata {
+		OwnedArgdata::Timestamp(*self)
+	}
+}
+
+impl ToArgdata for OwnedArgdata {
+	fn to_argdata(&self) -> OwnedArgdata {
+		self.clone()
+	}
+}
+
+/// `None` is represented as null.
+impl<T: ToArgdata> ToArgdata for Option<T> {
+	fn to_argdata(&self) -> OwnedArgdata {
+		match self {
+			Some(v) => v.to_argdata(),
+			None => OwnedArgdata::Null,
+		}
+	}
+}
+
+impl<T: ToArgdata + ?Sized> ToArgdata for &T {
+	fn to_argdata(&self) -> OwnedArgdata {
+		(**self).to_argdata()
+	}
+}
+
+impl<T: ToArgdata + ?Sized> ToArgdata for Box<T> {
+	fn to_argdata(&self) -> OwnedArgdata {
+		(**self).to_argdata()
+	}
+}
+
+impl<T: ToArgdata> ToArgdata for Vec<T> {
+	fn to_argdata(&self) -> OwnedArgdata {
+		OwnedArgdata::Seq(self.iter().map(ToArgdata::to_argdata).collect())
+	}
+}
+
+impl<K: ToArgdata, V: ToArgdata> ToArgdata for BTreeMap<K, V> {
+	fn to_argdata(&self) -> OwnedArgdata {
+		OwnedArgdata::Map(
+			self.iter()
+				.map(|(k, v)| (k.to_argdata(), v.to_argdata()))
+				.collect(),
+		)
+	}
+}
+
+#[test]
+fn convert_test() {
+	let mut map = BTreeMap::new();
+	map.insert("a".to_string(), vec![Some(1u8), None]);
+	map.insert("b".to_string(), vec![]);
+
+	let owned = map.to_argdata();
+	assert_eq!(format!("{:?}", owned), "{\"a\": [1, null], \"b\": []}");
+	assert_eq!(
+		BTreeMap::<String, Vec<Option<u8>>>::from_argdata(owned.argdata()),
+		Ok(map)
+	);
+
+	assert_eq!(
+		<&str>::from_argdata(&crate::int(5)),
+		Err(FromArgdataError::NoFit(NoFit::DifferentType))
+	);
+	assert_eq!(
+		u8::from_argdata(&crate::int(300)),
+		Err(FromArgdataError::NoFit(NoFit::OutOfRange))
+	);
+}
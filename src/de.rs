@@ -0,0 +1,447 @@
+//! Values are mapped the same way as by the [`Serializer`](crate::ser::Serializer).
+//! See the [`ser` module documentation](crate::ser#mapping).
+//!
+//! Strings and binary blobs are borrowed directly from the argdata, so types
+//! like `&str` and `&[u8]` can be deserialized without copying.
+
+use crate::{
+	fd,
+	ser::{BIGINT, FD, TIMESTAMP},
+	Argdata, ArgdataExt, ArgdataRef, IntValue, MapIterator, NoFit, NotRead, ReadError, SeqIterator,
+	Timespec, Value,
+};
+use byteorder::{BigEndian, ByteOrder};
+use serde::de::{self, Deserialize, IntoDeserializer, Visitor};
+use std::fmt;
+
+/// Deserializes any `Deserialize` value from argdata.
+///
+/// See the [module documentation](index.html) for how values are mapped onto argdata.
+#[derive(Clone, Copy)]
+pub struct Deserializer<'a, 'd: 'a> {
+	argdata: &'a (dyn Argdata<'d> + 'a),
+}
+
+/// An error while deserializing argdata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+	/// The argdata could not be read.
+	Read(ReadError),
+
+	/// The argdata did not match what was expected by the deserialized type.
+	Message(String),
+}
+
+impl<'a, 'd: 'a> Deserializer<'a, 'd> {
+	/// Create a deserializer that reads the given argdata value.
+	pub fn new(argdata: &'a (dyn Argdata<'d> + 'a)) -> Deserializer<'a, 'd> {
+		Deserializer { argdata }
+	}
+}
+
+/// Deserialize a value from argdata.
+pub fn from_argdata<'d, T: Deserialize<'d>>(argdata: &(dyn Argdata<'d> + '_)) -> Result<T, Error> {
+	T::deserialize(Deserializer::new(argdata))
+}
+
+/// Deserialize a value from an encoded argdata buffer.
+///
+/// File descriptors can not be deserialized from the buffer, since there are none attached.
+/// Use [`from_argdata`] with [`encoded_with_fds`](crate::encoded_with_fds) for that.
+pub fn from_slice<'d, T: Deserialize<'d>>(data: &'d [u8]) -> Result<T, Error> {
+	from_argdata(&crate::encoded(data))
+}
+
+fn int_bytes(value: IntValue) -> Vec<u8> {
+	let mut buf = Vec::with_capacity(value.serialized_length());
+	value.serialize(&mut buf).unwrap();
+	buf
+}
+
+impl<'a, 'd: 'a> Deserializer<'a, 'd> {
+	fn visit_fd<V: Visitor<'d>>(self, visitor: V) -> Result<V::Value, Error> {
+		let fd = self.argdata.read_fd()?;
+		visitor.visit_newtype_struct(fd.0.into_deserializer())
+	}
+
+	fn visit_timestamp<V: Visitor<'d>>(self, visitor: V) -> Result<V::Value, Error> {
+		let t = self.argdata.read_timestamp()?;
+		let fields = vec![t.sec, i64::from(t.nsec)];
+		visitor.visit_newtype_struct(de::value::SeqDeserializer::new(fields.into_iter()))
+	}
+
+	fn visit_bigint<V: Visitor<'d>>(self, visitor: V) -> Result<V::Value, Error> {
+		let bytes = int_bytes(self.argdata.read_int_value()?);
+		visitor.visit_newtype_struct(de::value::BytesDeserializer::new(&bytes))
+	}
+
+	fn visit_int<V: Visitor<'d>>(self, value: IntValue<'d>, visitor: V) -> Result<V::Value, Error> {
+		if let Ok(v) = value.try_into::<u64>() {
+			return visitor.visit_u64(v);
+		}
+		if let Ok(v) = value.try_into::<i64>() {
+			return visitor.visit_i64(v);
+		}
+		let bytes = int_bytes(value);
+		if bytes.len() == 17 && bytes[0] == 0 {
+			visitor.visit_u128(BigEndian::read_u128(&bytes[1..]))
+		} else if bytes.len() <= 16 {
+			visitor.visit_i128(BigEndian::read_int128(&bytes, bytes.len()))
+		} else {
+			visitor.visit_newtype_struct(de::value::BytesDeserializer::new(&bytes))
+		}
+	}
+}
+
+impl<'a, 'd: 'a> de::Deserializer<'d> for Deserializer<'a, 'd> {
+	type Error = Error;
+
+	fn deserialize_any<V: Visitor<'d>>(self, visitor: V) -> Result<V::Value, Error> {
+		match self.argdata.read()? {
+			Value::Null => visitor.visit_unit(),
+			Value::Binary(v) => visitor.visit_borrowed_bytes(v),
+			Value::Bool(v) => visitor.visit_bool(v),
+			Value::Fd(_) => self.visit_fd(visitor),
+			Value::Float(v) => visitor.visit_f64(v),
+			Value::Int(v) => self.visit_int(v, visitor),
+			Value::Str(v) => visitor.visit_borrowed_str(v.as_str().map_err(ReadError::from)?),
+			Value::Timestamp(_) => self.visit_timestamp(visitor),
+			Value::Map(it) => visitor.visit_map(MapAccess { it, value: None }),
+			Value::Seq(it) => visitor.visit_seq(SeqAccess { it }),
+		}
+	}
+
+	fn deserialize_option<V: Visitor<'d>>(self, visitor: V) -> Result<V::Value, Error> {
+		match self.argdata.read_null() {
+			Ok(()) => visitor.visit_none(),
+			Err(_) => visitor.visit_some(self),
+		}
+	}
+
+	fn deserialize_newtype_struct<V: Visitor<'d>>(
+		self,
+		name: &'static str,
+		visitor: V,
+	) -> Result<V::Value, Error> {
+		match name {
+			TIMESTAMP => self.visit_timestamp(visitor),
+			FD => self.visit_fd(visitor),
+			BIGINT => self.visit_bigint(visitor),
+			_ => visitor.visit_newtype_struct(self),
+		}
+	}
+
+	fn deserialize_enum<V: Visitor<'d>>(
+		self,
+		_name: &'static str,
+		_variants: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Error> {
+		match self.argdata.read()? {
+			Value::Str(v) => {
+				visitor.visit_enum(v.as_str().map_err(ReadError::from)?.into_deserializer())
+			}
+			Value::Map(mut it) => {
+				let (variant, value) = match it.next() {
+					Some(entry) => entry?,
+					None => return Err(de::Error::invalid_length(0, &"a single variant")),
+				};
+				if it.next().is_some() {
+					return Err(de::Error::invalid_length(2, &"a single variant"));
+				}
+				visitor.visit_enum(EnumAccess { variant, value })
+			}
+			_ => Err(de::Error::invalid_type(
+				de::Unexpected::Other("non-string non-map argdata"),
+				&"an enum variant",
+			)),
+		}
+	}
+
+	serde::forward_to_deserialize_any! {
+		<W: Visitor<'d>>
+		bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+		bytes byte_buf unit unit_struct seq tuple
+		tuple_struct map struct identifier ignored_any
+	}
+}
+
+struct MapAccess<'a, 'd: 'a> {
+	it: MapIterator<'a, 'd>,
+	value: Option<ArgdataRef<'a, 'd>>,
+}
+
+impl<'a, 'd: 'a> de::MapAccess<'d> for MapAccess<'a, 'd> {
+	type Error = Error;
+
+	fn next_key_seed<K: de::DeserializeSeed<'d>>(
+		&mut self,
+		seed: K,
+	) -> Result<Option<K::Value>, Error> {
+		match self.it.next() {
+			None => Ok(None),
+			Some(entry) => {
+				let (key, value) = entry?;
+				self.value = Some(value);
+				seed.deserialize(Deserializer::new(&*key)).map(Some)
+			}
+		}
+	}
+
+	fn next_value_seed<V: de::DeserializeSeed<'d>>(&mut self, seed: V) -> Result<V::Value, Error> {
+		let value = self
+			.value
+			.take()
+			.expect("next_value_seed called before next_key_seed");
+		seed.deserialize(Deserializer::new(&*value))
+	}
+}
+
+struct SeqAccess<'a, 'd: 'a> {
+	it: SeqIterator<'a, 'd>,
+}
+
+impl<'a, 'd: 'a> de::SeqAccess<'d> for SeqAccess<'a, 'd> {
+	type Error = Error;
+
+	fn next_element_seed<T: de::DeserializeSeed<'d>>(
+		&mut self,
+		seed: T,
+	) -> Result<Option<T::Value>, Error> {
+		match self.it.next() {
+			None => Ok(None),
+			Some(value) => seed.deserialize(Deserializer::new(&*value?)).map(Some),
+		}
+	}
+}
+
+struct EnumAccess<'a, 'd: 'a> {
+	variant: ArgdataRef<'a, 'd>,
+	value: ArgdataRef<'a, 'd>,
+}
+
+impl<'a, 'd: 'a> de::EnumAccess<'d> for EnumAccess<'a, 'd> {
+	type Error = Error;
+	type Variant = VariantAccess<'a, 'd>;
+
+	fn variant_seed<V: de::DeserializeSeed<'d>>(
+		self,
+		seed: V,
+	) -> Result<(V::Value, VariantAccess<'a, 'd>), Error> {
+		let variant = seed.deserialize(Deserializer::new(&*self.variant))?;
+		Ok((variant, VariantAccess { value: self.value }))
+	}
+}
+
+struct VariantAccess<'a, 'd: 'a> {
+	value: ArgdataRef<'a, 'd>,
+}
+
+impl<'a, 'd: 'a> de::VariantAccess<'d> for VariantAccess<'a, 'd> {
+	type Error = Error;
+
+	fn unit_variant(self) -> Result<(), Error> {
+		Deserialize::deserialize(Deserializer::new(&*self.value))
+	}
+
+	fn newtype_variant_seed<T: de::DeserializeSeed<'d>>(self, seed: T) -> Result<T::Value, Error> {
+		seed.deserialize(Deserializer::new(&*self.value))
+	}
+
+	fn tuple_variant<V: Visitor<'d>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+		de::Deserializer::deserialize_seq(Deserializer::new(&*self.value), visitor)
+	}
+
+	fn struct_variant<V: Visitor<'d>>(
+		self,
+		_fields: &'static [&'static str],
+		visitor: V,
+	) -> Result<V::Value, Error> {
+		de::Deserializer::deserialize_map(Deserializer::new(&*self.value), visitor)
+	}
+}
+
+struct TimespecVisitor;
+
+impl<'d> Visitor<'d> for TimespecVisitor {
+	type Value = Timespec;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "a timestamp")
+	}
+
+	fn visit_newtype_struct<D: de::Deserializer<'d>>(self, d: D) -> Result<Timespec, D::Error> {
+		let (sec, nsec) = <(i64, u32)>::deserialize(d)?;
+		if nsec >= 1_000_000_000 {
+			return Err(de::Error::invalid_value(
+				de::Unexpected::Unsigned(nsec.into()),
+				&"a number of nanoseconds below 1000000000",
+			));
+		}
+		Ok(Timespec { sec, nsec })
+	}
+}
+
+impl<'d> Deserialize<'d> for Timespec {
+	fn deserialize<D: de::Deserializer<'d>>(d: D) -> Result<Timespec, D::Error> {
+		d.deserialize_newtype_struct(TIMESTAMP, TimespecVisitor)
+	}
+}
+
+struct FdVisitor;
+
+impl<'d> Visitor<'d> for FdVisitor {
+	type Value = fd::Fd;
+
+	fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "a file descriptor")
+	}
+
+	fn visit_newtype_struct<D: de::Deserializer<'d>>(self, d: D) -> Result<fd::Fd, D::Error> {
+		Deserialize::deserialize(d).map(fd::Fd)
+	}
+}
+
+impl<'d> Deserialize<'d> for fd::Fd {
+	fn deserialize<D: de::Deserializer<'d>>(d: D) -> Result<fd::Fd, D::Error> {
+		d.deserialize_newtype_struct(FD, FdVisitor)
+	}
+}
+
+impl de::Error for Error {
+	fn custom<T: fmt::Display>(msg: T) -> Error {
+		Error::Message(msg.to_string())
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Read(e) => e.fmt(f),
+			Error::Message(msg) => f.write_str(msg),
+		}
+	}
+}
+
+impl From<ReadError> for Error {
+	fn from(e: ReadError) -> Error {
+		Error::Read(e)
+	}
+}
+
+impl From<NotRead> for Error {
+	fn from(e: NotRead) -> Error {
+		match e {
+			NotRead::Error(e) => Error::Read(e),
+			NotRead::NoFit(NoFit::OutOfRange) => Error::Message("value out of range".into()),
+			NotRead::NoFit(NoFit::DifferentType) => {
+				Error::Message("value of unexpected type".into())
+			}
+		}
+	}
+}
+
+impl<'a, 'd> fmt::Debug for Deserializer<'a, 'd> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Deserializer({:?})", self.argdata)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::ser::{to_vec, to_writer};
+	use serde::{Deserialize, Serialize};
+	use std::collections::BTreeMap;
+
+	#[derive(Serialize, Deserialize, Debug, PartialEq)]
+	struct Config<'a> {
+		name: &'a str,
+		port: u16,
+		verbose: bool,
+		ratio: f64,
+		tags: Vec<String>,
+		missing: Option<i32>,
+		present: Option<i32>,
+		kinds: Vec<Kind>,
+		started: Timespec,
+	}
+
+	#[derive(Serialize, Deserialize, Debug, PartialEq)]
+	enum Kind {
+		Plain,
+		Wrapped(i32),
+		Pair(i32, i32),
+		Named { x: i32 },
+	}
+
+	#[test]
+	fn deserialize_struct_test() {
+		let config = Config {
+			name: "server",
+			port: 8080,
+			verbose: true,
+			ratio: 0.5,
+			tags: vec!["a".into(), "b".into()],
+			missing: None,
+			present: Some(-3),
+			kinds: vec![
+				Kind::Plain,
+				Kind::Wrapped(1),
+				Kind::Pair(2, 3),
+				Kind::Named { x: 4 },
+			],
+			started: Timespec {
+				sec: -485,
+				nsec: 88045333,
+			},
+		};
+		let buf = to_vec(&config).unwrap();
+		assert_eq!(from_slice::<Config>(&buf), Ok(config));
+	}
+
+	#[test]
+	fn deserialize_borrowed_test() {
+		let buf = b"\x07\x84\x01abc\x87\x08hello\x00";
+		let (bin, s): (&[u8], &str) = from_slice(buf).unwrap();
+		assert_eq!(bin, b"abc");
+		assert_eq!(s, "hello");
+		assert_eq!(bin.as_ptr(), buf[3..].as_ptr());
+		assert_eq!(s.as_ptr(), buf[8..].as_ptr());
+	}
+
+	#[test]
+	fn deserialize_int_test() {
+		let values = (0u8, -1i8, u64::MAX, i64::MIN, u128::MAX, i128::MIN);
+		let buf = to_vec(&values).unwrap();
+		assert_eq!(from_slice(&buf), Ok(values));
+
+		assert_eq!(
+			from_slice::<u8>(b"\x05\x01\x00"),
+			Err(Error::Message(
+				"invalid value: integer `256`, expected u8".into()
+			))
+		);
+	}
+
+	#[test]
+	fn deserialize_fd_test() {
+		let mut map = BTreeMap::new();
+		map.insert("socket".to_string(), fd::Fd(9));
+		let mut buf = Vec::new();
+		let mut fds = Vec::new();
+		to_writer(&mut buf, &map, Some(&mut fds)).unwrap();
+		let fds = &fds;
+		let argdata = crate::encoded_with_fds(
+			&buf,
+			fd::ConvertFdFn(|raw| fds.get(raw as usize).cloned().ok_or(fd::InvalidFd)),
+		);
+		assert_eq!(from_argdata(&argdata), Ok(map));
+		assert_eq!(
+			from_slice::<BTreeMap<String, fd::Fd>>(&buf),
+			Err(Error::Read(ReadError::InvalidFdNumber(0)))
+		);
+	}
+}
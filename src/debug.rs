@@ -1,15 +1,154 @@
-use crate::{Argdata, ArgdataRef, ReadError, Value};
+use crate::{parse::MAX_DEPTH, values::Owned, Argdata, ArgdataRef, OwnedArgdata, ReadError, Value};
 use std::fmt;
 use std::ops::Deref;
 
-struct FmtError<T>(Result<T, ReadError>);
+/// A notation for argdata values, written by [`write_value`].
+///
+/// The traversal of maps and seqs is shared by the `Debug` implementations,
+/// [`text`](crate::text) and [`yaml`](crate::yaml). A notation only decides
+/// how to write single values and errors, and what to write around the
+/// elements of maps and seqs.
+pub(crate) trait Notation {
+	type Error;
 
-impl<T: fmt::Debug> fmt::Debug for FmtError<T> {
-	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-		match self.0 {
-			Ok(ref value) => value.fmt(f),
-			Err(ref err) => write!(f, "error(\"{:?}\")", err),
+	/// Write a value that is not a map or a seq.
+	fn scalar(&mut self, value: Value) -> Result<(), Self::Error>;
+
+	/// Handle a value that can't be read, either by writing something in its
+	/// place, or by returning an error.
+	fn error(&mut self, error: ReadError) -> Result<(), Self::Error>;
+
+	/// Write the start of a map or a seq.
+	fn begin(&mut self, map: bool) -> Result<(), Self::Error>;
+
+	/// Write what comes before an entry of a map or an element of a seq,
+	/// which is nested `depth` levels deep.
+	fn item(&mut self, map: bool, first: bool, depth: usize) -> Result<(), Self::Error>;
+
+	/// Write what comes between the key and the value of an entry.
+	fn colon(&mut self, depth: usize) -> Result<(), Self::Error>;
+
+	/// Write the end of a map or a seq, which is nested `depth` levels deep.
+	fn end(&mut self, map: bool, empty: bool, depth: usize) -> Result<(), Self::Error>;
+}
+
+/// Write a value and everything it contains in the given notation.
+///
+/// Maps and seqs nested more than 256 levels deep are written as a
+/// `ReadError::NestedTooDeeply` error instead.
+pub(crate) fn write_value<N: Notation>(
+	n: &mut N,
+	value: Result<Value, ReadError>,
+	depth: usize,
+) -> Result<(), N::Error> {
+	match value {
+		Err(e) => n.error(e),
+		Ok(Value::Map(_)) | Ok(Value::Seq(_)) if depth > MAX_DEPTH => {
+			n.error(ReadError::NestedTooDeeply)
+		}
+		Ok(Value::Map(it)) => {
+			n.begin(true)?;
+			let mut empty = true;
+			for entry in it {
+				n.item(true, empty, depth + 1)?;
+				empty = false;
+				match entry {
+					Ok((key, value)) => {
+						write_value(n, key.read(), depth + 1)?;
+						n.colon(depth + 1)?;
+						write_value(n, value.read(), depth + 1)?;
+					}
+					Err(e) => {
+						n.error(e)?;
+						n.colon(depth + 1)?;
+						n.error(e)?;
+					}
+				}
+			}
+			n.end(true, empty, depth)
+		}
+		Ok(Value::Seq(it)) => {
+			n.begin(false)?;
+			let mut empty = true;
+			for element in it {
+				n.item(false, empty, depth + 1)?;
+				empty = false;
+				match element {
+					Ok(element) => write_value(n, element.read(), depth + 1)?,
+					Err(e) => n.error(e)?,
+				}
+			}
+			n.end(false, empty, depth)
+		}
+		Ok(value) => n.scalar(value),
+	}
+}
+
+/// The notation of the `Debug` implementations, which also supports `{:#?}`.
+struct DebugNotation<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl<'a, 'b> DebugNotation<'a, 'b> {
+	fn newline(&mut self, depth: usize) -> fmt::Result {
+		writeln!(self.0)?;
+		for _ in 0..depth {
+			self.0.write_str("    ")?;
+		}
+		Ok(())
+	}
+}
+
+impl<'a, 'b> Notation for DebugNotation<'a, 'b> {
+	type Error = fmt::Error;
+
+	fn scalar(&mut self, value: Value) -> fmt::Result {
+		let f = &mut *self.0;
+		match value {
+			Value::Null => write!(f, "null"),
+			Value::Binary(val) => write!(f, "binary({:?})", val),
+			Value::Bool(val) => fmt::Debug::fmt(&val, f),
+			Value::Fd(fd) => write!(f, "fd({})", fd.raw_encoded_number()),
+			Value::Float(val) => fmt::Debug::fmt(&val, f),
+			Value::Int(val) => fmt::Debug::fmt(&val, f),
+			Value::Str(val) => match val.as_str() {
+				Ok(val) => fmt::Debug::fmt(val, f),
+				Err(e) => self.error(e.into()),
+			},
+			Value::Timestamp(val) => write!(f, "timestamp({}, {})", val.sec, val.nsec),
+			Value::Map(_) | Value::Seq(_) => unreachable!(),
+		}
+	}
+
+	fn error(&mut self, error: ReadError) -> fmt::Result {
+		write!(self.0, "error(\"{:?}\")", error)
+	}
+
+	fn begin(&mut self, map: bool) -> fmt::Result {
+		self.0.write_str(if map { "{" } else { "[" })
+	}
+
+	fn item(&mut self, _map: bool, first: bool, depth: usize) -> fmt::Result {
+		if self.0.alternate() {
+			if !first {
+				self.0.write_str(",")?;
+			}
+			self.newline(depth)
+		} else if !first {
+			self.0.write_str(", ")
+		} else {
+			Ok(())
+		}
+	}
+
+	fn colon(&mut self, _depth: usize) -> fmt::Result {
+		self.0.write_str(": ")
+	}
+
+	fn end(&mut self, map: bool, empty: bool, depth: usize) -> fmt::Result {
+		if self.0.alternate() && !empty {
+			self.0.write_str(",")?;
+			self.newline(depth)?;
 		}
+		self.0.write_str(if map { "}" } else { "]" })
 	}
 }
 
@@ -21,33 +160,25 @@ impl<'a, 'd> fmt::Debug for ArgdataRef<'a, 'd> {
 
 impl<'a, 'd> fmt::Debug for dyn Argdata<'d> + 'a {
 	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-		FmtError(self.read()).fmt(f)
+		write_value(&mut DebugNotation(f), self.read(), 0)
+	}
+}
+
+impl fmt::Debug for OwnedArgdata {
+	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		self.argdata().fmt(f)
+	}
+}
+
+impl<'d> fmt::Debug for Owned<'d> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		(self as &dyn Argdata).fmt(f)
 	}
 }
 
 impl<'a, 'd> fmt::Debug for Value<'a, 'd> {
 	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-		match self {
-			Value::Null => write!(f, "null"),
-			Value::Binary(val) => write!(f, "binary({:?})", val),
-			Value::Bool(val) => val.fmt(f),
-			Value::Fd(fd) => write!(f, "fd({})", fd.raw_encoded_number()),
-			Value::Float(val) => fmt::Debug::fmt(val, f),
-			Value::Int(val) => fmt::Debug::fmt(val, f),
-			Value::Str(val) => fmt::Debug::fmt(&FmtError(val.as_str().map_err(Into::into)), f),
-			Value::Timestamp(val) => write!(f, "timestamp({}, {})", val.sec, val.nsec),
-			Value::Map(val) => {
-				let it = val.map(|x| match x {
-					Ok((k, v)) => (FmtError(Ok(k)), FmtError(Ok(v))),
-					Err(e) => (FmtError(Err(e)), FmtError(Err(e))),
-				});
-				f.debug_map().entries(it).finish()
-			}
-			Value::Seq(val) => {
-				let it = val.map(FmtError);
-				f.debug_list().entries(it).finish()
-			}
-		}
+		write_value(&mut DebugNotation(f), Ok(*self), 0)
 	}
 }
 
@@ -71,3 +202,28 @@ fn debug_fmt() {
 		"[false, true, null, \"Hello\", {}, []]"
 	);
 }
+
+#[test]
+fn debug_fmt_alternate() {
+	let value = OwnedArgdata::Map(vec![
+		(
+			"a".into(),
+			OwnedArgdata::Seq(vec![1.into(), OwnedArgdata::Seq(vec![])]),
+		),
+		(OwnedArgdata::Map(vec![]), OwnedArgdata::Null),
+	]);
+	assert_eq!(
+		format!("{:#?}", value),
+		"{\n    \"a\": [\n        1,\n        [],\n    ],\n    {}: null,\n}"
+	);
+	assert_eq!(
+		format!("{:#?}", vec![OwnedArgdata::Seq(vec![true.into()])]),
+		"[\n    [\n        true,\n    ],\n]"
+	);
+
+	let argdata = crate::encoded(&b"\x07\x82\x08a"[..]);
+	assert_eq!(
+		format!("{:#?}", &argdata as &dyn Argdata),
+		"[\n    error(\"MissingNullTerminator\"),\n]"
+	);
+}
@@ -0,0 +1,471 @@
+//! [`diff`](crate::diff::diff) lists the differences between two values as
+//! [`Change`](crate::diff::Change)s, each at a [`Path`](crate::diff::Path)
+//! into the values, which is written in the syntax of
+//! [`get_path`](crate::get_path). Changes are displayed one per line, with
+//! the values in the [`text`](crate::text) notation:
+//!
+//! ```
+//! # use argdata::{diff, text};
+//! let old = text::parse(r#"{"port": 80, "hosts": ["a", "b", "c"], "debug": false}"#).unwrap();
+//! let new = text::parse(r#"{"port": 8080, "hosts": ["a", "c", "d"], "log": "x"}"#).unwrap();
+//! let changes = diff::diff(old.argdata(), new.argdata()).unwrap();
+//! assert_eq!(
+//!     diff::render(&changes),
+//!     "~ port: 80 -> 8080\n\
+//!      - hosts[1]: \"b\"\n\
+//!      + hosts[2]: \"d\"\n\
+//!      - debug: false\n\
+//!      + log: \"x\"\n"
+//! );
+//! ```
+//!
+//! Entries of maps are matched by their keys. If a map contains the same key
+//! more than once, only the first one is used. Elements of seqs are matched
+//! using a longest common subsequence, so an inserted or removed element
+//! doesn't show up as a change of every element after it. This takes time
+//! and memory (four bytes per pair of elements) proportional to the product
+//! of the lengths of the seqs, not counting the equal elements at their start
+//! and end. Above [`MAX_LCS_PAIRS`](crate::diff::MAX_LCS_PAIRS) pairs, the
+//! remaining elements are instead paired up by their position, as if they
+//! had nothing in common.
+//!
+//! Values are compared as with [`try_eq`](crate::try_eq), and included in the
+//! changes as with [`OwnedArgdata::from_argdata`].
+
+use crate::{
+	parse::MAX_DEPTH, path::write_key, try_eq, Argdata, ArgdataRef, OwnedArgdata, ReadError, Value,
+};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+/// The maximum number of pairs of elements for which a longest common
+/// subsequence is searched, which limits its memory use to 16 MiB.
+pub const MAX_LCS_PAIRS: usize = 1 << 22;
+
+/// A difference between two values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+	/// Where the change is.
+	///
+	/// For removed and changed seq elements, the last index is the index in
+	/// the old seq, and for inserted elements, the index in the new seq.
+	pub path: Path,
+	pub kind: ChangeKind,
+}
+
+/// What changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeKind {
+	/// A key was added to a map, with this value.
+	Added(OwnedArgdata),
+	/// An element was inserted into a seq.
+	Inserted(OwnedArgdata),
+	/// A key was removed from a map, or an element from a seq, with this
+	/// value.
+	Removed(OwnedArgdata),
+	/// The value changed. Maps and seqs that are both in the old and new
+	/// value at the same path are not changed themselves, only their contents.
+	Changed {
+		old: OwnedArgdata,
+		new: OwnedArgdata,
+	},
+}
+
+/// The location of a value inside of maps and seqs.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Path(pub Vec<PathSegment>);
+
+/// A key of a map, or an index into a seq.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+	Key(OwnedArgdata),
+	Index(usize),
+}
+
+/// Displays the path as accepted by [`get_path`](crate::get_path), such as
+/// `listen.addrs[2]`, or as nothing for the empty path.
+///
+/// Keys that aren't strings can't be used with `get_path`. They are written
+/// in the [`text`](crate::text) notation between `{` and `}`, such as
+/// `items{5}`.
+impl Display for Path {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for (i, segment) in self.0.iter().enumerate() {
+			match segment {
+				PathSegment::Key(OwnedArgdata::Str(key)) => write_key(f, key, i == 0)?,
+				PathSegment::Key(key) => write!(f, "{{{:?}}}", key)?,
+				PathSegment::Index(index) => write!(f, "[{}]", index)?,
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Displays the change on a single line, starting with `+` for added keys
+/// and inserted elements, `-` for removed keys and elements, and `~` for
+/// changed values.
+impl Display for Change {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let sign = match self.kind {
+			ChangeKind::Added(_) | ChangeKind::Inserted(_) => '+',
+			ChangeKind::Removed(_) => '-',
+			ChangeKind::Changed { .. } => '~',
+		};
+		write!(f, "{} ", sign)?;
+		if !self.path.0.is_empty() {
+			write!(f, "{}: ", self.path)?;
+		}
+		match &self.kind {
+			ChangeKind::Added(value) | ChangeKind::Removed(value) | ChangeKind::Inserted(value) => {
+				write!(f, "{:?}", value)
+			}
+			ChangeKind::Changed { old, new } => write!(f, "{:?} -> {:?}", old, new),
+		}
+	}
+}
+
+/// Render a list of changes, one per line.
+pub fn render(changes: &[Change]) -> String {
+	changes
+		.iter()
+		.map(|change| format!("{}\n", change))
+		.collect()
+}
+
+/// Find the differences between two values.
+///
+/// Returns an empty list if the values are equal. Fails if a value that
+/// needs to be compared can't be read, or contains a string that isn't valid
+/// UTF-8, and with `ReadError::NestedTooDeeply` if both values have maps or
+/// seqs at the same path that are nested more than 256 levels deep.
+pub fn diff(old: &dyn Argdata, new: &dyn Argdata) -> Result<Vec<Change>, ReadError> {
+	let mut changes = Vec::new();
+	diff_value(&mut Path::default(), old, new, &mut changes)?;
+	Ok(changes)
+}
+
+/// Add the changes between two values at `path`, which is `path.0.len()`
+/// levels deep.
+fn diff_value(
+	path: &mut Path,
+	old: &dyn Argdata,
+	new: &dyn Argdata,
+	changes: &mut Vec<Change>,
+) -> Result<(), ReadError> {
+	let values = (old.read()?, new.read()?);
+	match values {
+		// Maps and seqs are compared by their contents below, instead of
+		// comparing them completely first.
+		(Value::Map(_), Value::Map(_)) | (Value::Seq(_), Value::Seq(_))
+			if path.0.len() > MAX_DEPTH =>
+		{
+			return Err(ReadError::NestedTooDeeply);
+		}
+		(Value::Map(_), Value::Map(_)) | (Value::Seq(_), Value::Seq(_)) => {}
+		_ if try_eq(old, new)? => return Ok(()),
+		_ => {}
+	}
+	match values {
+		(Value::Map(old), Value::Map(new)) => {
+			let old: Vec<_> = old.collect::<Result<_, _>>()?;
+			let new: Vec<_> = new.collect::<Result<_, _>>()?;
+			let old_keys = first_keys(&old)?;
+			let new_keys = first_keys(&new)?;
+			for (i, (key, old_value)) in old.iter().enumerate() {
+				let key = OwnedArgdata::from_argdata(&**key)?;
+				if old_keys[&key] != i {
+					continue;
+				}
+				let new_index = new_keys.get(&key).cloned();
+				path.0.push(PathSegment::Key(key));
+				match new_index {
+					Some(j) => diff_value(path, &**old_value, &*new[j].1, changes)?,
+					None => push(
+						changes,
+						path,
+						ChangeKind::Removed(OwnedArgdata::from_argdata(&**old_value)?),
+					),
+				}
+				path.0.pop();
+			}
+			for (j, (key, new_value)) in new.iter().enumerate() {
+				let key = OwnedArgdata::from_argdata(&**key)?;
+				if new_keys[&key] != j || old_keys.contains_key(&key) {
+					continue;
+				}
+				path.0.push(PathSegment::Key(key));
+				push(
+					changes,
+					path,
+					ChangeKind::Added(OwnedArgdata::from_argdata(&**new_value)?),
+				);
+				path.0.pop();
+			}
+		}
+		(Value::Seq(old), Value::Seq(new)) => {
+			let old: Vec<_> = old.collect::<Result<_, _>>()?;
+			let new: Vec<_> = new.collect::<Result<_, _>>()?;
+			diff_seq(path, &old, &new, changes)?;
+		}
+		_ => push(
+			changes,
+			path,
+			ChangeKind::Changed {
+				old: OwnedArgdata::from_argdata(old)?,
+				new: OwnedArgdata::from_argdata(new)?,
+			},
+		),
+	}
+	Ok(())
+}
+
+fn diff_seq(
+	path: &mut Path,
+	old: &[ArgdataRef],
+	new: &[ArgdataRef],
+	changes: &mut Vec<Change>,
+) -> Result<(), ReadError> {
+	// Skip the equal elements at the start and end.
+	let mut start = 0;
+	while start < old.len() && start < new.len() && try_eq(&*old[start], &*new[start])? {
+		start += 1;
+	}
+	let (mut old_end, mut new_end) = (old.len(), new.len());
+	while old_end > start && new_end > start && try_eq(&*old[old_end - 1], &*new[new_end - 1])? {
+		old_end -= 1;
+		new_end -= 1;
+	}
+	let (n, m) = (old_end - start, new_end - start);
+	let (old, new) = (&old[start..old_end], &new[start..new_end]);
+
+	if n.saturating_mul(m) > MAX_LCS_PAIRS {
+		let (deleted, inserted): (Vec<usize>, Vec<usize>) = ((0..n).collect(), (0..m).collect());
+		return diff_block(path, old, new, start, &deleted, &inserted, changes);
+	}
+
+	// lcs[i * (m + 1) + j]: the length of the longest common subsequence of
+	// old[i..] and new[j..].
+	let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+	let at = |i: usize, j: usize| i * (m + 1) + j;
+	for i in (0..n).rev() {
+		for j in (0..m).rev() {
+			lcs[at(i, j)] = if try_eq(&*old[i], &*new[j])? {
+				lcs[at(i + 1, j + 1)] + 1
+			} else {
+				lcs[at(i + 1, j)].max(lcs[at(i, j + 1)])
+			};
+		}
+	}
+
+	let (mut i, mut j) = (0, 0);
+	let mut deleted: Vec<usize> = Vec::new();
+	let mut inserted: Vec<usize> = Vec::new();
+	loop {
+		let done = i == n && j == m;
+		if done || (i < n && j < m && try_eq(&*old[i], &*new[j])?) {
+			diff_block(path, old, new, start, &deleted, &inserted, changes)?;
+			if done {
+				return Ok(());
+			}
+			deleted.clear();
+			inserted.clear();
+			i += 1;
+			j += 1;
+		} else if j < m && (i == n || lcs[at(i, j + 1)] >= lcs[at(i + 1, j)]) {
+			inserted.push(j);
+			j += 1;
+		} else {
+			deleted.push(i);
+			i += 1;
+		}
+	}
+}
+
+/// Add the changes for the elements that were deleted and inserted between
+/// two common elements (or the start or end) of a seq. Those are paired up
+/// as changed elements, as far as possible.
+///
+/// `start` is the index in the seqs of the first element of `old` and `new`.
+fn diff_block(
+	path: &mut Path,
+	old: &[ArgdataRef],
+	new: &[ArgdataRef],
+	start: usize,
+	deleted: &[usize],
+	inserted: &[usize],
+	changes: &mut Vec<Change>,
+) -> Result<(), ReadError> {
+	let pairs = deleted.len().min(inserted.len());
+	for (&d, &k) in deleted.iter().zip(inserted) {
+		path.0.push(PathSegment::Index(start + d));
+		diff_value(path, &*old[d], &*new[k], changes)?;
+		path.0.pop();
+	}
+	for &d in &deleted[pairs..] {
+		path.0.push(PathSegment::Index(start + d));
+		push(
+			changes,
+			path,
+			ChangeKind::Removed(OwnedArgdata::from_argdata(&*old[d])?),
+		);
+		path.0.pop();
+	}
+	for &k in &inserted[pairs..] {
+		path.0.push(PathSegment::Index(start + k));
+		push(
+			changes,
+			path,
+			ChangeKind::Inserted(OwnedArgdata::from_argdata(&*new[k])?),
+		);
+		path.0.pop();
+	}
+	Ok(())
+}
+
+/// The index of the first entry with each key.
+fn first_keys(
+	entries: &[(ArgdataRef, ArgdataRef)],
+) -> Result<BTreeMap<OwnedArgdata, usize>, ReadError> {
+	let mut keys = BTreeMap::new();
+	for (i, (key, _)) in entries.iter().enumerate() {
+		keys.entry(OwnedArgdata::from_argdata(&**key)?).or_insert(i);
+	}
+	Ok(keys)
+}
+
+fn push(changes: &mut Vec<Change>, path: &Path, kind: ChangeKind) {
+	changes.push(Change {
+		path: path.clone(),
+		kind,
+	});
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{
+		encoded, encoded_with_fds,
+		fd::{self, Identity},
+		text,
+	};
+
+	fn changes(old: &str, new: &str) -> String {
+		let old = text::parse(old).unwrap();
+		let new = text::parse(new).unwrap();
+		render(&diff(old.argdata(), new.argdata()).unwrap())
+	}
+
+	#[test]
+	fn diff_test() {
+		assert_eq!(
+			changes(r#"{"a": [1, {"b": 2}]}"#, r#"{"a": [1, {"b": 2}]}"#),
+			""
+		);
+		assert_eq!(changes("1", "2"), "~ 1 -> 2\n");
+		assert_eq!(changes("1", "[1]"), "~ 1 -> [1]\n");
+		assert_eq!(
+			changes(
+				r#"{"a": {"b": [1, 2]}, "a.b": 1, 3: "x"}"#,
+				r#"{"a": {"b": [1, 3], "c": null}, "a.b": 2, 3: "y"}"#
+			),
+			"~ a.b[1]: 2 -> 3\n\
+			 + a.c: null\n\
+			 ~ [\"a.b\"]: 1 -> 2\n\
+			 ~ {3}: \"x\" -> \"y\"\n"
+		);
+		// Only the first of duplicate keys is compared.
+		assert_eq!(changes(r#"{"a": 1, "a": 2}"#, r#"{"a": 1}"#), "");
+	}
+
+	#[test]
+	fn diff_seq_test() {
+		assert_eq!(changes("[1, 2, 3]", "[1, 3]"), "- [1]: 2\n");
+		assert_eq!(changes("[1, 3]", "[1, 2, 3]"), "+ [1]: 2\n");
+		assert_eq!(changes("[]", "[1, 2]"), "+ [0]: 1\n+ [1]: 2\n");
+		assert_eq!(
+			changes("[0, 1, 2, 3, 9]", "[0, 4, 2, 5, 6, 9]"),
+			"~ [1]: 1 -> 4\n~ [3]: 3 -> 5\n+ [4]: 6\n"
+		);
+		assert_eq!(
+			changes(r#"[{"a": 1}, "x"]"#, r#"[{"a": 2}]"#),
+			"~ [0].a: 1 -> 2\n- [1]: \"x\"\n"
+		);
+	}
+
+	#[test]
+	fn diff_long_seq_test() {
+		// Too long to search for a common subsequence, so the elements are
+		// paired up by position.
+		let n = 3000;
+		let old = OwnedArgdata::Seq((0..n).map(Into::into).collect());
+		let new = OwnedArgdata::Seq((1..=n).map(Into::into).collect());
+		let changes = diff(old.argdata(), new.argdata()).unwrap();
+		assert_eq!(changes.len(), n as usize);
+		assert_eq!(changes[0].to_string(), "~ [0]: 0 -> 1");
+
+		// Short enough, so only the first and last element differ.
+		let n = 1000;
+		let old = OwnedArgdata::Seq((0..n).map(Into::into).collect());
+		let new = OwnedArgdata::Seq((1..=n).map(Into::into).collect());
+		assert_eq!(
+			render(&diff(old.argdata(), new.argdata()).unwrap()),
+			format!("- [0]: 0\n+ [{}]: {}\n", n - 1, n)
+		);
+	}
+
+	#[test]
+	fn diff_kind_test() {
+		let old = encoded_with_fds(b"\x07\x85\x03\x00\x00\x00\x01", Identity);
+		let new = encoded_with_fds(b"\x07\x85\x03\x00\x00\x00\x02\x83\x08a\x00", Identity);
+		assert_eq!(
+			diff(&old, &new),
+			Ok(vec![
+				Change {
+					path: Path(vec![PathSegment::Index(0)]),
+					kind: ChangeKind::Changed {
+						old: fd::Fd(1).into(),
+						new: fd::Fd(2).into(),
+					},
+				},
+				Change {
+					path: Path(vec![PathSegment::Index(1)]),
+					kind: ChangeKind::Inserted("a".into()),
+				},
+			])
+		);
+		assert_eq!(
+			diff(&encoded(b"\x07\x81\x08"), &encoded(b"\x07")),
+			Err(ReadError::MissingNullTerminator)
+		);
+	}
+
+	#[test]
+	fn diff_deep_nesting_test() {
+		// The innermost seq containing a null is replaced by true.
+		let nested = |depth| {
+			let old = crate::parse::nested_seqs(depth);
+			let mut new = old.clone();
+			new.truncate(old.len() - 2);
+			new.extend_from_slice(b"\x02\x01");
+			(old, new)
+		};
+		let (old, new) = nested(MAX_DEPTH + 2);
+		assert_eq!(
+			diff(&encoded(&old), &encoded(&new)).map(|c| render(&c)),
+			Ok(format!(
+				"~ {}: [null] -> true\n",
+				"[0]".repeat(MAX_DEPTH + 1)
+			))
+		);
+		let (old, new) = nested(MAX_DEPTH + 3);
+		assert_eq!(
+			diff(&encoded(&old), &encoded(&new)),
+			Err(ReadError::NestedTooDeeply)
+		);
+		let (old, new) = nested(200_000);
+		assert_eq!(
+			diff(&encoded(&old), &encoded(&new)),
+			Err(ReadError::NestedTooDeeply)
+		);
+	}
+}
@@ -1,8 +1,38 @@
 use crate::{encoded_with_fds, fd, values::EncodedArgdata};
 
+/// The environment variable naming the file descriptor from which
+/// [`argdata()`] is read on targets other than CloudABI.
+pub const FD_VAR: &str = "ARGDATA_FD";
+
+/// The environment variable naming the process for which [`FD_VAR`] is
+/// meant, as a decimal process ID.
+pub const PID_VAR: &str = "ARGDATA_PID";
+
 /// Returns the argdata which this program was started with.
 ///
-/// Only available on CloudABI targets.
+/// On CloudABI, this is the argdata the program was started with by the
+/// system. On other Unix targets, the argdata is read from the inherited
+/// file descriptor named by the `ARGDATA_FD` environment variable (see
+/// [`FD_VAR`]), which is then closed. This happens only once, on the first
+/// call. Without that variable, or on other targets, the argdata is empty.
+///
+/// The file descriptor is only used if it is meant for this process, and not
+/// inherited through it by a child: either `ARGDATA_PID` (see [`PID_VAR`]) is
+/// set to the ID of this process, or, if that variable isn't set, the owner of
+/// the file descriptor (as set by `fcntl(F_SETOWN)`) is this process. The
+/// latter is what [`process::CommandExt`](crate::process::CommandExt) does,
+/// as the ID of the child isn't known when its environment is set up.
+///
+/// Both variables are removed from the environment on the first call, so
+/// they are not passed on to children started afterwards.
+///
+/// The file descriptors in the argdata are the inherited ones, with the same
+/// number.
+///
+/// # Panics
+///
+/// Panics if the argdata is meant for this process, but `ARGDATA_FD` does
+/// not name an open file descriptor above standard error that can be read.
 pub fn argdata() -> EncodedArgdata<'static, fd::Identity> {
 	argdata_impl()
 }
@@ -23,7 +53,101 @@ fn argdata_impl() -> EncodedArgdata<'static, fd::Identity> {
 	}
 }
 
-#[cfg(not(target_os = "cloudabi"))]
+#[cfg(all(unix, not(target_os = "cloudabi")))]
+fn argdata_impl() -> EncodedArgdata<'static, fd::Identity> {
+	use std::sync::OnceLock;
+	static DATA: OnceLock<Vec<u8>> = OnceLock::new();
+	let data = DATA.get_or_init(|| {
+		let fd_var = std::env::var_os(FD_VAR);
+		let pid_var = std::env::var_os(PID_VAR);
+		std::env::remove_var(FD_VAR);
+		std::env::remove_var(PID_VAR);
+		match fd_var {
+			Some(var) => read_fd_var(&var, pid_var.as_deref())
+				.unwrap_or_else(|e| panic!("{}={:?}: {}", FD_VAR, var, e)),
+			None => Vec::new(),
+		}
+	});
+	encoded_with_fds(data, fd::Identity)
+}
+
+#[cfg(not(any(unix, target_os = "cloudabi")))]
 fn argdata_impl() -> EncodedArgdata<'static, fd::Identity> {
 	encoded_with_fds(&[], fd::Identity)
 }
+
+/// Read all data from the file descriptor named by the value of [`FD_VAR`],
+/// and close it, if it is meant for this process.
+///
+/// `pid` is the value of [`PID_VAR`], if any. Returns nothing if the file
+/// descriptor is not meant for this process, without touching it.
+///
+/// Reading starts at the beginning of the file, if it is seekable.
+#[cfg(all(unix, not(target_os = "cloudabi")))]
+fn read_fd_var(var: &std::ffi::OsStr, pid: Option<&std::ffi::OsStr>) -> std::io::Result<Vec<u8>> {
+	use std::io::{self, Read, Seek, SeekFrom};
+	use std::os::unix::io::{FromRawFd, RawFd};
+
+	let fd: RawFd = var
+		.to_str()
+		.and_then(|s| s.parse().ok())
+		.filter(|&fd| fd > 2)
+		.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file descriptor"))?;
+	let open = if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+		Err(io::Error::last_os_error())
+	} else {
+		Ok(())
+	};
+	let own_pid = unsafe { libc::getpid() };
+	let ours = match pid {
+		Some(pid) => pid.to_str() == Some(&own_pid.to_string()),
+		None => open.is_ok() && unsafe { libc::fcntl(fd, libc::F_GETOWN) } == own_pid,
+	};
+	if !ours {
+		return Ok(Vec::new());
+	}
+	open?;
+	let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+	// Pipes and sockets can't seek, but they are read from the start anyway.
+	let _ = file.seek(SeekFrom::Start(0));
+	let mut data = Vec::new();
+	file.read_to_end(&mut data)?;
+	Ok(data)
+}
+
+#[cfg(all(unix, not(target_os = "cloudabi")))]
+#[test]
+fn read_fd_var_test() {
+	use std::io::Write;
+	use std::os::unix::{io::IntoRawFd, net::UnixStream};
+
+	let pipe = || {
+		let (mut a, b) = UnixStream::pair().unwrap();
+		a.write_all(b"\x08hello\x00").unwrap();
+		b.into_raw_fd()
+	};
+	let is_open = |fd| unsafe { libc::fcntl(fd, libc::F_GETFD) } >= 0;
+	let own_pid = std::process::id().to_string();
+
+	let fd = pipe();
+	let var = fd.to_string();
+	assert_eq!(read_fd_var(var.as_ref(), Some("1".as_ref())).unwrap(), b"");
+	assert_eq!(read_fd_var(var.as_ref(), None).unwrap(), b"");
+	assert!(is_open(fd));
+	assert_eq!(
+		read_fd_var(var.as_ref(), Some(own_pid.as_ref())).unwrap(),
+		b"\x08hello\x00"
+	);
+	assert!(!is_open(fd));
+
+	let fd = pipe();
+	unsafe { libc::fcntl(fd, libc::F_SETOWN, libc::getpid()) };
+	assert_eq!(
+		read_fd_var(fd.to_string().as_ref(), None).unwrap(),
+		b"\x08hello\x00"
+	);
+
+	for var in &["x", "-1", "2", "1000000"] {
+		assert!(read_fd_var(var.as_ref(), Some(own_pid.as_ref())).is_err());
+	}
+}
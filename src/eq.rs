@@ -0,0 +1,263 @@
+use crate::{
+	fd, parse::MAX_DEPTH, values::EncodedArgdata, Argdata, ArgdataRef, OwnedArgdata, ReadError,
+	StrValue, Value,
+};
+use std::os::raw::c_int;
+
+/// Check if two values are equal, including everything they contain.
+///
+/// Values of different types are never equal. Otherwise:
+///
+///  - Strings are equal if they have the same bytes, even if they are not
+///    valid UTF-8.
+///  - Floats are equal if they have the same bits. So a NaN is equal to a NaN
+///    with the same bits, and `0.0` is not equal to `-0.0`.
+///  - File descriptors are equal if they convert to the same `Fd` (see
+///    [`EncodedFd::to_fd`](fd::EncodedFd::to_fd)), such that a value is equal
+///    to the [`OwnedArgdata`] read from it. A file descriptor that can't be
+///    converted results in `ReadError::InvalidFdNumber`.
+///  - Maps are equal if they have equal entries, in the same order.
+///
+/// Values that are both backed by encoded data (see
+/// [`Argdata::encoded_bytes`]) with identical bytes are equal, without
+/// reading them, unless the data might contain file descriptors. Otherwise, an
+/// error is returned when a value that needs to be compared can't be read, or
+/// when maps or seqs that need to be compared are nested more than 256
+/// levels deep.
+///
+/// `==` on argdata values uses this function, and treats errors as not equal.
+pub fn try_eq(a: &dyn Argdata<'_>, b: &dyn Argdata<'_>) -> Result<bool, ReadError> {
+	eq_at(a, b, 0)
+}
+
+/// Compare two values that are nested `depth` levels deep.
+fn eq_at(a: &dyn Argdata<'_>, b: &dyn Argdata<'_>, depth: usize) -> Result<bool, ReadError> {
+	if identical(a, b) {
+		return Ok(true);
+	}
+	value_eq(a.read()?, b.read()?, depth)
+}
+
+/// Check if both values are backed by identical encoded data that can't
+/// contain any file descriptors.
+///
+/// File descriptors might be converted differently for both values. Data
+/// without any byte 3 (the fd tag) can't contain any.
+pub(crate) fn identical(a: &dyn Argdata<'_>, b: &dyn Argdata<'_>) -> bool {
+	match (a.encoded_bytes(), b.encoded_bytes()) {
+		(Some(a), Some(b)) => a == b && !a.contains(&3),
+		_ => false,
+	}
+}
+
+/// Convert a file descriptor, for comparing it.
+pub(crate) fn fd_number(fd: fd::EncodedFd<&dyn fd::ConvertFd>) -> Result<c_int, ReadError> {
+	fd.to_fd()
+		.map(|fd| fd.0)
+		.map_err(ReadError::InvalidFdNumber)
+}
+
+fn value_eq(a: Value, b: Value, depth: usize) -> Result<bool, ReadError> {
+	if depth > MAX_DEPTH {
+		if let (Value::Map(_), Value::Map(_)) | (Value::Seq(_), Value::Seq(_)) = (a, b) {
+			return Err(ReadError::NestedTooDeeply);
+		}
+	}
+	Ok(match (a, b) {
+		(Value::Null, Value::Null) => true,
+		(Value::Binary(a), Value::Binary(b)) => a == b,
+		(Value::Bool(a), Value::Bool(b)) => a == b,
+		(Value::Fd(a), Value::Fd(b)) => fd_number(a)? == fd_number(b)?,
+		(Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+		(Value::Int(a), Value::Int(b)) => a == b,
+		(Value::Str(a), Value::Str(b)) => a == b,
+		(Value::Timestamp(a), Value::Timestamp(b)) => a == b,
+		(Value::Map(mut a), Value::Map(mut b)) => loop {
+			match (a.next(), b.next()) {
+				(None, None) => break true,
+				(Some(a), Some(b)) => {
+					let ((ak, av), (bk, bv)) = (a?, b?);
+					if !eq_at(&*ak, &*bk, depth + 1)? || !eq_at(&*av, &*bv, depth + 1)? {
+						break false;
+					}
+				}
+				_ => break false,
+			}
+		},
+		(Value::Seq(mut a), Value::Seq(mut b)) => loop {
+			match (a.next(), b.next()) {
+				(None, None) => break true,
+				(Some(a), Some(b)) => {
+					if !eq_at(&*a?, &*b?, depth + 1)? {
+						break false;
+					}
+				}
+				_ => break false,
+			}
+		},
+		_ => false,
+	})
+}
+
+/// Compares using [`try_eq`]. Values that can't be read are not equal.
+impl<'a, 'b, 'd, 'e> PartialEq<dyn Argdata<'e> + 'b> for dyn Argdata<'d> + 'a {
+	fn eq(&self, other: &(dyn Argdata<'e> + 'b)) -> bool {
+		try_eq(self, other).unwrap_or(false)
+	}
+}
+
+/// Compares using [`try_eq`]. Values that can't be read are not equal.
+impl<'a, 'b, 'd, 'e> PartialEq<ArgdataRef<'b, 'e>> for ArgdataRef<'a, 'd> {
+	fn eq(&self, other: &ArgdataRef<'b, 'e>) -> bool {
+		**self == **other
+	}
+}
+
+/// Compares using [`try_eq`]. Values that can't be read are not equal.
+impl<'d, 'e, F: fd::ConvertFd, G: fd::ConvertFd> PartialEq<EncodedArgdata<'e, G>>
+	for EncodedArgdata<'d, F>
+{
+	fn eq(&self, other: &EncodedArgdata<'e, G>) -> bool {
+		try_eq(self, other).unwrap_or(false)
+	}
+}
+
+/// Compares using [`try_eq`]. Maps and seqs that can't be read are not equal.
+impl<'a, 'b, 'd, 'e> PartialEq<Value<'b, 'e>> for Value<'a, 'd> {
+	fn eq(&self, other: &Value<'b, 'e>) -> bool {
+		value_eq(*self, *other, 0).unwrap_or(false)
+	}
+}
+
+/// Compares using [`try_eq`].
+impl PartialEq for OwnedArgdata {
+	fn eq(&self, other: &OwnedArgdata) -> bool {
+		try_eq(self.argdata(), other.argdata()).unwrap_or(false)
+	}
+}
+
+/// Compares the bytes, regardless of how the string is represented.
+impl<'d, 'e> PartialEq<StrValue<'e>> for StrValue<'d> {
+	fn eq(&self, other: &StrValue<'e>) -> bool {
+		self.as_bytes() == other.as_bytes()
+	}
+}
+
+impl Eq for StrValue<'_> {}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{encoded, encoded_with_fds, Timespec};
+	use std::ffi::CStr;
+
+	#[test]
+	fn eq_test() {
+		let owned = OwnedArgdata::Seq(vec![
+			1.into(),
+			"two".into(),
+			OwnedArgdata::Map(vec![(3.5.into(), fd::Fd(4).into())]),
+			Timespec { sec: 5, nsec: 6 }.into(),
+		]);
+		// With a non-minimal int and subfield length.
+		let data = b"\x07\x83\x05\x00\x01\x85\x08two\x00\x00\x91\x06\x89\x04\x40\x0C\x00\x00\x00\x00\x00\x00\x85\x03\x00\x00\x00\x04\x86\x09\x01\x2A\x05\xF2\x06";
+		let value = encoded_with_fds(data, fd::Identity);
+		assert_eq!(try_eq(owned.argdata(), &value), Ok(true));
+		assert!(owned == value.to_owned().unwrap());
+		let (a, b): (&dyn Argdata, &dyn Argdata) = (owned.argdata(), &value);
+		assert!(*a == *b);
+
+		let other = OwnedArgdata::Seq(vec![1.into(), "two".into()]);
+		assert_eq!(try_eq(owned.argdata(), other.argdata()), Ok(false));
+		assert!(owned != other);
+
+		let map = |entries: &[(i32, i32)]| {
+			OwnedArgdata::Map(entries.iter().map(|&(k, v)| (k.into(), v.into())).collect())
+		};
+		assert!(map(&[(1, 2), (3, 4)]) == map(&[(1, 2), (3, 4)]));
+		assert!(map(&[(1, 2), (3, 4)]) != map(&[(3, 4), (1, 2)]));
+		assert!(map(&[(1, 2)]) != map(&[(1, 2), (1, 2)]));
+		assert!(OwnedArgdata::from(1) != OwnedArgdata::from(1.0));
+	}
+
+	#[test]
+	fn eq_float_fd_test() {
+		let nan = OwnedArgdata::Float(f64::NAN);
+		assert!(nan == nan.clone());
+		assert!(OwnedArgdata::Float(0.0) != OwnedArgdata::Float(-0.0));
+
+		let data = b"\x03\x00\x00\x00\x00";
+		let fd = encoded_with_fds(data, fd::Identity);
+		assert_eq!(try_eq(&fd, &fd::Fd(0)), Ok(true));
+		assert_eq!(try_eq(&fd, &fd::Fd(4)), Ok(false));
+		assert_eq!(
+			try_eq(&encoded(data), &encoded(data)),
+			Err(ReadError::InvalidFdNumber(0))
+		);
+
+		// Converted through a table, encoded fd 0 is fd 4.
+		let fds = [fd::Fd(4)];
+		let mapped = encoded_with_fds(data, &fds[..]);
+		assert_eq!(try_eq(&mapped, &fd::Fd(4)), Ok(true));
+		assert_eq!(try_eq(&mapped, &fd), Ok(false));
+		assert_eq!(
+			try_eq(mapped.to_owned().unwrap().argdata(), &mapped),
+			Ok(true)
+		);
+	}
+
+	#[test]
+	fn eq_str_test() {
+		let cstr = CStr::from_bytes_with_nul(b"hi\0").unwrap();
+		let strs = [
+			StrValue::from_str("hi"),
+			StrValue::from_bytes_with_nul(b"hi\0"),
+			StrValue::from_bytes_without_nul(b"hi"),
+			StrValue::from_cstr(cstr),
+		];
+		for a in &strs {
+			for b in &strs {
+				assert!(a == b);
+				assert!(Value::Str(*a) == Value::Str(*b));
+			}
+		}
+		assert!(StrValue::from_str("hi") != StrValue::from_str("hi\0"));
+	}
+
+	#[test]
+	fn eq_error_test() {
+		let invalid = encoded(b"\x02\x02");
+		assert_eq!(try_eq(&invalid, &invalid), Ok(true));
+		assert_eq!(
+			try_eq(&invalid, &crate::bool(true)),
+			Err(ReadError::InvalidBoolValue)
+		);
+		assert!(invalid != encoded(b"\x02\x02\x02"));
+
+		let seq = encoded(b"\x07\x81\x05\x82");
+		assert_eq!(
+			try_eq(&seq, &encoded(b"\x07\x81\x05\x80")),
+			Err(ReadError::InvalidSubfield)
+		);
+	}
+
+	#[test]
+	fn eq_deep_nesting_test() {
+		// The innermost seq is replaced by a boolean of the same length.
+		let a = crate::parse::nested_seqs(200_000);
+		let mut b = a.clone();
+		let n = b.len();
+		b[n - 2..].copy_from_slice(b"\x02\x01");
+		assert_eq!(
+			try_eq(&encoded(&a), &encoded(&b)),
+			Err(ReadError::NestedTooDeeply)
+		);
+		assert!(encoded(&a) != encoded(&b));
+
+		let a = crate::parse::nested_seqs(MAX_DEPTH + 1);
+		let mut b = a.clone();
+		let n = b.len();
+		b[n - 2..].copy_from_slice(b"\x02\x01");
+		assert_eq!(try_eq(&encoded(&a), &encoded(&b)), Ok(false));
+	}
+}
@@ -32,32 +32,68 @@ pub enum ReadError {
 	/// The data contains a map with an incomplete key-value pair.
 	InvalidKeyValuePair,
 
+	/// The data contains a map that was required to have its keys sorted in
+	/// canonical order, but they are not.
+	UnsortedMap,
+
+	/// The data represents an integer with redundant leading bytes, which was
+	/// required to be encoded canonically.
+	NonMinimalInt,
+
+	/// The data represents a timestamp with redundant leading bytes, which
+	/// was required to be encoded canonically.
+	NonMinimalTimestamp,
+
+	/// The data contains a subfield length with redundant leading bytes,
+	/// which was required to be encoded canonically.
+	NonMinimalSubfieldLength,
+
+	/// The data contains a map with the same key more than once, which was
+	/// required to be encoded canonically.
+	DuplicateMapKey,
+
 	/// The data represents a file descriptor that doesn't exist.
 	/// (Possibly because there were no file descriptors 'attached' to the argdata value at all.)
 	InvalidFdNumber(u32),
+
+	/// The data contains maps or seqs nested more than 256 levels deep, which
+	/// is too deep to be handled recursively.
+	NestedTooDeeply,
 }
 
-impl Error for ReadError {
-	fn description(&self) -> &str {
-		#[cfg_attr(rustfmt, rustfmt_skip)]
+impl ReadError {
+	#[rustfmt::skip]
+	fn message(&self) -> &'static str {
 		match self {
-			ReadError::InvalidTag(_)         => "Invalid argdata tag",
-			ReadError::MissingNullTerminator => "Argdata contains a string without nul terminator",
-			ReadError::InvalidUtf8           => "Argdata contains invalid UTF-8",
-			ReadError::InvalidBoolValue      => "Argdata contains an invalid boolean value",
-			ReadError::InvalidFloatLength    => "Argdata contains floating point data of invalid length",
-			ReadError::InvalidFdLength       => "Argdata contains file descriptor data of invalid length",
-			ReadError::TimestampOutOfRange   => "Argdata contains a timestamp which is out of the accepted range",
-			ReadError::InvalidSubfield       => "Argdata has an incomplete subfield",
-			ReadError::InvalidKeyValuePair   => "Argdata map has an incomplete key-value pair",
-			ReadError::InvalidFdNumber(_)    => "Argdata contains a file descriptor that doesn't exist",
+			ReadError::InvalidTag(_)            => "Invalid argdata tag",
+			ReadError::MissingNullTerminator    => "Argdata contains a string without nul terminator",
+			ReadError::InvalidUtf8              => "Argdata contains invalid UTF-8",
+			ReadError::InvalidBoolValue         => "Argdata contains an invalid boolean value",
+			ReadError::InvalidFloatLength       => "Argdata contains floating point data of invalid length",
+			ReadError::InvalidFdLength          => "Argdata contains file descriptor data of invalid length",
+			ReadError::TimestampOutOfRange      => "Argdata contains a timestamp which is out of the accepted range",
+			ReadError::InvalidSubfield          => "Argdata has an incomplete subfield",
+			ReadError::InvalidKeyValuePair      => "Argdata map has an incomplete key-value pair",
+			ReadError::UnsortedMap              => "Argdata map keys are not sorted in canonical order",
+			ReadError::NonMinimalInt            => "Argdata contains an integer that is not minimally encoded",
+			ReadError::NonMinimalTimestamp      => "Argdata contains a timestamp that is not minimally encoded",
+			ReadError::NonMinimalSubfieldLength => "Argdata contains a subfield length that is not minimally encoded",
+			ReadError::DuplicateMapKey          => "Argdata map contains a duplicate key",
+			ReadError::InvalidFdNumber(_)       => "Argdata contains a file descriptor that doesn't exist",
+			ReadError::NestedTooDeeply          => "Argdata is nested too deeply",
 		}
 	}
 }
 
+impl Error for ReadError {
+	fn description(&self) -> &str {
+		self.message()
+	}
+}
+
 impl Display for ReadError {
 	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-		write!(f, "{}", self.description())?;
+		write!(f, "{}", self.message())?;
 		match self {
 			ReadError::InvalidTag(x) => write!(f, " (0x{:02X})", x),
 			ReadError::InvalidFdNumber(x) => write!(f, " ({})", *x as i32),
@@ -66,6 +102,33 @@ impl Display for ReadError {
 	}
 }
 
+/// An error while parsing or decoding another format, such as
+/// [`text`](crate::text), into argdata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+	offset: usize,
+	message: &'static str,
+}
+
+impl ParseError {
+	pub(crate) fn new(offset: usize, message: &'static str) -> ParseError {
+		ParseError { offset, message }
+	}
+
+	/// The byte offset in the input where the error occurred.
+	pub fn offset(&self) -> usize {
+		self.offset
+	}
+}
+
+impl Error for ParseError {}
+
+impl Display for ParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{} at byte {}", self.message, self.offset)
+	}
+}
+
 /// The reason why an `Argdata::read_*()` call didn't return a value, when there was no read error.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NoFit {
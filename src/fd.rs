@@ -79,6 +79,14 @@ where
 	}
 }
 
+/// Converts encoded fd numbers to the Fd at that index, the inverse of the
+/// [`FdMapping`] implementation for `Vec<Fd>`.
+impl ConvertFd for [Fd] {
+	fn convert_fd(&self, fd: u32) -> Result<Fd, InvalidFd> {
+		self.get(fd as usize).cloned().ok_or(InvalidFd)
+	}
+}
+
 impl<'a, T> ConvertFd for &'a T
 where
 	T: ConvertFd + 'a + ?Sized,
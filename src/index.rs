@@ -0,0 +1,388 @@
+use crate::{
+	canonical, fd, values::EncodedArgdata, Argdata, ArgdataRef, NoFit, NotRead, ReadError,
+};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// An index of the elements of an encoded seq or map, for random access.
+///
+/// Building the index reads the whole seq or map once. After that, getting
+/// an element by index, or a value of a map by its key, doesn't need to read
+/// any of the other elements.
+///
+/// Maps of which the keys are sorted in canonical order can be indexed with
+/// [`new_sorted`](EncodedIndex::new_sorted) instead, which doesn't build a
+/// hash table, and looks up keys using a binary search.
+///
+/// All returned values borrow the original encoded data, and are read in
+/// [strict](EncodedArgdata::strict) mode if the indexed value was.
+pub struct EncodedIndex<'d, F> {
+	/// The elements of a seq, or the keys and values of a map, alternating.
+	elements: Vec<&'d [u8]>,
+	keys: Keys<'d>,
+	convert_fd: F,
+	strict: bool,
+}
+
+enum Keys<'d> {
+	/// The indexed value is a seq.
+	None,
+	/// The index of the first entry for each key, by the canonical encoding
+	/// of the key. Keys that are already canonically encoded are borrowed.
+	Hashed(HashMap<Cow<'d, [u8]>, usize>),
+	/// The keys are sorted by their encoded bytes, without duplicates.
+	Sorted,
+}
+
+impl<'d, F: fd::ConvertFd> EncodedIndex<'d, F> {
+	/// Index an encoded seq or map.
+	///
+	/// Fails with `NoFit::DifferentType` if the value is neither a seq nor a
+	/// map, and with a `ReadError` if a subfield or key-value pair is
+	/// incomplete. In strict mode, it also fails if a subfield length is not
+	/// minimally encoded, or if a map contains the same key more than once.
+	///
+	/// The keys of a map are hashed by their
+	/// [canonical](crate::canonical) encoding, such that a key can be found
+	/// regardless of how it was encoded. Keys that can't be read or
+	/// canonicalized can't be looked up, but are still available through
+	/// [`get_entry`](EncodedIndex::get_entry).
+	pub fn new(argdata: EncodedArgdata<'d, F>) -> Result<Self, NotRead> {
+		let mut index = EncodedIndex::new_unkeyed(argdata)?;
+		if let Keys::Hashed(_) = index.keys {
+			let mut keys = HashMap::new();
+			for (i, &key) in index.elements.iter().step_by(2).enumerate() {
+				let key = if canonical::is_canonical(key, 0) {
+					Cow::Borrowed(key)
+				} else {
+					match canonical::encode(&*index.argdata_ref(key), &Default::default(), 0) {
+						Ok(c) => Cow::Owned(c),
+						Err(_) => continue,
+					}
+				};
+				keys.entry(key).or_insert(i);
+			}
+			index.keys = Keys::Hashed(keys);
+		}
+		Ok(index)
+	}
+
+	/// Index an encoded map of which the keys are sorted in canonical order:
+	/// by the bytes of the encoded keys, without duplicates.
+	///
+	/// Fails with `ReadError::UnsortedMap` if the keys are not sorted, and
+	/// otherwise in the same way as [`new`](EncodedIndex::new). Seqs are
+	/// indexed as with `new`.
+	///
+	/// Maps are written in this order by [`ArgdataWriter`](crate::ArgdataWriter)
+	/// with [`sort_maps`](crate::ArgdataWriter::sort_maps).
+	pub fn new_sorted(argdata: EncodedArgdata<'d, F>) -> Result<Self, NotRead> {
+		let mut index = EncodedIndex::new_unkeyed(argdata)?;
+		if let Keys::Hashed(_) = index.keys {
+			let keys: Vec<&[u8]> = index.elements.iter().step_by(2).cloned().collect();
+			if keys.windows(2).any(|w| w[0] >= w[1]) {
+				return Err(ReadError::UnsortedMap.into());
+			}
+			index.keys = Keys::Sorted;
+		}
+		Ok(index)
+	}
+
+	/// Index the elements, with an empty hash table for maps.
+	fn new_unkeyed(argdata: EncodedArgdata<'d, F>) -> Result<Self, NotRead> {
+		let is_map = match argdata.bytes().first() {
+			Some(6) => true,
+			Some(7) => false,
+			_ => return Err(NoFit::DifferentType.into()),
+		};
+		let mut elements = Vec::new();
+		let mut offset = 1;
+		while let (Some(subfield), n) = argdata.read_subfield(offset) {
+			elements.push(subfield?);
+			offset += n;
+		}
+		let strict = argdata.is_strict();
+		let keys = if is_map {
+			if elements.len() % 2 != 0 {
+				return Err(ReadError::InvalidKeyValuePair.into());
+			}
+			if strict {
+				let mut keys = HashSet::new();
+				if !elements.iter().step_by(2).all(|key| keys.insert(key)) {
+					return Err(ReadError::DuplicateMapKey.into());
+				}
+			}
+			Keys::Hashed(HashMap::new())
+		} else {
+			Keys::None
+		};
+		Ok(EncodedIndex {
+			elements,
+			keys,
+			convert_fd: argdata.into_convert_fd(),
+			strict,
+		})
+	}
+
+	/// Whether the indexed value is a map.
+	pub fn is_map(&self) -> bool {
+		match self.keys {
+			Keys::None => false,
+			Keys::Hashed(_) | Keys::Sorted => true,
+		}
+	}
+
+	/// Whether the indexed value is a map with sorted keys, indexed with
+	/// [`new_sorted`](EncodedIndex::new_sorted).
+	pub fn is_sorted(&self) -> bool {
+		match self.keys {
+			Keys::Sorted => true,
+			Keys::None | Keys::Hashed(_) => false,
+		}
+	}
+
+	/// The number of elements of the seq, or entries of the map.
+	pub fn len(&self) -> usize {
+		if self.is_map() {
+			self.elements.len() / 2
+		} else {
+			self.elements.len()
+		}
+	}
+
+	/// Whether the seq or map is empty.
+	pub fn is_empty(&self) -> bool {
+		self.elements.is_empty()
+	}
+
+	/// Get an element of a seq.
+	///
+	/// Returns None if the index is out of bounds, or if the indexed value is
+	/// a map. Use [`get_entry`](EncodedIndex::get_entry) for maps.
+	pub fn get(&self, index: usize) -> Option<ArgdataRef<'_, 'd>> {
+		if self.is_map() {
+			return None;
+		}
+		self.elements.get(index).map(|&e| self.argdata_ref(e))
+	}
+
+	/// Get a key-value pair of a map.
+	///
+	/// Returns None if the index is out of bounds, or if the indexed value is
+	/// a seq.
+	pub fn get_entry(&self, index: usize) -> Option<(ArgdataRef<'_, 'd>, ArgdataRef<'_, 'd>)> {
+		if !self.is_map() || index >= self.len() {
+			return None;
+		}
+		Some((
+			self.argdata_ref(self.elements[index * 2]),
+			self.argdata_ref(self.elements[index * 2 + 1]),
+		))
+	}
+
+	/// Get the value of the first entry of a map with the given string key.
+	///
+	/// Returns None if there is no such entry, or if the indexed value is a seq.
+	pub fn get_str_key(&self, key: &str) -> Option<ArgdataRef<'_, 'd>> {
+		let index = match &self.keys {
+			Keys::None => None,
+			Keys::Hashed(keys) => {
+				let mut encoded = Vec::with_capacity(key.len() + 2);
+				encoded.push(8);
+				encoded.extend_from_slice(key.as_bytes());
+				encoded.push(0);
+				keys.get(&encoded[..]).cloned()
+			}
+			Keys::Sorted => self.binary_search(|k| {
+				let encoded = [8].iter().chain(key.as_bytes()).chain(&[0]);
+				k.iter().cmp(encoded)
+			}),
+		}?;
+		Some(self.argdata_ref(self.elements[index * 2 + 1]))
+	}
+
+	/// Get the value of the first entry of a map with a key equal to the given
+	/// value, by comparing the canonically encoded keys.
+	///
+	/// Returns None if there is no such entry, if the indexed value is a seq,
+	/// or if `key` can't be canonicalized. A map indexed with
+	/// [`new_sorted`](EncodedIndex::new_sorted) is searched by its encoded
+	/// keys as they are, so only canonically encoded keys are found there.
+	pub fn lookup(&self, key: &dyn Argdata) -> Option<ArgdataRef<'_, 'd>> {
+		let encoded = canonical::encode(key, &Default::default(), 0).ok()?;
+		let index = match &self.keys {
+			Keys::None => None,
+			Keys::Hashed(keys) => keys.get(&encoded[..]).cloned(),
+			Keys::Sorted => self.binary_search(|k| k.cmp(&encoded[..])),
+		}?;
+		Some(self.argdata_ref(self.elements[index * 2 + 1]))
+	}
+
+	/// Find the entry of a sorted map for which `f` returns `Equal` when given
+	/// its encoded key.
+	fn binary_search(&self, f: impl Fn(&[u8]) -> Ordering) -> Option<usize> {
+		let mut low = 0;
+		let mut high = self.len();
+		while low < high {
+			let mid = low + (high - low) / 2;
+			match f(self.elements[mid * 2]) {
+				Ordering::Less => low = mid + 1,
+				Ordering::Greater => high = mid,
+				Ordering::Equal => return Some(mid),
+			}
+		}
+		None
+	}
+
+	fn argdata_ref(&self, bytes: &'d [u8]) -> ArgdataRef<'_, 'd> {
+		ArgdataRef::from_encoded(
+			crate::encoded_with_fds(bytes, &self.convert_fd as _).strict(self.strict),
+		)
+	}
+}
+
+impl<'d, F: fd::ConvertFd> std::fmt::Debug for EncodedIndex<'d, F> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("EncodedIndex")
+			.field("elements", &self.elements.len())
+			.field("is_map", &self.is_map())
+			.field("is_sorted", &self.is_sorted())
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{encoded, Argdata, ArgdataExt, OwnedArgdata};
+
+	fn encode(value: &OwnedArgdata) -> Vec<u8> {
+		let mut data = Vec::new();
+		value.argdata().serialize(&mut data, None).unwrap();
+		data
+	}
+
+	#[test]
+	fn index_seq_test() {
+		let data = encode(&OwnedArgdata::Seq((0..1000).map(Into::into).collect()));
+		let index = encoded(&data).index().unwrap();
+		assert!(!index.is_map());
+		assert_eq!(index.len(), 1000);
+		assert_eq!(index.get(0).unwrap().read_int(), Ok(0));
+		assert_eq!(index.get(999).unwrap().read_int(), Ok(999));
+		assert!(index.get(1000).is_none());
+		assert!(index.get_entry(0).is_none());
+		assert!(index.get_str_key("0").is_none());
+	}
+
+	#[test]
+	fn index_map_test() {
+		let mut entries: Vec<(OwnedArgdata, OwnedArgdata)> = (0..1000)
+			.map(|i| (format!("key{}", i).into(), i.into()))
+			.collect();
+		entries.push(("key5".into(), "duplicate".into()));
+		entries.push((5.into(), "int key".into()));
+		let data = encode(&OwnedArgdata::Map(entries));
+		let index = encoded(&data).index().unwrap();
+		assert!(index.is_map());
+		assert_eq!(index.len(), 1002);
+		assert_eq!(index.get_str_key("key5").unwrap().read_int(), Ok(5));
+		assert_eq!(index.get_str_key("key999").unwrap().read_int(), Ok(999));
+		assert!(index.get_str_key("key1000").is_none());
+		assert!(index.get_str_key("5").is_none());
+		let (k, v) = index.get_entry(1001).unwrap();
+		assert_eq!(k.read_int(), Ok(5));
+		assert_eq!(v.read_str(), Ok("int key"));
+		assert!(index.get_entry(1002).is_none());
+		assert!(index.get(0).is_none());
+	}
+
+	#[test]
+	fn index_lookup_test() {
+		let data = encode(&OwnedArgdata::Map(vec![
+			(5.into(), "int".into()),
+			(OwnedArgdata::Seq(vec![1.into(), "a".into()]), "seq".into()),
+			(OwnedArgdata::Float(1.5), "float".into()),
+			(5.into(), "duplicate".into()),
+		]));
+		let index = encoded(&data).index().unwrap();
+		assert_eq!(index.lookup(&crate::int(5)).unwrap().read_str(), Ok("int"));
+		assert_eq!(
+			index.lookup(&crate::float(1.5)).unwrap().read_str(),
+			Ok("float")
+		);
+		let seq = OwnedArgdata::Seq(vec![1.into(), "a".into()]);
+		assert_eq!(index.lookup(seq.argdata()).unwrap().read_str(), Ok("seq"));
+		assert!(index.lookup(&crate::int(6)).is_none());
+		assert!(index.lookup(&crate::str("5")).is_none());
+
+		// Keys with a non-minimal integer or subfield length are found by
+		// their canonical encoding.
+		let data = b"\x06\x83\x05\x00\x05\x82\x02\x01\x00\x84\x08ab\x00\x81\x02";
+		let index = encoded(data).index().unwrap();
+		assert_eq!(index.lookup(&crate::int(5)).unwrap().read_bool(), Ok(true));
+		assert_eq!(
+			index.lookup(&crate::str("ab")).unwrap().read_bool(),
+			Ok(false)
+		);
+		assert_eq!(index.get_str_key("ab").unwrap().read_bool(), Ok(false));
+	}
+
+	#[test]
+	fn index_error_test() {
+		let index = |data| encoded(data).index().map(|_| ());
+		assert_eq!(index(b"\x08a\x00"), Err(NoFit::DifferentType.into()));
+		assert_eq!(index(b""), Err(NoFit::DifferentType.into()));
+		assert_eq!(
+			index(b"\x07\x81\x02\x85"),
+			Err(ReadError::InvalidSubfield.into())
+		);
+		assert_eq!(
+			index(b"\x06\x81\x02"),
+			Err(ReadError::InvalidKeyValuePair.into())
+		);
+		assert_eq!(index(b"\x06"), Ok(()));
+		assert_eq!(
+			encoded(b"\x06\x82\x08\x00\x80\x82\x08\x00\x80")
+				.strict(true)
+				.index()
+				.map(|_| ()),
+			Err(ReadError::DuplicateMapKey.into())
+		);
+	}
+
+	#[test]
+	fn index_sorted_test() {
+		let mut w = crate::ArgdataWriter::new(Vec::new(), None).sort_maps(true);
+		w.begin_map().unwrap();
+		for i in (0..1000).rev() {
+			w.value(&crate::str(&format!("key{}", i))).unwrap();
+			w.value(&crate::int(i)).unwrap();
+			w.value(&crate::int(i)).unwrap();
+			w.value(&crate::bool(i % 2 == 0)).unwrap();
+		}
+		w.end().unwrap();
+		let data = w.finish();
+
+		let index = EncodedIndex::new_sorted(encoded(&data)).unwrap();
+		assert!(index.is_sorted());
+		assert_eq!(index.len(), 2000);
+		assert_eq!(index.get_str_key("key0").unwrap().read_int(), Ok(0));
+		assert_eq!(index.get_str_key("key999").unwrap().read_int(), Ok(999));
+		assert!(index.get_str_key("key1000").is_none());
+		assert_eq!(index.lookup(&crate::int(7)).unwrap().read_bool(), Ok(false));
+		assert_eq!(index.lookup(&crate::str("key7")).unwrap().read_int(), Ok(7));
+		assert!(index.lookup(&crate::int(1000)).is_none());
+
+		let index = encoded(&data).index().unwrap();
+		assert!(!index.is_sorted());
+		assert_eq!(index.lookup(&crate::int(8)).unwrap().read_bool(), Ok(true));
+		assert_eq!(index.lookup(&crate::str("key8")).unwrap().read_int(), Ok(8));
+
+		let unsorted = EncodedIndex::new_sorted(encoded(b"\x06\x81\x02\x80\x81\x02\x80"));
+		assert_eq!(unsorted.err(), Some(ReadError::UnsortedMap.into()));
+		let seq = EncodedIndex::new_sorted(encoded(b"\x07\x81\x02\x80")).unwrap();
+		assert!(!seq.is_sorted());
+	}
+}
@@ -10,12 +10,12 @@ use crate::TryFrom;
 /// An integer that fits in a `u64` or `i64` is directly stored in the object.
 /// Anything bigger is stored somewhere else (with lifetime `'a`) as a 2's complement big-endian
 /// integer in the form of an `[u8]`.
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct IntValue<'a> {
 	inner: Inner<'a>,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 enum Inner<'a> {
 	Unsigned(u64),
 	Signed(i64),   // For negative numbers only.
@@ -32,7 +32,7 @@ impl<'a> IntValue<'a> {
 		let sign = sign(data);
 
 		// Remove redundant leading zeros/ones.
-		while data.get(0) == Some(if sign { &0xFF } else { &0 })
+		while data.first() == Some(if sign { &0xFF } else { &0 })
 			&& (*data.get(1).unwrap_or(&0) >= 0x80) == sign
 		{
 			data = &data[1..]
@@ -76,6 +76,7 @@ impl<'a> IntValue<'a> {
 	/// available directly: Instead, the [`std::convert::TryFrom`] trait is
 	/// implemented for these types.
 	#[cfg(not(nightly))]
+	#[allow(clippy::result_unit_err)]
 	pub fn try_into<T: TryFrom<Self>>(self) -> Result<T, ()> {
 		TryFrom::try_from(self).map_err(|_| ())
 	}
@@ -177,8 +178,8 @@ impl<'a> PartialOrd<IntValue<'a>> for IntValue<'a> {
 
 // TODO: test
 impl<'a> Ord for IntValue<'a> {
+	#[rustfmt::skip]
 	fn cmp(&self, other: &Self) -> Ordering {
-		#[cfg_attr(rustfmt, rustfmt_skip)]
 		match (&self.inner, &other.inner) {
 			(&Inner::Unsigned(a), &Inner::Unsigned(b))             => a.cmp(&b),
 			(&Inner::Unsigned(_), &Inner::Big(b)) if !sign(b)      => Ordering::Less,
@@ -203,19 +204,105 @@ fn cmp_same_sign_bigint(a: &[u8], b: &[u8]) -> Ordering {
 
 impl<'a> fmt::Debug for IntValue<'a> {
 	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-		match self.inner {
-			Inner::Unsigned(v) => write!(f, "{}", v),
-			Inner::Signed(v) => write!(f, "{}", v),
-			Inner::Big(ref d) => {
-				// TODO: Print negative numbers correctly.
-				write!(f, "0x")?;
-				for byte in &d[..] {
-					write!(f, "{:02X}", byte)?;
-				}
-				Ok(())
+		fmt::Display::fmt(self, f)
+	}
+}
+
+/// Formats the integer in decimal, including integers that don't fit in 64 bits.
+impl<'a> fmt::Display for IntValue<'a> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+		let d = match self.inner {
+			Inner::Unsigned(v) => return fmt::Display::fmt(&v, f),
+			Inner::Signed(v) => return fmt::Display::fmt(&v, f),
+			Inner::Big(d) => d,
+		};
+
+		// Get the absolute value as big-endian unsigned integer.
+		let negative = sign(d);
+		let mut magnitude = d.to_vec();
+		if negative {
+			let mut carry = true;
+			for byte in magnitude.iter_mut().rev() {
+				let (v, c) = (!*byte).overflowing_add(carry as u8);
+				*byte = v;
+				carry = c;
+			}
+		}
+
+		// Repeatedly divide by 10^9 to get the decimal digits, nine at a time.
+		let mut chunks = Vec::new();
+		while magnitude.iter().any(|&b| b != 0) {
+			let mut rem = 0u64;
+			for byte in magnitude.iter_mut() {
+				let v = rem << 8 | *byte as u64;
+				*byte = (v / 1_000_000_000) as u8;
+				rem = v % 1_000_000_000;
 			}
+			chunks.push(rem as u32);
+		}
+
+		let mut chunks = chunks.iter().rev();
+		let mut s = chunks.next().map_or(String::from("0"), u32::to_string);
+		for chunk in chunks {
+			s += &format!("{:09}", chunk);
+		}
+		f.pad_integral(!negative, "", &s)
+	}
+}
+
+/// Parse a decimal integer of any size into a big-endian 2's complement
+/// integer, without redundant leading bytes.
+pub(crate) fn parse_decimal(s: &str) -> Option<Vec<u8>> {
+	let (negative, digits) = match s.strip_prefix('-') {
+		Some(digits) => (true, digits),
+		None => (false, s),
+	};
+	if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+
+	// Little-endian magnitude.
+	let mut magnitude: Vec<u8> = Vec::new();
+	for digit in digits.bytes() {
+		let mut carry = (digit - b'0') as u32;
+		for byte in magnitude.iter_mut() {
+			let v = *byte as u32 * 10 + carry;
+			*byte = v as u8;
+			carry = v >> 8;
+		}
+		if carry != 0 {
+			magnitude.push(carry as u8);
 		}
 	}
+
+	// Add a sign bit, and negate if necessary.
+	magnitude.push(0);
+	if negative {
+		let mut carry = true;
+		for byte in magnitude.iter_mut() {
+			let (v, c) = (!*byte).overflowing_add(carry as u8);
+			*byte = v;
+			carry = c;
+		}
+	}
+	magnitude.reverse();
+
+	let mut result = Vec::new();
+	IntValue::from_bigint(&magnitude)
+		.serialize(&mut result)
+		.unwrap();
+	Some(result)
+}
+
+/// Check if `data` is the shortest big-endian two's complement encoding of
+/// its value, which is empty for zero.
+pub(crate) fn is_minimal_bigint(data: &[u8]) -> bool {
+	match data {
+		[0] => false,
+		[0, next, ..] => *next >= 0x80,
+		[0xFF, next, ..] => *next < 0x80,
+		_ => true,
+	}
 }
 
 #[test]
@@ -235,22 +322,19 @@ fn test_serialize() {
 	assert_serialize(IntValue::from(-0x100), &[0xFF, 0x00]);
 	assert_serialize(IntValue::from(1000), &[0x03, 0xE8]);
 	assert_serialize(IntValue::from(-1000), &[0xFC, 0x18]);
+	assert_serialize(IntValue::from(u32::MAX), &[0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
 	assert_serialize(
-		IntValue::from(u32::max_value()),
-		&[0x00, 0xFF, 0xFF, 0xFF, 0xFF],
-	);
-	assert_serialize(
-		IntValue::from(u64::max_value()),
+		IntValue::from(u64::MAX),
 		&[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
 	);
-	assert_serialize(IntValue::from(i32::max_value()), &[0x7F, 0xFF, 0xFF, 0xFF]);
+	assert_serialize(IntValue::from(i32::MAX), &[0x7F, 0xFF, 0xFF, 0xFF]);
 	assert_serialize(
-		IntValue::from(i64::max_value()),
+		IntValue::from(i64::MAX),
 		&[0x7F, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
 	);
-	assert_serialize(IntValue::from(i32::min_value()), &[0x80, 0x00, 0x00, 0x00]);
+	assert_serialize(IntValue::from(i32::MIN), &[0x80, 0x00, 0x00, 0x00]);
 	assert_serialize(
-		IntValue::from(i64::min_value()),
+		IntValue::from(i64::MIN),
 		&[0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
 	);
 	assert_serialize(IntValue::from_bigint(&[0, 0]), &[]);
@@ -261,6 +345,31 @@ fn test_serialize() {
 	assert_serialize(IntValue::from_bigint(&[0x00, 0xFF]), &[0x00, 0xFF]);
 }
 
+#[test]
+fn test_decimal() {
+	let roundtrip = |s: &str| {
+		let data = parse_decimal(s).unwrap();
+		IntValue::from_bigint(&data).to_string()
+	};
+	assert_eq!(roundtrip("0"), "0");
+	assert_eq!(roundtrip("-0"), "0");
+	assert_eq!(roundtrip("007"), "7");
+	assert_eq!(roundtrip("-128"), "-128");
+	assert_eq!(roundtrip("18446744073709551615"), "18446744073709551615");
+	assert_eq!(roundtrip("18446744073709551616"), "18446744073709551616");
+	assert_eq!(roundtrip("-9223372036854775809"), "-9223372036854775809");
+	assert_eq!(
+		roundtrip("-123456789012345678901234567890"),
+		"-123456789012345678901234567890"
+	);
+	assert_eq!(parse_decimal("128"), Some(vec![0x00, 0x80]));
+	assert_eq!(parse_decimal("-129"), Some(vec![0xFF, 0x7F]));
+	assert_eq!(parse_decimal(""), None);
+	assert_eq!(parse_decimal("-"), None);
+	assert_eq!(parse_decimal("1.0"), None);
+	assert_eq!(format!("{:>5}", IntValue::from(-3)), "   -3");
+}
+
 // TODO: update tests
 
 /*
@@ -274,9 +383,9 @@ fn test_64() {
 	assert_eq!(Int::from(5i64).get_u64(), Some(5));
 	assert_eq!(Int::from(-1).get_u64(), None);
 	assert_eq!(Int::from(-1).get_i64(), Some(-1));
-	assert_eq!(Int::from(u64::max_value()).get_i64(), None);
-	assert_eq!(Int::from(u64::max_value()).get_u64(), Some(u64::max_value()));
-	assert_eq!(Int::from(i64::max_value()).get_u64(), Some(i64::max_value() as u64));
+	assert_eq!(Int::from(u64::MAX).get_i64(), None);
+	assert_eq!(Int::from(u64::MAX).get_u64(), Some(u64::MAX));
+	assert_eq!(Int::from(i64::MAX).get_u64(), Some(i64::MAX as u64));
 }
 
 #[test]
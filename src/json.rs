@@ -0,0 +1,626 @@
+//! Types that JSON doesn't have are represented as configured in [`Options`].
+//! Tagged representations (objects with a single `"binary"`, `"timestamp"`,
+//! or `"fd"` key) and RFC 3339 strings are converted back when parsing JSON,
+//! if that type is configured to be written that way. (Tagged timestamps are
+//! always converted back.) All other representations are parsed as the JSON
+//! values they are.
+//!
+//! Integers in JSON are parsed as argdata integers of any size, and all other
+//! numbers as floats.
+
+use crate::{
+	base64, fd, intvalue,
+	parse::{self, invalid_data, Input, Parse, MAX_DEPTH},
+	rfc3339, Argdata, ArgdataExt, IntValue, OwnedArgdata, ParseError, ReadError, Timespec, Value,
+};
+use std::io;
+
+/// How to represent argdata types that JSON doesn't have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options {
+	pub binary: BinaryFormat,
+	pub timestamp: TimestampFormat,
+	pub big_int: BigIntFormat,
+	pub map_keys: MapKeyFormat,
+	pub fd: FdFormat,
+}
+
+/// How to represent binary blobs in JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryFormat {
+	/// A base64 string.
+	Base64,
+	/// An object with a base64 string under the key `"binary"`: `{"binary": "AQI="}`.
+	Tagged,
+	/// An array of integers: `[1, 2]`.
+	IntArray,
+}
+
+/// How to represent timestamps in JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFormat {
+	/// An RFC 3339 string in UTC: `"2018-06-01T12:34:56.789Z"`.
+	///
+	/// Timestamps outside of the years 0 to 9999 are written as `Tagged`.
+	Rfc3339,
+	/// An object with the seconds and nanoseconds since the unix epoch under
+	/// the key `"timestamp"`: `{"timestamp": {"sec": 1527856496, "nsec": 789000000}}`.
+	Tagged,
+}
+
+/// How to represent integers that don't fit in 64 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BigIntFormat {
+	/// A number, which many JSON parsers will not read exactly.
+	Number,
+	/// A string containing the decimal number.
+	String,
+}
+
+/// How to represent maps that have keys other than strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapKeyFormat {
+	/// An object, with the JSON representation of the non-string keys as string keys.
+	Stringify,
+	/// An array of key-value pairs: `[[1, "one"], [2, "two"]]`.
+	Entries,
+}
+
+/// How to represent file descriptors in JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdFormat {
+	/// An object with the raw encoded number under the key `"fd"`: `{"fd": 3}`.
+	Tagged,
+	/// The raw encoded number, which is parsed back as an integer.
+	Number,
+}
+
+/// Represents binary blobs, timestamps and file descriptors as tagged objects, and all other
+/// values as numbers and objects, to lose as little information as possible.
+impl Default for Options {
+	fn default() -> Self {
+		Options {
+			binary: BinaryFormat::Tagged,
+			timestamp: TimestampFormat::Tagged,
+			big_int: BigIntFormat::Number,
+			map_keys: MapKeyFormat::Stringify,
+			fd: FdFormat::Tagged,
+		}
+	}
+}
+
+/// Write an argdata value as JSON.
+///
+/// Floats that JSON can't represent are written as the strings `"NaN"`,
+/// `"Infinity"` and `"-Infinity"`.
+///
+/// Errors while reading the argdata are returned as an `io::Error` of kind
+/// `InvalidData` containing the [`ReadError`]. Maps and seqs nested more than
+/// 256 levels deep give a `ReadError::NestedTooDeeply`.
+pub fn to_writer(
+	writer: &mut dyn io::Write,
+	value: &dyn Argdata,
+	options: &Options,
+) -> io::Result<()> {
+	write_value(writer, value, options, 0)
+}
+
+/// Convert an argdata value to a JSON string.
+///
+/// See [`to_writer`].
+pub fn to_string(value: &dyn Argdata, options: &Options) -> Result<String, ReadError> {
+	let buf = parse::to_vec(|buf| write_value(buf, value, options, 0))?;
+	Ok(String::from_utf8(buf).unwrap())
+}
+
+/// Parse a JSON text into an argdata value.
+pub fn from_str(s: &str, options: &Options) -> Result<OwnedArgdata, ParseError> {
+	let mut parser = Parser {
+		input: Input::new(s.as_bytes()),
+		options,
+	};
+	let value = parser.value(0)?;
+	parser.whitespace();
+	if parser.input.pos != s.len() {
+		return Err(parser.input.error("Trailing characters after JSON value"));
+	}
+	Ok(value)
+}
+
+/// Write a value that is nested `depth` levels deep.
+fn write_value(
+	w: &mut dyn io::Write,
+	value: &dyn Argdata,
+	options: &Options,
+	depth: usize,
+) -> io::Result<()> {
+	match parse::read_nested(value, depth)? {
+		Value::Null => write!(w, "null"),
+		Value::Binary(v) => match options.binary {
+			BinaryFormat::Base64 => write!(w, "\"{}\"", base64::encode(v)),
+			BinaryFormat::Tagged => write!(w, "{{\"binary\":\"{}\"}}", base64::encode(v)),
+			BinaryFormat::IntArray => {
+				write!(w, "[")?;
+				for (i, byte) in v.iter().enumerate() {
+					if i != 0 {
+						write!(w, ",")?;
+					}
+					write!(w, "{}", byte)?;
+				}
+				write!(w, "]")
+			}
+		},
+		Value::Bool(v) => write!(w, "{}", v),
+		Value::Fd(v) => match options.fd {
+			FdFormat::Tagged => write!(w, "{{\"fd\":{}}}", v.raw_encoded_number()),
+			FdFormat::Number => write!(w, "{}", v.raw_encoded_number()),
+		},
+		Value::Float(v) if v.is_nan() => write!(w, "\"NaN\""),
+		Value::Float(v) if v.is_infinite() && v > 0.0 => write!(w, "\"Infinity\""),
+		Value::Float(v) if v.is_infinite() => write!(w, "\"-Infinity\""),
+		Value::Float(v) => write!(w, "{:?}", v),
+		Value::Int(v) => write_int(w, v, options),
+		Value::Str(v) => write_str(w, v.as_str().map_err(|e| invalid_data(e.into()))?),
+		Value::Timestamp(v) => write_timestamp(w, v, options),
+		Value::Map(it) => {
+			let mut as_object = true;
+			if options.map_keys == MapKeyFormat::Entries {
+				for entry in it {
+					let (key, _) = entry.map_err(invalid_data)?;
+					as_object &= key.read_str().is_ok();
+				}
+			}
+			let entries = value.read_map().unwrap();
+			if as_object {
+				write!(w, "{{")?;
+				for (i, entry) in entries.enumerate() {
+					let (key, val) = entry.map_err(invalid_data)?;
+					if i != 0 {
+						write!(w, ",")?;
+					}
+					match key.read_str() {
+						Ok(key) => write_str(w, key)?,
+						Err(_) => {
+							let mut buf = Vec::new();
+							write_value(&mut buf, &*key, options, depth + 1)?;
+							write_str(w, std::str::from_utf8(&buf).unwrap())?;
+						}
+					}
+					write!(w, ":")?;
+					write_value(w, &*val, options, depth + 1)?;
+				}
+				write!(w, "}}")
+			} else {
+				write!(w, "[")?;
+				for (i, entry) in entries.enumerate() {
+					let (key, val) = entry.map_err(invalid_data)?;
+					if i != 0 {
+						write!(w, ",")?;
+					}
+					write!(w, "[")?;
+					write_value(w, &*key, options, depth + 1)?;
+					write!(w, ",")?;
+					write_value(w, &*val, options, depth + 1)?;
+					write!(w, "]")?;
+				}
+				write!(w, "]")
+			}
+		}
+		Value::Seq(it) => {
+			write!(w, "[")?;
+			for (i, val) in it.enumerate() {
+				if i != 0 {
+					write!(w, ",")?;
+				}
+				write_value(w, &*val.map_err(invalid_data)?, options, depth + 1)?;
+			}
+			write!(w, "]")
+		}
+	}
+}
+
+fn write_int(w: &mut dyn io::Write, v: IntValue, options: &Options) -> io::Result<()> {
+	let fits = v.try_into::<i64>().is_ok() || v.try_into::<u64>().is_ok();
+	if fits || options.big_int == BigIntFormat::Number {
+		write!(w, "{}", v)
+	} else {
+		write!(w, "\"{}\"", v)
+	}
+}
+
+fn write_timestamp(w: &mut dyn io::Write, t: Timespec, options: &Options) -> io::Result<()> {
+	if options.timestamp == TimestampFormat::Rfc3339 && rfc3339::in_range(t) {
+		return write!(w, "\"{}\"", rfc3339::format(t, false));
+	}
+	write!(
+		w,
+		"{{\"timestamp\":{{\"sec\":{},\"nsec\":{}}}}}",
+		t.sec, t.nsec
+	)
+}
+
+fn write_str(w: &mut dyn io::Write, s: &str) -> io::Result<()> {
+	write!(w, "\"")?;
+	let mut start = 0;
+	for (i, c) in s.char_indices() {
+		let escape = match c {
+			'"' => "\\\"",
+			'\\' => "\\\\",
+			'\n' => "\\n",
+			'\r' => "\\r",
+			'\t' => "\\t",
+			c if (c as u32) < 0x20 => "",
+			_ => continue,
+		};
+		w.write_all(&s.as_bytes()[start..i])?;
+		if escape.is_empty() {
+			write!(w, "\\u{:04x}", c as u32)?;
+		} else {
+			write!(w, "{}", escape)?;
+		}
+		start = i + c.len_utf8();
+	}
+	w.write_all(&s.as_bytes()[start..])?;
+	write!(w, "\"")
+}
+
+struct Parser<'a> {
+	input: Input<'a>,
+	options: &'a Options,
+}
+
+impl<'a> Parse<'a> for Parser<'a> {
+	fn input(&mut self) -> &mut Input<'a> {
+		&mut self.input
+	}
+
+	fn whitespace(&mut self) {
+		while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.input.data.get(self.input.pos) {
+			self.input.pos += 1;
+		}
+	}
+}
+
+impl<'a> Parser<'a> {
+	fn keyword(&mut self, word: &str, value: OwnedArgdata) -> Result<OwnedArgdata, ParseError> {
+		if self.input.data[self.input.pos..].starts_with(word.as_bytes()) {
+			self.input.pos += word.len();
+			Ok(value)
+		} else {
+			Err(self.input.error("Invalid JSON value"))
+		}
+	}
+
+	fn value(&mut self, depth: usize) -> Result<OwnedArgdata, ParseError> {
+		if depth > MAX_DEPTH {
+			return Err(self.input.error("JSON nested too deeply"));
+		}
+		self.whitespace();
+		match self.input.data.get(self.input.pos) {
+			None => Err(self.input.error("Unexpected end of JSON")),
+			Some(b'n') => self.keyword("null", OwnedArgdata::Null),
+			Some(b't') => self.keyword("true", OwnedArgdata::Bool(true)),
+			Some(b'f') => self.keyword("false", OwnedArgdata::Bool(false)),
+			Some(b'"') => {
+				let s = self.string()?;
+				if self.options.timestamp == TimestampFormat::Rfc3339 {
+					if let Some(t) = rfc3339::parse(&s) {
+						return Ok(OwnedArgdata::Timestamp(t));
+					}
+				}
+				Ok(OwnedArgdata::Str(s))
+			}
+			Some(b'[') => {
+				self.input.pos += 1;
+				let mut elements = Vec::new();
+				if !self.eat(b']') {
+					loop {
+						elements.push(self.value(depth + 1)?);
+						if self.eat(b']') {
+							break;
+						}
+						self.expect(b',', "Expected ',' or ']'")?;
+					}
+				}
+				Ok(OwnedArgdata::Seq(elements))
+			}
+			Some(b'{') => {
+				self.input.pos += 1;
+				let mut entries = Vec::new();
+				if !self.eat(b'}') {
+					loop {
+						self.whitespace();
+						if self.input.data.get(self.input.pos) != Some(&b'"') {
+							return Err(self.input.error("Expected string key"));
+						}
+						let key = self.string()?;
+						self.expect(b':', "Expected ':'")?;
+						entries.push((OwnedArgdata::Str(key), self.value(depth + 1)?));
+						if self.eat(b'}') {
+							break;
+						}
+						self.expect(b',', "Expected ',' or '}'")?;
+					}
+				}
+				Ok(self.untag(entries))
+			}
+			Some(_) => self.number(),
+		}
+	}
+
+	/// Convert tagged objects back to the value they represent.
+	fn untag(&self, entries: Vec<(OwnedArgdata, OwnedArgdata)>) -> OwnedArgdata {
+		if let [(OwnedArgdata::Str(key), value)] = &entries[..] {
+			match (&key[..], value) {
+				("fd", value) if self.options.fd == FdFormat::Tagged => {
+					if let Ok(n) = ArgdataExt::read_int::<u32>(value.argdata()) {
+						return OwnedArgdata::Fd(fd::Fd(n as i32));
+					}
+				}
+				("binary", OwnedArgdata::Str(s)) if self.options.binary == BinaryFormat::Tagged => {
+					if let Some(data) = base64::decode(s) {
+						return OwnedArgdata::Binary(data);
+					}
+				}
+				("timestamp", OwnedArgdata::Map(fields)) => {
+					if let [(OwnedArgdata::Str(k1), sec), (OwnedArgdata::Str(k2), nsec)] =
+						&fields[..]
+					{
+						let sec = ArgdataExt::read_int::<i64>(sec.argdata());
+						let nsec = ArgdataExt::read_int::<u32>(nsec.argdata());
+						if let ("sec", "nsec", Ok(sec), Ok(nsec)) = (&k1[..], &k2[..], sec, nsec) {
+							if nsec < 1_000_000_000 {
+								return OwnedArgdata::Timestamp(Timespec { sec, nsec });
+							}
+						}
+					}
+				}
+				_ => {}
+			}
+		}
+		OwnedArgdata::Map(entries)
+	}
+
+	fn hex4(&mut self) -> Result<u32, ParseError> {
+		let digits = self
+			.input
+			.data
+			.get(self.input.pos..self.input.pos + 4)
+			.filter(|d| d.iter().all(u8::is_ascii_hexdigit))
+			.map(|d| u32::from_str_radix(std::str::from_utf8(d).unwrap(), 16).unwrap())
+			.ok_or_else(|| self.input.error("Invalid \\u escape"))?;
+		self.input.pos += 4;
+		Ok(digits)
+	}
+
+	fn string(&mut self) -> Result<String, ParseError> {
+		self.input.pos += 1; // Opening quote.
+		let mut s = Vec::new();
+		loop {
+			match self.input.data.get(self.input.pos) {
+				None => return Err(self.input.error("Unterminated string")),
+				Some(b'"') => break,
+				Some(b'\\') => {
+					self.input.pos += 1;
+					let c = match self.input.data.get(self.input.pos) {
+						Some(b'"') => '"',
+						Some(b'\\') => '\\',
+						Some(b'/') => '/',
+						Some(b'b') => '\x08',
+						Some(b'f') => '\x0C',
+						Some(b'n') => '\n',
+						Some(b'r') => '\r',
+						Some(b't') => '\t',
+						Some(b'u') => {
+							self.input.pos += 1;
+							let mut c = self.hex4()?;
+							if (0xD800..0xDC00).contains(&c)
+								&& self.input.data[self.input.pos..].starts_with(b"\\u")
+							{
+								self.input.pos += 2;
+								let low = self.hex4()?;
+								if !(0xDC00..0xE000).contains(&low) {
+									return Err(self.input.error("Invalid surrogate pair"));
+								}
+								c = 0x10000 + ((c - 0xD800) << 10) + (low - 0xDC00);
+							}
+							self.input.pos -= 1;
+							std::char::from_u32(c)
+								.ok_or_else(|| self.input.error("Invalid surrogate pair"))?
+						}
+						_ => return Err(self.input.error("Invalid escape sequence")),
+					};
+					s.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+				}
+				Some(&c) if c < 0x20 => {
+					return Err(self.input.error("Control character in string"))
+				}
+				Some(&c) => s.push(c),
+			}
+			self.input.pos += 1;
+		}
+		self.input.pos += 1; // Closing quote.
+		Ok(String::from_utf8(s).unwrap())
+	}
+
+	fn number(&mut self) -> Result<OwnedArgdata, ParseError> {
+		let start = self.input.pos;
+		let mut is_float = false;
+		while let Some(&c) = self.input.data.get(self.input.pos) {
+			match c {
+				b'0'..=b'9' | b'-' => {}
+				b'.' | b'e' | b'E' | b'+' => is_float = true,
+				_ => break,
+			}
+			self.input.pos += 1;
+		}
+		let text = std::str::from_utf8(&self.input.data[start..self.input.pos]).unwrap();
+		let valid = {
+			let t = text.strip_prefix('-').unwrap_or(text);
+			let int_part = t.split(['.', 'e', 'E']).next().unwrap();
+			!int_part.is_empty()
+				&& (int_part == "0" || !int_part.starts_with('0'))
+				&& !text.ends_with('.')
+				&& !text.contains(".e")
+				&& !text.contains(".E")
+		};
+		let result = if !valid {
+			None
+		} else if is_float {
+			text.parse().ok().map(OwnedArgdata::Float)
+		} else {
+			intvalue::parse_decimal(text).map(OwnedArgdata::Int)
+		};
+		result.ok_or(ParseError::new(start, "Invalid JSON value"))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn sample() -> OwnedArgdata {
+		OwnedArgdata::Map(vec![
+			("str".into(), "a\"b\\c\n\u{1}é".into()),
+			("int".into(), (-5).into()),
+			(
+				"big".into(),
+				OwnedArgdata::Int(vec![1, 0, 0, 0, 0, 0, 0, 0, 0]),
+			),
+			("float".into(), 0.5.into()),
+			("bool".into(), true.into()),
+			("null".into(), OwnedArgdata::Null),
+			("bin".into(), b"\x01\x02"[..].into()),
+			(
+				"time".into(),
+				Timespec {
+					sec: 1_528_000_000,
+					nsec: 500_000_000,
+				}
+				.into(),
+			),
+			("fd".into(), fd::Fd(3).into()),
+			(
+				"seq".into(),
+				OwnedArgdata::Seq(vec![1.into(), OwnedArgdata::Seq(vec![])]),
+			),
+		])
+	}
+
+	#[test]
+	fn json_roundtrip_test() {
+		let options = Options::default();
+		let json = to_string(sample().argdata(), &options).unwrap();
+		assert_eq!(
+			json,
+			"{\"str\":\"a\\\"b\\\\c\\n\\u0001é\",\"int\":-5,\"big\":18446744073709551616,\
+			 \"float\":0.5,\"bool\":true,\"null\":null,\"bin\":{\"binary\":\"AQI=\"},\
+			 \"time\":{\"timestamp\":{\"sec\":1528000000,\"nsec\":500000000}},\
+			 \"fd\":{\"fd\":3},\"seq\":[1,[]]}"
+		);
+		let parsed = from_str(&json, &options).unwrap();
+		assert_eq!(parsed, sample());
+	}
+
+	#[test]
+	fn json_options_test() {
+		let options = Options {
+			binary: BinaryFormat::IntArray,
+			timestamp: TimestampFormat::Rfc3339,
+			big_int: BigIntFormat::String,
+			map_keys: MapKeyFormat::Entries,
+			fd: FdFormat::Number,
+		};
+		let value = OwnedArgdata::Seq(vec![
+			b"\x01\x02"[..].into(),
+			Timespec { sec: 0, nsec: 0 }.into(),
+			OwnedArgdata::Int(vec![0xFE, 0, 0, 0, 0, 0, 0, 0, 0]),
+			OwnedArgdata::Map(vec![
+				(1.into(), "one".into()),
+				("x".into(), OwnedArgdata::Null),
+			]),
+			f64::NAN.into(),
+			fd::Fd(3).into(),
+		]);
+		assert_eq!(
+			to_string(value.argdata(), &options).unwrap(),
+			"[[1,2],\"1970-01-01T00:00:00Z\",\"-36893488147419103232\",\
+			 [[1,\"one\"],[\"x\",null]],\"NaN\",3]"
+		);
+		assert_eq!(
+			format!("{:?}", from_str("{\"fd\":3}", &options).unwrap()),
+			"{\"fd\": 3}"
+		);
+		assert_eq!(
+			format!(
+				"{:?}",
+				from_str("\"1970-01-01T00:00:00Z\"", &options).unwrap()
+			),
+			"timestamp(0, 0)"
+		);
+
+		let options = Options {
+			binary: BinaryFormat::Base64,
+			..Options::default()
+		};
+		assert_eq!(
+			to_string(value.argdata(), &options).unwrap(),
+			"[\"AQI=\",{\"timestamp\":{\"sec\":0,\"nsec\":0}},-36893488147419103232,\
+			 {\"1\":\"one\",\"x\":null},\"NaN\",{\"fd\":3}]"
+		);
+	}
+
+	#[test]
+	fn json_parse_test() {
+		let options = Options::default();
+		let parse = |s| from_str(s, &options).map(|v| format!("{:?}", v));
+		assert_eq!(
+			parse(" [ 1 , -0, 1.5e3, \"\\ud83d\\ude00\\/\" , {} ] ").unwrap(),
+			"[1, 0, 1500.0, \"😀/\", {}]"
+		);
+		assert_eq!(parse("{\"fd\": -1}").unwrap(), "{\"fd\": -1}");
+		assert_eq!(parse("{\"binary\": \"!\"}").unwrap(), "{\"binary\": \"!\"}");
+		assert_eq!(
+			parse("{\"binary\": \"QQ======\"}").unwrap(),
+			"{\"binary\": \"QQ======\"}"
+		);
+
+		let error = |s| from_str(s, &options).unwrap_err().to_string();
+		assert_eq!(error(""), "Unexpected end of JSON at byte 0");
+		assert_eq!(error("[1,]"), "Invalid JSON value at byte 3");
+		assert_eq!(error("[1 2]"), "Expected ',' or ']' at byte 3");
+		assert_eq!(error("01"), "Invalid JSON value at byte 0");
+		assert_eq!(error("1."), "Invalid JSON value at byte 0");
+		assert_eq!(error("{1: 2}"), "Expected string key at byte 1");
+		assert_eq!(error("\"\\x\""), "Invalid escape sequence at byte 2");
+		assert_eq!(error("\"\\u+041\""), "Invalid \\u escape at byte 3");
+		assert_eq!(error("nul"), "Invalid JSON value at byte 0");
+		assert_eq!(
+			error("1 1"),
+			"Trailing characters after JSON value at byte 2"
+		);
+		assert_eq!(
+			error(&"[".repeat(1000)),
+			"JSON nested too deeply at byte 257"
+		);
+	}
+
+	#[test]
+	fn json_deep_nesting_test() {
+		let options = Options::default();
+		let data = parse::nested_seqs(MAX_DEPTH + 1);
+		let json = to_string(&crate::encoded(&data), &options).unwrap();
+		let brackets = |b: &str| b.repeat(MAX_DEPTH + 1);
+		assert_eq!(json, brackets("[") + "null" + &brackets("]"));
+		let data = parse::nested_seqs(MAX_DEPTH + 2);
+		assert_eq!(
+			to_string(&crate::encoded(&data), &options),
+			Err(ReadError::NestedTooDeeply)
+		);
+		let data = parse::nested_seqs(200_000);
+		assert_eq!(
+			to_string(&crate::encoded(&data), &options),
+			Err(ReadError::NestedTooDeeply)
+		);
+	}
+}
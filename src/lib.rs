@@ -14,12 +14,56 @@ use std::io;
 /// Access to the program environment.
 pub mod env;
 
+/// Re-encoding argdata in its canonical form.
+pub mod canonical;
+
+/// Finding the differences between two argdata values.
+pub mod diff;
+
 /// Traits used for `Seq` and `Map` value implementations.
 pub mod container_traits;
 
 /// All the things related to file descriptors.
 pub mod fd;
 
+/// Incremental reading of argdata from an `io::Read` stream.
+pub mod stream;
+
+/// A human-readable text notation for argdata, as used by `Debug`.
+pub mod text;
+
+/// Sending and receiving argdata with file descriptors over Unix domain sockets.
+#[cfg(unix)]
+pub mod unix;
+
+/// Starting child processes with argdata.
+#[cfg(target_os = "linux")]
+pub mod process;
+
+/// Serialization of any `serde::Serialize` value as argdata.
+#[cfg(feature = "serde")]
+pub mod ser;
+
+/// Deserialization of any `serde::Deserialize` value from argdata.
+#[cfg(feature = "serde")]
+pub mod de;
+
+/// Conversion between argdata and JSON.
+#[cfg(feature = "json")]
+pub mod json;
+
+/// Conversion between argdata and YAML, in the style of the C implementation.
+#[cfg(feature = "yaml")]
+pub mod yaml;
+
+/// Conversion between argdata and CBOR.
+#[cfg(feature = "cbor")]
+pub mod cbor;
+
+/// Conversion between argdata and MessagePack.
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+
 #[cfg(nightly)]
 use std::convert::TryFrom;
 
@@ -29,39 +73,61 @@ mod try_from;
 #[cfg(not(nightly))]
 use crate::try_from::TryFrom;
 
+#[cfg(any(feature = "json", feature = "yaml"))]
+mod base64;
+mod convert;
 mod debug;
+mod eq;
 mod errors;
+mod index;
 mod intvalue;
 mod mapiterator;
+mod ord;
+mod parse;
+mod path;
 mod reference;
+#[cfg(any(feature = "json", feature = "yaml"))]
+mod rfc3339;
 mod seqiterator;
 mod strvalue;
 mod subfield;
 mod timespec;
+mod validate;
 mod value;
+mod writer;
 
-pub use crate::errors::{NoFit, NotRead, ReadError};
+pub use crate::convert::{FromArgdata, FromArgdataError, ToArgdata};
+pub use crate::eq::try_eq;
+pub use crate::errors::{NoFit, NotRead, ParseError, ReadError};
+pub use crate::index::EncodedIndex;
 pub use crate::intvalue::IntValue;
 pub use crate::mapiterator::{MapIterable, MapIterator};
+pub use crate::ord::{try_cmp, OrdArgdata};
+pub use crate::path::{get_path, PathError};
 pub use crate::reference::ArgdataRef;
 pub use crate::seqiterator::{SeqIterable, SeqIterator};
 pub use crate::strvalue::StrValue;
 pub use crate::timespec::Timespec;
+pub use crate::validate::ValidationError;
 pub use crate::value::{Type, Value};
+pub use crate::writer::ArgdataWriter;
+
+#[cfg(feature = "derive")]
+pub use argdata_derive::{FromArgdata, ToArgdata};
 
 #[path = "values/mod.rs"]
 mod values_;
 
 pub use crate::values_::{
 	bigint, binary, bool, encoded, encoded_fd, encoded_with_fds, float, int, invalid_fd, map, null,
-	process_fd, seq, str, timestamp,
+	process_fd, seq, str, timestamp, OwnedArgdata,
 };
 
 /// Implementations of specific `Argdata` types.
 /// Use the functions in the root of this crate to create them.
 pub mod values {
 	pub use crate::values_::{
-		BigInt, Binary, Bool, EncodedArgdata, Float, Int, Map, Null, Seq, Str, Timestamp,
+		BigInt, Binary, Bool, EncodedArgdata, Float, Int, Map, Null, Owned, Seq, Str, Timestamp,
 	};
 }
 
@@ -225,6 +291,14 @@ pub trait Argdata<'d>: Sync {
 
 	/// The number of bytes that `self.serialize()` will write.
 	fn serialized_length(&self) -> usize;
+
+	/// The encoded data this value reads from, if it is backed by encoded
+	/// argdata. (Including any file descriptors as their raw encoded numbers.)
+	///
+	/// This allows comparing values without reading them. See [`try_eq`].
+	fn encoded_bytes(&self) -> Option<&'d [u8]> {
+		None
+	}
 }
 
 /// Extra methods for `Argdata` values.
@@ -259,7 +333,6 @@ where
 }
 
 // TODO:
-// owning datastructures
 // Fix/update/make Tests
 
 #[allow(dead_code)]
@@ -281,7 +354,5 @@ fn example<'d>(ad: &dyn Argdata<'d>) {
 		}
 	}
 
-	drop(sock_fd);
-	drop(read_fd);
-	drop(message);
+	let _ = (sock_fd, read_fd, message);
 }
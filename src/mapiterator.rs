@@ -1,4 +1,4 @@
-use crate::{ArgdataRef, ReadError};
+use crate::{ArgdataExt, ArgdataRef, NoFit, NotRead, ReadError};
 
 /// An iterator, iterating over an argdata map.
 #[derive(Copy, Clone)]
@@ -34,6 +34,34 @@ pub trait MapIterable<'d>: Sync {
 	) -> Option<Result<(ArgdataRef<'a, 'd>, ArgdataRef<'a, 'd>), ReadError>>
 	where
 		'd: 'a;
+
+	/// Find the value of the first key-value pair with the given string key,
+	/// starting at `cookie`.
+	///
+	/// **Don't use this method directly.** Use
+	/// [`MapIterator::get_str_key`] instead.
+	///
+	/// # For implementors
+	///
+	/// The provided implementation uses `iter_map_next`. Override it if
+	/// keys can be compared more efficiently. Keys that are not valid strings
+	/// never match, and should not result in an error.
+	fn iter_map_get_str<'a>(
+		&'a self,
+		mut cookie: usize,
+		key: &str,
+	) -> Result<Option<ArgdataRef<'a, 'd>>, ReadError>
+	where
+		'd: 'a,
+	{
+		while let Some(entry) = self.iter_map_next(&mut cookie) {
+			let (k, v) = entry?;
+			if k.read_str() == Ok(key) {
+				return Ok(Some(v));
+			}
+		}
+		Ok(None)
+	}
 }
 
 impl<'a, 'd: 'a> MapIterator<'a, 'd> {
@@ -46,6 +74,42 @@ impl<'a, 'd: 'a> MapIterator<'a, 'd> {
 	pub fn new(map: &'a (dyn MapIterable<'d> + 'a), cookie: usize) -> Self {
 		MapIterator { map, cookie }
 	}
+
+	/// Find the value of the first remaining key-value pair with the given
+	/// string key.
+	///
+	/// Keys that are not valid strings are skipped. This does not advance the
+	/// iterator.
+	pub fn get_str_key(&self, key: &str) -> Result<Option<ArgdataRef<'a, 'd>>, ReadError> {
+		self.map.iter_map_get_str(self.cookie, key)
+	}
+
+	/// An iterator over the remaining keys.
+	pub fn keys(self) -> impl Iterator<Item = Result<ArgdataRef<'a, 'd>, ReadError>> {
+		self.map(|entry| entry.map(|(k, _)| k))
+	}
+
+	/// An iterator over the remaining values.
+	pub fn values(self) -> impl Iterator<Item = Result<ArgdataRef<'a, 'd>, ReadError>> {
+		self.map(|entry| entry.map(|(_, v)| v))
+	}
+
+	/// An iterator over the remaining key-value pairs, with the keys read as
+	/// strings.
+	///
+	/// Keys that are not strings result in an `Err(NotRead::NoFit(_))`.
+	pub fn str_entries(
+		self,
+	) -> impl Iterator<Item = Result<(&'d str, ArgdataRef<'a, 'd>), NotRead>> {
+		self.map(|entry| {
+			let (k, v) = entry?;
+			match k.read_str() {
+				Ok(k) => Ok((k, v)),
+				Err(NotRead::NoFit(_)) => Err(NoFit::DifferentType.into()),
+				Err(e) => Err(e),
+			}
+		})
+	}
 }
 
 impl<'a, 'd: 'a> Iterator for MapIterator<'a, 'd> {
@@ -60,3 +124,67 @@ impl std::fmt::Debug for MapIterator<'_, '_> {
 		write!(f, "MapIterator(.., {})", self.cookie)
 	}
 }
+
+#[test]
+fn map_iterator_test() {
+	use crate::{encoded, Argdata, OwnedArgdata};
+	let owned = OwnedArgdata::Map(vec![
+		(1.into(), "one".into()),
+		(b"\xFF"[..].into(), "binary".into()),
+		("a".into(), "first a".into()),
+		("b".into(), "b".into()),
+		("a".into(), "second a".into()),
+	]);
+	let mut data = Vec::new();
+	owned.argdata().serialize(&mut data, None).unwrap();
+	let encoded_map = encoded(&data);
+	for value in &[owned.argdata() as &dyn Argdata, &encoded_map] {
+		let map = value.read_map().unwrap();
+		let get = |key| {
+			map.get_str_key(key)
+				.map(|v| v.map(|v| format!("{:?}", &*v)))
+		};
+		assert_eq!(get("a"), Ok(Some("\"first a\"".to_string())));
+		assert_eq!(get("b"), Ok(Some("\"b\"".to_string())));
+		assert_eq!(get("c"), Ok(None));
+		assert_eq!(get("\u{FFFD}"), Ok(None));
+
+		let keys: Vec<_> = map.keys().map(|k| format!("{:?}", &*k.unwrap())).collect();
+		assert_eq!(keys, ["1", "binary([255])", "\"a\"", "\"b\"", "\"a\""]);
+		let values: Vec<_> = map
+			.values()
+			.map(|v| v.unwrap().read_str().unwrap())
+			.collect();
+		assert_eq!(values, ["one", "binary", "first a", "b", "second a"]);
+
+		let mut entries = map.str_entries();
+		assert_eq!(
+			entries.next().unwrap().err(),
+			Some(NoFit::DifferentType.into())
+		);
+		assert_eq!(
+			entries.next().unwrap().err(),
+			Some(NoFit::DifferentType.into())
+		);
+		let (k, v) = entries.next().unwrap().unwrap();
+		assert_eq!((k, v.read_str()), ("a", Ok("first a")));
+
+		let mut map = map;
+		map.nth(2);
+		assert_eq!(
+			map.get_str_key("a").unwrap().unwrap().read_str(),
+			Ok("second a")
+		);
+	}
+
+	let invalid = encoded(b"\x06\x82\x08b\x85");
+	assert_eq!(
+		invalid.read_map().unwrap().get_str_key("a").err(),
+		Some(ReadError::InvalidSubfield)
+	);
+	let invalid = encoded(b"\x06\x82\x08a");
+	assert_eq!(
+		invalid.read_map().unwrap().get_str_key("a").err(),
+		Some(ReadError::InvalidKeyValuePair)
+	);
+}
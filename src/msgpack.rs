@@ -0,0 +1,481 @@
+//! The argdata types are mapped onto MessagePack as follows:
+//!
+//!  - Null, booleans, strings, maps and seqs to their MessagePack counterparts.
+//!  - Binary blobs to bin.
+//!  - Floats to float 64. Float 32 is accepted when decoding.
+//!  - Integers to MessagePack integers if they fit in 64 bits, and otherwise
+//!    to an extension of type [`BIG_INT_EXT`] containing the big-endian 2's
+//!    complement representation.
+//!  - Timestamps to the timestamp extension type (-1), using the smallest of
+//!    its three formats that fits.
+//!  - File descriptors to an extension of type [`FD_EXT`] containing the
+//!    32-bit big-endian encoded fd number.
+
+use crate::{
+	fd,
+	parse::{self, invalid_data, Input, MAX_DEPTH},
+	Argdata, IntValue, OwnedArgdata, ParseError, Timespec, Value,
+};
+use byteorder::{BigEndian, ByteOrder};
+use std::io;
+
+/// The extension type used for file descriptors.
+pub const FD_EXT: i8 = 1;
+
+/// The extension type used for integers that don't fit in 64 bits.
+pub const BIG_INT_EXT: i8 = 2;
+
+/// The predefined extension type for timestamps.
+const TIMESTAMP_EXT: i8 = -1;
+
+/// Write an argdata value as MessagePack.
+///
+/// Errors while reading the argdata are returned as an `io::Error` of kind
+/// `InvalidData` containing the [`ReadError`](crate::ReadError). Maps and
+/// seqs nested more than 256 levels deep give a `ReadError::NestedTooDeeply`.
+///
+/// Strings and binary blobs of 4 GiB or larger, and maps and seqs with
+/// 2<sup>32</sup> or more elements, give an `io::Error` of kind
+/// `InvalidInput`, since MessagePack can't represent those.
+pub fn to_writer(writer: &mut dyn io::Write, value: &dyn Argdata) -> io::Result<()> {
+	write_value(writer, value, 0)
+}
+
+/// Convert an argdata value to MessagePack.
+///
+/// Errors are returned as by [`to_writer`], since values that can be read
+/// can still be too large for MessagePack.
+pub fn to_vec(value: &dyn Argdata) -> io::Result<Vec<u8>> {
+	let mut buf = Vec::new();
+	write_value(&mut buf, value, 0)?;
+	Ok(buf)
+}
+
+/// Decode a single MessagePack object into an argdata value.
+pub fn from_slice(data: &[u8]) -> Result<OwnedArgdata, ParseError> {
+	let mut decoder = Decoder {
+		input: Input::new(data),
+	};
+	let value = decoder.object(0)?;
+	if decoder.input.pos != data.len() {
+		return Err(decoder
+			.input
+			.error("Trailing bytes after MessagePack object"));
+	}
+	Ok(value)
+}
+
+/// Write the header of a str, bin, array or map.
+///
+/// `fix` is the first byte of the fixed-size format (if any) and its maximum
+/// length, and `formats` the first bytes of the 8, 16, and 32-bit formats
+/// (if they exist).
+fn write_len(
+	w: &mut dyn io::Write,
+	len: usize,
+	fix: Option<(u8, usize)>,
+	formats: [Option<u8>; 3],
+) -> io::Result<()> {
+	match (fix, formats) {
+		(Some((fix, max)), _) if len <= max => w.write_all(&[fix | len as u8]),
+		(_, [Some(b), _, _]) if len <= 0xFF => w.write_all(&[b, len as u8]),
+		(_, [_, Some(b), _]) if len <= 0xFFFF => {
+			w.write_all(&[b])?;
+			w.write_all(&(len as u16).to_be_bytes())
+		}
+		(_, [_, _, Some(b)]) if len <= 0xFFFF_FFFF => {
+			w.write_all(&[b])?;
+			w.write_all(&(len as u32).to_be_bytes())
+		}
+		(_, [_, _, Some(_)]) => Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"Too large for MessagePack",
+		)),
+		_ => unreachable!(),
+	}
+}
+
+fn write_ext(w: &mut dyn io::Write, ext: i8, data: &[u8]) -> io::Result<()> {
+	match data.len() {
+		1 => w.write_all(&[0xD4])?,
+		2 => w.write_all(&[0xD5])?,
+		4 => w.write_all(&[0xD6])?,
+		8 => w.write_all(&[0xD7])?,
+		16 => w.write_all(&[0xD8])?,
+		len => write_len(w, len, None, [Some(0xC7), Some(0xC8), Some(0xC9)])?,
+	}
+	w.write_all(&[ext as u8])?;
+	w.write_all(data)
+}
+
+fn write_int(w: &mut dyn io::Write, v: IntValue) -> io::Result<()> {
+	if let Ok(v) = v.try_into::<u64>() {
+		if v < 0x80 {
+			w.write_all(&[v as u8])
+		} else if v <= 0xFF {
+			w.write_all(&[0xCC, v as u8])
+		} else if v <= 0xFFFF {
+			w.write_all(&[0xCD])?;
+			w.write_all(&(v as u16).to_be_bytes())
+		} else if v <= 0xFFFF_FFFF {
+			w.write_all(&[0xCE])?;
+			w.write_all(&(v as u32).to_be_bytes())
+		} else {
+			w.write_all(&[0xCF])?;
+			w.write_all(&v.to_be_bytes())
+		}
+	} else if let Ok(v) = v.try_into::<i64>() {
+		if v >= -32 {
+			w.write_all(&[v as u8])
+		} else if v >= i8::MIN as i64 {
+			w.write_all(&[0xD0, v as u8])
+		} else if v >= i16::MIN as i64 {
+			w.write_all(&[0xD1])?;
+			w.write_all(&(v as i16).to_be_bytes())
+		} else if v >= i32::MIN as i64 {
+			w.write_all(&[0xD2])?;
+			w.write_all(&(v as i32).to_be_bytes())
+		} else {
+			w.write_all(&[0xD3])?;
+			w.write_all(&v.to_be_bytes())
+		}
+	} else {
+		let mut bytes = Vec::with_capacity(v.serialized_length());
+		v.serialize(&mut bytes)?;
+		write_ext(w, BIG_INT_EXT, &bytes)
+	}
+}
+
+fn write_timestamp(w: &mut dyn io::Write, t: Timespec) -> io::Result<()> {
+	if t.sec >> 34 == 0 {
+		let v = (t.nsec as u64) << 34 | t.sec as u64;
+		if v >> 32 == 0 {
+			write_ext(w, TIMESTAMP_EXT, &(v as u32).to_be_bytes())
+		} else {
+			write_ext(w, TIMESTAMP_EXT, &v.to_be_bytes())
+		}
+	} else {
+		let mut data = [0; 12];
+		BigEndian::write_u32(&mut data[..4], t.nsec);
+		BigEndian::write_i64(&mut data[4..], t.sec);
+		write_ext(w, TIMESTAMP_EXT, &data)
+	}
+}
+
+/// Write a value that is nested `depth` levels deep.
+fn write_value(w: &mut dyn io::Write, value: &dyn Argdata, depth: usize) -> io::Result<()> {
+	match parse::read_nested(value, depth)? {
+		Value::Null => w.write_all(&[0xC0]),
+		Value::Binary(v) => {
+			write_len(w, v.len(), None, [Some(0xC4), Some(0xC5), Some(0xC6)])?;
+			w.write_all(v)
+		}
+		Value::Bool(v) => w.write_all(&[if v { 0xC3 } else { 0xC2 }]),
+		Value::Fd(v) => write_ext(w, FD_EXT, &v.raw_encoded_number().to_be_bytes()),
+		Value::Float(v) => {
+			w.write_all(&[0xCB])?;
+			w.write_all(&v.to_bits().to_be_bytes())
+		}
+		Value::Int(v) => write_int(w, v),
+		Value::Str(v) => {
+			let s = v.as_str().map_err(|e| invalid_data(e.into()))?;
+			write_len(
+				w,
+				s.len(),
+				Some((0xA0, 31)),
+				[Some(0xD9), Some(0xDA), Some(0xDB)],
+			)?;
+			w.write_all(s.as_bytes())
+		}
+		Value::Timestamp(t) => write_timestamp(w, t),
+		Value::Map(it) => {
+			let mut len = 0;
+			for entry in it {
+				entry.map_err(invalid_data)?;
+				len += 1;
+			}
+			write_len(w, len, Some((0x80, 15)), [None, Some(0xDE), Some(0xDF)])?;
+			for entry in value.read_map().unwrap() {
+				let (key, val) = entry.map_err(invalid_data)?;
+				write_value(w, &*key, depth + 1)?;
+				write_value(w, &*val, depth + 1)?;
+			}
+			Ok(())
+		}
+		Value::Seq(it) => {
+			let mut len = 0;
+			for entry in it {
+				entry.map_err(invalid_data)?;
+				len += 1;
+			}
+			write_len(w, len, Some((0x90, 15)), [None, Some(0xDC), Some(0xDD)])?;
+			for val in value.read_seq().unwrap() {
+				write_value(w, &*val.map_err(invalid_data)?, depth + 1)?;
+			}
+			Ok(())
+		}
+	}
+}
+
+struct Decoder<'a> {
+	input: Input<'a>,
+}
+
+impl<'a> Decoder<'a> {
+	fn bytes(&mut self, n: usize) -> Result<&'a [u8], ParseError> {
+		self.input
+			.bytes(n as u64, "Unexpected end of MessagePack data")
+	}
+
+	fn uint(&mut self, n: usize) -> Result<u64, ParseError> {
+		Ok(BigEndian::read_uint(self.bytes(n)?, n))
+	}
+
+	fn int(&mut self, n: usize) -> Result<i64, ParseError> {
+		Ok(BigEndian::read_int(self.bytes(n)?, n))
+	}
+
+	fn array(&mut self, len: u64, depth: usize) -> Result<OwnedArgdata, ParseError> {
+		let mut elements = Vec::new();
+		for _ in 0..len {
+			elements.push(self.object(depth + 1)?);
+		}
+		Ok(OwnedArgdata::Seq(elements))
+	}
+
+	fn map(&mut self, len: u64, depth: usize) -> Result<OwnedArgdata, ParseError> {
+		let mut entries = Vec::new();
+		for _ in 0..len {
+			let key = self.object(depth + 1)?;
+			let value = self.object(depth + 1)?;
+			entries.push((key, value));
+		}
+		Ok(OwnedArgdata::Map(entries))
+	}
+
+	fn str(&mut self, len: u64) -> Result<OwnedArgdata, ParseError> {
+		let start = self.input.pos;
+		let s = self.bytes(len as usize)?;
+		match std::str::from_utf8(s) {
+			Ok(s) => Ok(OwnedArgdata::Str(s.to_string())),
+			Err(_) => {
+				self.input.pos = start;
+				Err(self.input.error("Invalid UTF-8 in str"))
+			}
+		}
+	}
+
+	fn ext(&mut self, len: u64) -> Result<OwnedArgdata, ParseError> {
+		let ext = self.bytes(1)?[0] as i8;
+		let start = self.input.pos;
+		let data = self.bytes(len as usize)?;
+		let value = match (ext, data.len()) {
+			(FD_EXT, 4) => Some(fd::Fd(BigEndian::read_i32(data)).into()),
+			(BIG_INT_EXT, _) => Some(IntValue::from_bigint(data).into()),
+			(TIMESTAMP_EXT, 4) => Some(
+				Timespec {
+					sec: BigEndian::read_u32(data) as i64,
+					nsec: 0,
+				}
+				.into(),
+			),
+			(TIMESTAMP_EXT, 8) => {
+				let v = BigEndian::read_u64(data);
+				Some((v as i64 & 0x3_FFFF_FFFF, (v >> 34) as u32))
+					.filter(|&(_, nsec)| nsec < 1_000_000_000)
+					.map(|(sec, nsec)| Timespec { sec, nsec }.into())
+			}
+			(TIMESTAMP_EXT, 12) => {
+				Some((BigEndian::read_i64(&data[4..]), BigEndian::read_u32(data)))
+					.filter(|&(_, nsec)| nsec < 1_000_000_000)
+					.map(|(sec, nsec)| Timespec { sec, nsec }.into())
+			}
+			(FD_EXT, _) | (TIMESTAMP_EXT, _) => None,
+			_ => {
+				self.input.pos = start - 1;
+				return Err(self.input.error("Unsupported extension type"));
+			}
+		};
+		value.ok_or(ParseError::new(start, "Invalid extension data"))
+	}
+
+	fn object(&mut self, depth: usize) -> Result<OwnedArgdata, ParseError> {
+		if depth > MAX_DEPTH {
+			return Err(self.input.error("MessagePack nested too deeply"));
+		}
+		let start = self.input.pos;
+		let b = self.bytes(1)?[0];
+		match b {
+			0x00..=0x7F => Ok((b as u64).into()),
+			0x80..=0x8F => self.map((b & 0x0F) as u64, depth),
+			0x90..=0x9F => self.array((b & 0x0F) as u64, depth),
+			0xA0..=0xBF => self.str((b & 0x1F) as u64),
+			0xC0 => Ok(OwnedArgdata::Null),
+			0xC2 => Ok(false.into()),
+			0xC3 => Ok(true.into()),
+			0xC4..=0xC6 => {
+				let len = self.uint(1 << (b - 0xC4))?;
+				Ok(OwnedArgdata::Binary(self.bytes(len as usize)?.to_vec()))
+			}
+			0xC7..=0xC9 => {
+				let len = self.uint(1 << (b - 0xC7))?;
+				self.ext(len)
+			}
+			0xCA => Ok((f32::from_bits(self.uint(4)? as u32) as f64).into()),
+			0xCB => Ok(f64::from_bits(self.uint(8)?).into()),
+			0xCC..=0xCF => Ok(self.uint(1 << (b - 0xCC))?.into()),
+			0xD0..=0xD3 => Ok(self.int(1 << (b - 0xD0))?.into()),
+			0xD4..=0xD8 => self.ext(1 << (b - 0xD4)),
+			0xD9..=0xDB => {
+				let len = self.uint(1 << (b - 0xD9))?;
+				self.str(len)
+			}
+			0xDC | 0xDD => {
+				let len = self.uint(if b == 0xDC { 2 } else { 4 })?;
+				self.array(len, depth)
+			}
+			0xDE | 0xDF => {
+				let len = self.uint(if b == 0xDE { 2 } else { 4 })?;
+				self.map(len, depth)
+			}
+			0xE0..=0xFF => Ok((b as i8).into()),
+			0xC1 => {
+				self.input.pos = start;
+				Err(self.input.error("Invalid MessagePack format byte"))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::ReadError;
+
+	fn read_error(result: io::Result<Vec<u8>>) -> ReadError {
+		*result
+			.unwrap_err()
+			.into_inner()
+			.unwrap()
+			.downcast::<ReadError>()
+			.unwrap()
+	}
+
+	fn roundtrip(value: OwnedArgdata, msgpack: &[u8]) {
+		assert_eq!(to_vec(value.argdata()).unwrap(), msgpack);
+		let decoded = from_slice(msgpack).unwrap();
+		assert_eq!(decoded, value);
+	}
+
+	#[test]
+	fn msgpack_roundtrip_test() {
+		roundtrip(OwnedArgdata::Null, b"\xC0");
+		roundtrip(false.into(), b"\xC2");
+		roundtrip(5.into(), b"\x05");
+		roundtrip(200.into(), b"\xCC\xC8");
+		roundtrip(u64::MAX.into(), b"\xCF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF");
+		roundtrip((-1).into(), b"\xFF");
+		roundtrip((-33).into(), b"\xD0\xDF");
+		roundtrip((-40000).into(), b"\xD2\xFF\xFF\x63\xC0");
+		roundtrip(
+			IntValue::from_bigint(b"\x01\0\0\0\0\0\0\0\0").into(),
+			b"\xC7\x09\x02\x01\0\0\0\0\0\0\0\0",
+		);
+		roundtrip(0.5.into(), b"\xCB\x3F\xE0\0\0\0\0\0\0");
+		roundtrip("abc".into(), b"\xA3abc");
+		roundtrip(
+			"a".repeat(32).into(),
+			&[&b"\xD9\x20"[..], &[b'a'; 32]].concat(),
+		);
+		roundtrip(b"\x01"[..].into(), b"\xC4\x01\x01");
+		roundtrip(fd::Fd(2).into(), b"\xD6\x01\0\0\0\x02");
+		roundtrip(Timespec { sec: 1, nsec: 0 }.into(), b"\xD6\xFF\0\0\0\x01");
+		roundtrip(
+			Timespec { sec: 1, nsec: 1 }.into(),
+			b"\xD7\xFF\0\0\0\x04\0\0\0\x01",
+		);
+		roundtrip(
+			Timespec { sec: -1, nsec: 0 }.into(),
+			b"\xC7\x0C\xFF\0\0\0\0\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF",
+		);
+		roundtrip(
+			OwnedArgdata::Map(vec![(
+				"a".into(),
+				OwnedArgdata::Seq(vec![OwnedArgdata::Null; 16]),
+			)]),
+			&[&b"\x81\xA1a\xDC\x00\x10"[..], &[0xC0; 16]].concat(),
+		);
+	}
+
+	#[test]
+	fn msgpack_encoded_test() {
+		let argdata = crate::encoded(&b"\x07\x81\x02\x82\x02\x01\x87\x08Hello\x00"[..]);
+		assert_eq!(to_vec(&argdata).unwrap(), b"\x93\xC2\xC3\xA5Hello");
+
+		let invalid = crate::encoded(&b"\x07\x82\x08a"[..]);
+		assert_eq!(
+			read_error(to_vec(&invalid)),
+			ReadError::MissingNullTerminator
+		);
+	}
+
+	#[test]
+	#[cfg(target_pointer_width = "64")]
+	fn msgpack_too_large_test() {
+		let error = write_len(&mut Vec::new(), 1 << 32, None, [None, None, Some(0xC6)]);
+		assert_eq!(error.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+	}
+
+	#[test]
+	fn msgpack_decode_test() {
+		let decode = |data: &[u8]| from_slice(data).map(|v| format!("{:?}", v));
+		assert_eq!(
+			decode(b"\x92\xCA\x3F\xC0\0\0\xD1\xFF\x00").unwrap(),
+			"[1.5, -256]"
+		);
+
+		let error = |data: &[u8]| from_slice(data).unwrap_err().to_string();
+		assert_eq!(error(b""), "Unexpected end of MessagePack data at byte 0");
+		assert_eq!(
+			error(b"\x92\x01"),
+			"Unexpected end of MessagePack data at byte 2"
+		);
+		assert_eq!(
+			error(b"\x01\x02"),
+			"Trailing bytes after MessagePack object at byte 1"
+		);
+		assert_eq!(error(b"\xC1"), "Invalid MessagePack format byte at byte 0");
+		assert_eq!(error(b"\xA1\xFF"), "Invalid UTF-8 in str at byte 1");
+		assert_eq!(
+			error(b"\xD4\x05\x00"),
+			"Unsupported extension type at byte 1"
+		);
+		assert_eq!(error(b"\xD5\x01\0\0"), "Invalid extension data at byte 2");
+		assert_eq!(
+			error(b"\xD7\xFF\xFF\xFF\xFF\xFF\0\0\0\0"),
+			"Invalid extension data at byte 2"
+		);
+		assert_eq!(
+			error(&[0x91; 1000]),
+			"MessagePack nested too deeply at byte 257"
+		);
+	}
+
+	#[test]
+	fn msgpack_deep_nesting_test() {
+		let data = parse::nested_seqs(MAX_DEPTH + 1);
+		let mut msgpack = vec![0x91; MAX_DEPTH + 1];
+		msgpack.push(0xC0);
+		assert_eq!(to_vec(&crate::encoded(&data)).unwrap(), msgpack);
+		let data = parse::nested_seqs(MAX_DEPTH + 2);
+		assert_eq!(
+			read_error(to_vec(&crate::encoded(&data))),
+			ReadError::NestedTooDeeply
+		);
+		let data = parse::nested_seqs(200_000);
+		assert_eq!(
+			read_error(to_vec(&crate::encoded(&data))),
+			ReadError::NestedTooDeeply
+		);
+	}
+}
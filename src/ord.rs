@@ -0,0 +1,440 @@
+use crate::{
+	eq::{fd_number, identical},
+	parse::MAX_DEPTH,
+	Argdata, OwnedArgdata, ReadError, Type, Value,
+};
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+/// Compare two values, including everything they contain.
+///
+/// Values are ordered by their type first, in the order of the tags that
+/// identify them in the encoding: null, binary, bool, fd, float, int, map,
+/// seq, str, timestamp. This is the same order in which values of different
+/// types end up when the keys of a map are sorted by their encoded bytes,
+/// as done by [`canonical`](crate::canonical),
+/// [`ArgdataWriter::sort_maps`](crate::ArgdataWriter::sort_maps) and
+/// expected by [`EncodedIndex::new_sorted`](crate::EncodedIndex::new_sorted).
+///
+/// Values of the same type are ordered as follows, which is *not* the order
+/// of their encoded bytes. (For example, `-1` is encoded as `05 FF`, which
+/// sorts after `1`, encoded as `05 01`.) Sorting values with `try_cmp` does
+/// therefore not give a canonical order of map keys.
+///
+///
+///  - Binary blobs and strings by their bytes, lexicographically.
+///  - Booleans with `false` first.
+///  - File descriptors by the number of the `Fd` they convert to.
+///  - Floats by [`f64::total_cmp`], which orders `-0.0` before `0.0`, and
+///    NaNs by their bits (before or after all other values, depending on their
+///    sign bit).
+///  - Integers and timestamps by their value.
+///  - Maps by their entries (key first, then value), and seqs by their
+///    elements, lexicographically.
+///
+/// This is consistent with [`try_eq`](crate::try_eq): two values are `Equal`
+/// exactly if they are equal. As with `try_eq`, values that are both backed
+/// by identical encoded data without file descriptors are `Equal` without
+/// reading them, and an error is returned when a value that needs to be
+/// compared can't be read, or when maps or seqs that need to be compared are
+/// nested more than 256 levels deep.
+pub fn try_cmp(a: &dyn Argdata<'_>, b: &dyn Argdata<'_>) -> Result<Ordering, ReadError> {
+	cmp_at(a, b, 0)
+}
+
+/// Compare two values that are nested `depth` levels deep.
+fn cmp_at(a: &dyn Argdata<'_>, b: &dyn Argdata<'_>, depth: usize) -> Result<Ordering, ReadError> {
+	if identical(a, b) {
+		return Ok(Ordering::Equal);
+	}
+	let (a, b) = (a.read()?, b.read()?);
+	if depth > MAX_DEPTH {
+		if let (Value::Map(_), Value::Map(_)) | (Value::Seq(_), Value::Seq(_)) = (a, b) {
+			return Err(ReadError::NestedTooDeeply);
+		}
+	}
+	Ok(match (a, b) {
+		(Value::Null, Value::Null) => Ordering::Equal,
+		(Value::Binary(a), Value::Binary(b)) => a.cmp(b),
+		(Value::Bool(a), Value::Bool(b)) => a.cmp(&b),
+		(Value::Fd(a), Value::Fd(b)) => fd_number(a)?.cmp(&fd_number(b)?),
+		(Value::Float(a), Value::Float(b)) => a.total_cmp(&b),
+		(Value::Int(a), Value::Int(b)) => a.cmp(&b),
+		(Value::Str(a), Value::Str(b)) => a.as_bytes().cmp(b.as_bytes()),
+		(Value::Timestamp(a), Value::Timestamp(b)) => a.cmp(&b),
+		(Value::Map(mut a), Value::Map(mut b)) => loop {
+			match (a.next(), b.next()) {
+				(None, None) => break Ordering::Equal,
+				(None, Some(_)) => break Ordering::Less,
+				(Some(_), None) => break Ordering::Greater,
+				(Some(a), Some(b)) => {
+					let ((ak, av), (bk, bv)) = (a?, b?);
+					match cmp_at(&*ak, &*bk, depth + 1)? {
+						Ordering::Equal => {}
+						o => break o,
+					}
+					match cmp_at(&*av, &*bv, depth + 1)? {
+						Ordering::Equal => {}
+						o => break o,
+					}
+				}
+			}
+		},
+		(Value::Seq(mut a), Value::Seq(mut b)) => loop {
+			match (a.next(), b.next()) {
+				(None, None) => break Ordering::Equal,
+				(None, Some(_)) => break Ordering::Less,
+				(Some(_), None) => break Ordering::Greater,
+				(Some(a), Some(b)) => match cmp_at(&*a?, &*b?, depth + 1)? {
+					Ordering::Equal => {}
+					o => break o,
+				},
+			}
+		},
+		(a, b) => tag(a.get_type()).cmp(&tag(b.get_type())),
+	})
+}
+
+/// The tag of the encoding of a value of a type, or 0 for null.
+fn tag(t: Type) -> u8 {
+	match t {
+		Type::Null => 0,
+		Type::Binary => 1,
+		Type::Bool => 2,
+		Type::Fd => 3,
+		Type::Float => 4,
+		Type::Int => 5,
+		Type::Map => 6,
+		Type::Seq => 7,
+		Type::Str => 8,
+		Type::Timestamp => 9,
+	}
+}
+
+/// Hash a value that is nested `depth` levels deep, consistently with
+/// [`try_eq`](crate::try_eq).
+fn try_hash<H: Hasher>(
+	value: &dyn Argdata<'_>,
+	state: &mut H,
+	depth: usize,
+) -> Result<(), ReadError> {
+	let value = value.read()?;
+	if depth > MAX_DEPTH && matches!(value, Value::Map(_) | Value::Seq(_)) {
+		return Err(ReadError::NestedTooDeeply);
+	}
+	value.get_type().hash(state);
+	match value {
+		Value::Null => {}
+		Value::Binary(v) => v.hash(state),
+		Value::Bool(v) => v.hash(state),
+		Value::Fd(v) => fd_number(v)?.hash(state),
+		Value::Float(v) => v.to_bits().hash(state),
+		Value::Int(v) => v.hash(state),
+		Value::Str(v) => v.as_bytes().hash(state),
+		Value::Timestamp(v) => v.hash(state),
+		Value::Map(map) => {
+			let mut len = 0usize;
+			for entry in map {
+				let (key, value) = entry?;
+				try_hash(&*key, state, depth + 1)?;
+				try_hash(&*value, state, depth + 1)?;
+				len += 1;
+			}
+			len.hash(state);
+		}
+		Value::Seq(seq) => {
+			let mut len = 0usize;
+			for element in seq {
+				try_hash(&*element?, state, depth + 1)?;
+				len += 1;
+			}
+			len.hash(state);
+		}
+	}
+	Ok(())
+}
+
+/// Read a value that is nested `depth` levels deep and everything it
+/// contains, to check that it can be compared.
+fn read_all(value: &dyn Argdata<'_>, depth: usize) -> Result<(), ReadError> {
+	let value = value.read()?;
+	if depth > MAX_DEPTH && matches!(value, Value::Map(_) | Value::Seq(_)) {
+		return Err(ReadError::NestedTooDeeply);
+	}
+	match value {
+		Value::Fd(fd) => {
+			fd_number(fd)?;
+		}
+		Value::Map(map) => {
+			for entry in map {
+				let (key, value) = entry?;
+				read_all(&*key, depth + 1)?;
+				read_all(&*value, depth + 1)?;
+			}
+		}
+		Value::Seq(seq) => {
+			for element in seq {
+				read_all(&*element?, depth + 1)?;
+			}
+		}
+		_ => {}
+	}
+	Ok(())
+}
+
+/// A reference to an argdata value, which implements `Eq`, `Ord` and `Hash`.
+///
+/// This allows argdata values to be used as keys in a `BTreeMap` or
+/// `HashMap`, or to be sorted. `T` can be anything that refers to a
+/// `dyn Argdata`, such as a `&dyn Argdata`, an [`ArgdataRef`](crate::ArgdataRef)
+/// or a `Box<dyn Argdata>`.
+///
+/// Values are compared using [`try_cmp`] and [`try_eq`](crate::try_eq). The
+/// whole value is read when the `OrdArgdata` is created, such that comparing
+/// and hashing it can't fail afterwards.
+#[derive(Debug, Clone, Copy)]
+pub struct OrdArgdata<T>(T);
+
+impl<'a, 'd: 'a, T: Deref<Target = dyn Argdata<'d> + 'a>> OrdArgdata<T> {
+	/// Wrap a value, after checking that it can be read completely, and that
+	/// it is not nested more than 256 levels deep.
+	pub fn new(value: T) -> Result<Self, ReadError> {
+		read_all(&*value, 0)?;
+		Ok(OrdArgdata(value))
+	}
+
+	/// Get the wrapped value back.
+	pub fn into_inner(self) -> T {
+		self.0
+	}
+}
+
+impl<T> Deref for OrdArgdata<T> {
+	type Target = T;
+	fn deref(&self) -> &T {
+		&self.0
+	}
+}
+
+impl<'a, 'd: 'a, T: Deref<Target = dyn Argdata<'d> + 'a>> PartialEq for OrdArgdata<T> {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl<'a, 'd: 'a, T: Deref<Target = dyn Argdata<'d> + 'a>> Eq for OrdArgdata<T> {}
+
+impl<'a, 'd: 'a, T: Deref<Target = dyn Argdata<'d> + 'a>> PartialOrd for OrdArgdata<T> {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl<'a, 'd: 'a, T: Deref<Target = dyn Argdata<'d> + 'a>> Ord for OrdArgdata<T> {
+	fn cmp(&self, other: &Self) -> Ordering {
+		try_cmp(&*self.0, &*other.0).expect("OrdArgdata value could not be read")
+	}
+}
+
+impl<'a, 'd: 'a, T: Deref<Target = dyn Argdata<'d> + 'a>> Hash for OrdArgdata<T> {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		try_hash(&*self.0, state, 0).expect("OrdArgdata value could not be read")
+	}
+}
+
+// Reading an OwnedArgdata never fails, so it can implement these directly.
+// They panic on values nested more than 256 levels deep, which can only be
+// constructed by hand.
+
+impl Eq for OwnedArgdata {}
+
+/// Compares using [`try_cmp`].
+impl PartialOrd for OwnedArgdata {
+	fn partial_cmp(&self, other: &OwnedArgdata) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+/// Compares using [`try_cmp`].
+impl Ord for OwnedArgdata {
+	fn cmp(&self, other: &OwnedArgdata) -> Ordering {
+		try_cmp(self.argdata(), other.argdata()).unwrap()
+	}
+}
+
+impl Hash for OwnedArgdata {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		try_hash(self.argdata(), state, 0).unwrap()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{encoded, encoded_with_fds, fd, ArgdataRef, Timespec};
+	use std::collections::{BTreeSet, HashSet};
+
+	#[test]
+	fn cmp_test() {
+		let sorted: Vec<OwnedArgdata> = vec![
+			OwnedArgdata::Null,
+			OwnedArgdata::Binary(vec![]),
+			OwnedArgdata::Binary(vec![0]),
+			false.into(),
+			true.into(),
+			fd::Fd(0).into(),
+			f64::NEG_INFINITY.into(),
+			(-0.0).into(),
+			0.0.into(),
+			f64::NAN.into(),
+			OwnedArgdata::Int(vec![0x80, 0, 0, 0, 0, 0, 0, 0, 0]),
+			i64::MIN.into(),
+			(-1).into(),
+			0.into(),
+			u64::MAX.into(),
+			OwnedArgdata::Int(vec![1, 0, 0, 0, 0, 0, 0, 0, 0]),
+			OwnedArgdata::Map(vec![]),
+			OwnedArgdata::Map(vec![(1.into(), 1.into())]),
+			OwnedArgdata::Map(vec![(1.into(), 2.into())]),
+			OwnedArgdata::Map(vec![(2.into(), 0.into())]),
+			OwnedArgdata::Seq(vec![]),
+			OwnedArgdata::Seq(vec![OwnedArgdata::Null]),
+			OwnedArgdata::Seq(vec![OwnedArgdata::Null, OwnedArgdata::Null]),
+			OwnedArgdata::Seq(vec![false.into()]),
+			"".into(),
+			"a".into(),
+			"b".into(),
+			Timespec { sec: -1, nsec: 0 }.into(),
+			Timespec { sec: 0, nsec: 0 }.into(),
+		];
+		for (i, a) in sorted.iter().enumerate() {
+			for (j, b) in sorted.iter().enumerate() {
+				assert_eq!(a.cmp(b), i.cmp(&j), "{:?} <=> {:?}", a, b);
+				assert_eq!(a == b, i == j);
+			}
+		}
+
+		// Non-minimal encodings compare equal to minimal ones.
+		assert_eq!(
+			try_cmp(&encoded(b"\x05\xFF\xFF"), &crate::int(-1)),
+			Ok(Ordering::Equal)
+		);
+		assert_eq!(
+			try_cmp(&encoded(b"\x05\x00\x80"), &crate::int(127)),
+			Ok(Ordering::Greater)
+		);
+		assert_eq!(
+			try_cmp(&encoded(b"\x07\x81\x05\x82"), &encoded(b"\x07\x81\x05\x80")),
+			Err(ReadError::InvalidSubfield)
+		);
+
+		// File descriptors are compared after conversion.
+		let fd = b"\x03\x00\x00\x00\x00";
+		let fds = [fd::Fd(9)];
+		assert_eq!(
+			try_cmp(&encoded_with_fds(fd, &fds[..]), &fd::Fd(5)),
+			Ok(Ordering::Greater)
+		);
+		assert_eq!(
+			try_cmp(&encoded(fd), &encoded(fd)),
+			Err(ReadError::InvalidFdNumber(0))
+		);
+	}
+
+	#[test]
+	fn cmp_encoded_order_test() {
+		let encode = |value: &OwnedArgdata| {
+			let mut data = Vec::new();
+			value.argdata().serialize(&mut data, None).unwrap();
+			data
+		};
+
+		// Values of different types are in the order of their encoding.
+		let sorted: Vec<OwnedArgdata> = vec![
+			OwnedArgdata::Null,
+			OwnedArgdata::Binary(vec![1]),
+			true.into(),
+			fd::Fd(0).into(),
+			1.5.into(),
+			1.into(),
+			OwnedArgdata::Map(vec![]),
+			OwnedArgdata::Seq(vec![]),
+			"a".into(),
+			Timespec { sec: 0, nsec: 0 }.into(),
+		];
+		for w in sorted.windows(2) {
+			assert_eq!(w[0].cmp(&w[1]), Ordering::Less);
+			assert_eq!(encode(&w[0]).cmp(&encode(&w[1])), Ordering::Less);
+		}
+
+		// Values of the same type need not be.
+		let (a, b) = (OwnedArgdata::from(-1), OwnedArgdata::from(1));
+		assert_eq!(a.cmp(&b), Ordering::Less);
+		assert_eq!(encode(&a).cmp(&encode(&b)), Ordering::Greater);
+	}
+
+	#[test]
+	fn ord_argdata_test() {
+		// 2, 1, 1 (non-minimal), and "a".
+		let data = b"\x07\x82\x05\x02\x82\x05\x01\x83\x05\x00\x01\x83\x08a\x00";
+		let seq = encoded(data);
+		let elements: Vec<ArgdataRef> = seq.read_seq().unwrap().map(Result::unwrap).collect();
+
+		let set: BTreeSet<_> = elements
+			.iter()
+			.map(|e| OrdArgdata::new(&**e).unwrap())
+			.collect();
+		assert_eq!(
+			set.iter().map(|e| format!("{:?}", e)).collect::<Vec<_>>(),
+			["OrdArgdata(1)", "OrdArgdata(2)", "OrdArgdata(\"a\")"]
+		);
+
+		let set: HashSet<_> = elements
+			.into_iter()
+			.map(|e| OrdArgdata::new(e).unwrap())
+			.collect();
+		assert_eq!(set.len(), 3);
+		let one = crate::int(1);
+		assert!(set.contains(&OrdArgdata::new(ArgdataRef::reference(&one)).unwrap()));
+
+		let owned: HashSet<OwnedArgdata> = vec![1.into(), 1.into(), 2.into()].into_iter().collect();
+		assert_eq!(owned.len(), 2);
+
+		assert_eq!(
+			OrdArgdata::new(&encoded(b"\x07\x81\x08") as &dyn Argdata).err(),
+			Some(ReadError::MissingNullTerminator)
+		);
+		assert_eq!(
+			OrdArgdata::new(&encoded(b"\x07\x85\x03\x00\x00\x00\x00") as &dyn Argdata).err(),
+			Some(ReadError::InvalidFdNumber(0))
+		);
+	}
+
+	#[test]
+	fn ord_deep_nesting_test() {
+		let a = crate::parse::nested_seqs(200_000);
+		let mut b = a.clone();
+		let n = b.len();
+		b[n - 2..].copy_from_slice(b"\x02\x01");
+		assert_eq!(
+			try_cmp(&encoded(&a), &encoded(&b)),
+			Err(ReadError::NestedTooDeeply)
+		);
+		assert_eq!(
+			OrdArgdata::new(&encoded(&a) as &dyn Argdata).err(),
+			Some(ReadError::NestedTooDeeply)
+		);
+
+		let a = crate::parse::nested_seqs(MAX_DEPTH + 1);
+		let mut b = a.clone();
+		let n = b.len();
+		b[n - 2..].copy_from_slice(b"\x02\x01");
+		let set: HashSet<_> = [&a, &b]
+			.iter()
+			.map(|data| OrdArgdata::new(ArgdataRef::encoded(data, &fd::Identity)).unwrap())
+			.collect();
+		assert_eq!(set.len(), 2);
+		assert_eq!(try_cmp(&encoded(&a), &encoded(&b)), Ok(Ordering::Greater));
+	}
+}
@@ -0,0 +1,128 @@
+//! The parts shared by the conversions from and to other formats.
+
+use crate::ParseError;
+#[cfg(any(feature = "json", feature = "cbor", feature = "msgpack"))]
+use crate::{Argdata, ReadError, Value};
+#[cfg(any(feature = "json", feature = "cbor", feature = "msgpack"))]
+use std::io;
+
+/// The maximum nesting depth of maps and seqs that we parse or decode, or
+/// otherwise handle recursively. Deeper values result in
+/// `ReadError::NestedTooDeeply` or a `ParseError`.
+pub(crate) const MAX_DEPTH: usize = 256;
+
+/// The input of a parser or decoder, and how far it got.
+pub(crate) struct Input<'a> {
+	pub(crate) data: &'a [u8],
+	pub(crate) pos: usize,
+}
+
+impl<'a> Input<'a> {
+	pub(crate) fn new(data: &'a [u8]) -> Self {
+		Input { data, pos: 0 }
+	}
+
+	/// An error at the current position.
+	pub(crate) fn error(&self, message: &'static str) -> ParseError {
+		ParseError::new(self.pos, message)
+	}
+
+	pub(crate) fn peek(&self) -> Option<u8> {
+		self.data.get(self.pos).cloned()
+	}
+
+	/// Take the next `n` bytes, failing with `message` if there are fewer.
+	#[cfg(any(feature = "cbor", feature = "msgpack"))]
+	pub(crate) fn bytes(&mut self, n: u64, message: &'static str) -> Result<&'a [u8], ParseError> {
+		if n > (self.data.len() - self.pos) as u64 {
+			return Err(self.error(message));
+		}
+		let bytes = &self.data[self.pos..self.pos + n as usize];
+		self.pos += n as usize;
+		Ok(bytes)
+	}
+}
+
+/// A parser of a text format, which ignores whitespace between tokens.
+pub(crate) trait Parse<'a> {
+	fn input(&mut self) -> &mut Input<'a>;
+
+	/// Skip whitespace, and comments if the format has them.
+	fn whitespace(&mut self);
+
+	/// Skip whitespace, and then `c` if that comes next.
+	fn eat(&mut self, c: u8) -> bool {
+		self.whitespace();
+		let input = self.input();
+		if input.peek() == Some(c) {
+			input.pos += 1;
+			true
+		} else {
+			false
+		}
+	}
+
+	/// Skip whitespace and `c`, or fail with `message` if `c` doesn't come next.
+	fn expect(&mut self, c: u8, message: &'static str) -> Result<(), ParseError> {
+		if self.eat(c) {
+			Ok(())
+		} else {
+			Err(self.input().error(message))
+		}
+	}
+}
+
+/// Wrap a `ReadError` in an `io::Error`, as returned by the `to_writer`
+/// functions.
+#[cfg(any(feature = "json", feature = "cbor", feature = "msgpack"))]
+pub(crate) fn invalid_data(e: ReadError) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Read a value that is nested `depth` levels deep, to write it in another
+/// format. Fails with an error made by [`invalid_data`] if it can't be read,
+/// or if it is a map or seq nested too deeply.
+#[cfg(any(feature = "json", feature = "cbor", feature = "msgpack"))]
+pub(crate) fn read_nested<'a, 'd>(
+	value: &'a (dyn Argdata<'d> + 'a),
+	depth: usize,
+) -> io::Result<Value<'a, 'd>> {
+	let value = value.read().map_err(invalid_data)?;
+	if depth > MAX_DEPTH && matches!(value, Value::Map(_) | Value::Seq(_)) {
+		return Err(invalid_data(ReadError::NestedTooDeeply));
+	}
+	Ok(value)
+}
+
+/// Write into a `Vec` with a function that only fails with errors made by
+/// [`invalid_data`], and unwrap the `ReadError` from such an error.
+#[cfg(any(feature = "json", feature = "cbor"))]
+pub(crate) fn to_vec(
+	write: impl FnOnce(&mut Vec<u8>) -> io::Result<()>,
+) -> Result<Vec<u8>, ReadError> {
+	let mut buf = Vec::new();
+	match write(&mut buf) {
+		Ok(()) => Ok(buf),
+		Err(e) => Err(*e.into_inner().unwrap().downcast::<ReadError>().unwrap()),
+	}
+}
+
+/// Encode `depth` seqs nested in each other, for testing.
+#[cfg(test)]
+pub(crate) fn nested_seqs(depth: usize) -> Vec<u8> {
+	// The lengths of the seqs from the inside out, and then their headers
+	// from the outside in.
+	let mut lengths = vec![0];
+	for _ in 1..depth {
+		let inner = lengths[lengths.len() - 1];
+		let mut header = vec![7];
+		crate::subfield::write_subfield_length(inner, &mut header).unwrap();
+		lengths.push(header.len() + inner);
+	}
+	let mut data = Vec::with_capacity(lengths[lengths.len() - 1] + 4);
+	for &inner in lengths.iter().rev() {
+		data.push(7);
+		crate::subfield::write_subfield_length(inner, &mut data).unwrap();
+	}
+	data
+}
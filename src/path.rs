@@ -0,0 +1,311 @@
+use crate::{Argdata, ArgdataRef, NotRead, ReadError, Type};
+use std::error::Error;
+use std::fmt::{self, Display};
+
+/// Look up a value inside of maps and seqs.
+///
+/// The path consists of keys, which select a value from a map by its string
+/// key, and indexes, which select an element from a seq:
+///
+///  - `key` or `.key` selects the value of `key`. Unquoted keys can't contain
+///    `.`, `[`, `]` or `"`, and can't be empty.
+///  - `["key"]` does the same, but allows any key. Only `"` and `\` need to be
+///    escaped, with a `\`.
+///  - `[2]` selects the element at index 2.
+///
+/// A path starts with a key without a `.` or with an index, and the empty
+/// path selects the value itself. For example: `listen.addrs[2].port`.
+///
+/// If a map contains the same key more than once, the first one is used.
+pub fn get_path<'a, 'd>(
+	value: &'a (dyn Argdata<'d> + 'a),
+	path: &str,
+) -> Result<ArgdataRef<'a, 'd>, PathError> {
+	let mut value = ArgdataRef::reference(value);
+	for (segment, start, end) in parse(path)? {
+		value = value
+			.get_inner(|v| lookup(v, &segment))
+			.map_err(|e| e.at(&path[..start], &path[..end]))?;
+	}
+	Ok(value)
+}
+
+/// An error while looking up a value using [`get_path`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+	/// The path is not valid. Contains the byte offset into the path of the error.
+	InvalidPath(usize),
+
+	/// There is no value at the path.
+	///
+	/// Contains the part of the path up to and including the missing key or index.
+	NotFound(String),
+
+	/// A key or index was used on a value that is not a map or seq, respectively.
+	TypeMismatch {
+		/// The part of the path up to the value.
+		path: String,
+		/// The type the key or index needs: `Map` or `Seq`.
+		expected: Type,
+		/// The type of the value.
+		found: Type,
+	},
+
+	/// The data could not be read.
+	///
+	/// Contains the part of the path up to and including the key or index
+	/// where the error occurred.
+	Read(String, ReadError),
+}
+
+impl Error for PathError {}
+
+impl Display for PathError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			PathError::InvalidPath(offset) => write!(f, "Invalid path at byte {}", offset),
+			PathError::NotFound(path) => write!(f, "No value at `{}`", path),
+			PathError::TypeMismatch {
+				path,
+				expected,
+				found,
+			} => write!(
+				f,
+				"Expected {:?} at `{}`, found {:?}",
+				expected, path, found
+			),
+			PathError::Read(path, e) => write!(f, "{} at `{}`", e, path),
+		}
+	}
+}
+
+enum Segment {
+	Key(String),
+	Index(usize),
+}
+
+enum LookupError {
+	NotFound,
+	TypeMismatch(Type, Type),
+	Read(ReadError),
+}
+
+impl LookupError {
+	/// Convert to a `PathError`, given the path up to the value, and the path
+	/// including the key or index.
+	fn at(self, value_path: &str, path: &str) -> PathError {
+		match self {
+			LookupError::NotFound => PathError::NotFound(path.to_string()),
+			LookupError::TypeMismatch(expected, found) => PathError::TypeMismatch {
+				path: value_path.to_string(),
+				expected,
+				found,
+			},
+			LookupError::Read(e) => PathError::Read(path.to_string(), e),
+		}
+	}
+}
+
+impl From<ReadError> for LookupError {
+	fn from(e: ReadError) -> LookupError {
+		LookupError::Read(e)
+	}
+}
+
+fn lookup<'b, 'd>(
+	value: &'b (dyn Argdata<'d> + 'b),
+	segment: &Segment,
+) -> Result<ArgdataRef<'b, 'd>, LookupError> {
+	let not_read = |e: NotRead, expected| match e {
+		NotRead::Error(e) => LookupError::Read(e),
+		NotRead::NoFit(_) => match value.get_type() {
+			Ok(found) => LookupError::TypeMismatch(expected, found),
+			Err(e) => LookupError::Read(e),
+		},
+	};
+	match segment {
+		Segment::Key(key) => {
+			let map = value.read_map().map_err(|e| not_read(e, Type::Map))?;
+			map.get_str_key(key)?.ok_or(LookupError::NotFound)
+		}
+		&Segment::Index(index) => {
+			let seq = value.read_seq().map_err(|e| not_read(e, Type::Seq))?;
+			seq.get(index)?.ok_or(LookupError::NotFound)
+		}
+	}
+}
+
+/// Parse a path into its segments, each with the offsets of its start and end.
+fn parse(path: &str) -> Result<Vec<(Segment, usize, usize)>, PathError> {
+	let bytes = path.as_bytes();
+	let mut segments = Vec::new();
+	let mut pos = 0;
+	while pos < bytes.len() {
+		let start = pos;
+		let segment = match bytes[pos] {
+			b'[' if bytes.get(pos + 1) == Some(&b'"') => {
+				pos += 2;
+				let mut key = String::new();
+				let mut chars = path[pos..].char_indices();
+				loop {
+					match chars.next() {
+						Some((i, '"')) => {
+							pos += i + 1;
+							break;
+						}
+						Some((_, '\\')) => match chars.next() {
+							Some((_, c @ ('"' | '\\'))) => key.push(c),
+							Some((i, _)) => return Err(PathError::InvalidPath(pos + i)),
+							None => return Err(PathError::InvalidPath(bytes.len())),
+						},
+						Some((_, c)) => key.push(c),
+						None => return Err(PathError::InvalidPath(bytes.len())),
+					}
+				}
+				if bytes.get(pos) != Some(&b']') {
+					return Err(PathError::InvalidPath(pos));
+				}
+				pos += 1;
+				Segment::Key(key)
+			}
+			b'[' => {
+				pos += 1;
+				let len = bytes[pos..]
+					.iter()
+					.take_while(|b| b.is_ascii_digit())
+					.count();
+				let index = path[pos..pos + len].parse().ok();
+				match index {
+					Some(index) if bytes.get(pos + len) == Some(&b']') => {
+						pos += len + 1;
+						Segment::Index(index)
+					}
+					_ => return Err(PathError::InvalidPath(pos)),
+				}
+			}
+			_ => {
+				if !segments.is_empty() {
+					if bytes[pos] != b'.' {
+						return Err(PathError::InvalidPath(pos));
+					}
+					pos += 1;
+				}
+				let len = bytes[pos..]
+					.iter()
+					.take_while(|b| !b"[].\"".contains(b))
+					.count();
+				if len == 0 {
+					return Err(PathError::InvalidPath(pos));
+				}
+				pos += len;
+				Segment::Key(path[pos - len..pos].to_string())
+			}
+		};
+		segments.push((segment, start, pos));
+	}
+	Ok(segments)
+}
+
+/// Write a key as a segment of a path, in the form that [`get_path`] parses.
+///
+/// `first` says whether this is the first segment of the path.
+pub(crate) fn write_key(f: &mut dyn fmt::Write, key: &str, first: bool) -> fmt::Result {
+	if !key.is_empty() && !key.bytes().any(|b| b"[].\"".contains(&b)) {
+		if !first {
+			f.write_char('.')?;
+		}
+		f.write_str(key)
+	} else {
+		f.write_str("[\"")?;
+		for c in key.chars() {
+			if c == '"' || c == '\\' {
+				f.write_char('\\')?;
+			}
+			f.write_char(c)?;
+		}
+		f.write_str("\"]")
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::OwnedArgdata;
+
+	fn sample() -> OwnedArgdata {
+		OwnedArgdata::Map(vec![
+			(1.into(), "not a string key".into()),
+			(
+				"listen".into(),
+				OwnedArgdata::Map(vec![(
+					"addrs".into(),
+					OwnedArgdata::Seq(vec![
+						1.into(),
+						2.into(),
+						OwnedArgdata::Map(vec![("port".into(), 80.into())]),
+					]),
+				)]),
+			),
+			("a.b".into(), true.into()),
+		])
+	}
+
+	#[test]
+	fn get_path_test() {
+		let value = sample();
+		let get = |path| get_path(value.argdata(), path).map(|v| format!("{:?}", &*v));
+		assert_eq!(get("listen.addrs[2].port"), Ok("80".to_string()));
+		assert_eq!(get("listen.addrs[1]"), Ok("2".to_string()));
+		assert_eq!(get("[\"listen\"][\"addrs\"][0]"), Ok("1".to_string()));
+		assert_eq!(get("[\"a.b\"]"), Ok("true".to_string()));
+		assert_eq!(get(""), Ok(format!("{:?}", value)));
+
+		let encoded = b"\x07\x86\x06\x82\x08\x00\x81\x02";
+		let encoded = crate::encoded(encoded);
+		assert_eq!(
+			format!("{:?}", &*get_path(&encoded, "[0][\"\"]").unwrap()),
+			"false"
+		);
+	}
+
+	#[test]
+	fn get_path_error_test() {
+		let value = sample();
+		let get = |path| get_path(value.argdata(), path).map(|_| ());
+		assert_eq!(
+			get("listen.port"),
+			Err(PathError::NotFound("listen.port".to_string()))
+		);
+		assert_eq!(
+			get("listen.addrs[3]"),
+			Err(PathError::NotFound("listen.addrs[3]".to_string()))
+		);
+		assert_eq!(
+			get("listen[0]"),
+			Err(PathError::TypeMismatch {
+				path: "listen".to_string(),
+				expected: Type::Seq,
+				found: Type::Map,
+			})
+		);
+		assert_eq!(
+			get("listen.addrs[0].port").unwrap_err().to_string(),
+			"Expected Map at `listen.addrs[0]`, found Int"
+		);
+		assert_eq!(get("listen..addrs"), Err(PathError::InvalidPath(7)));
+		assert_eq!(get(".listen"), Err(PathError::InvalidPath(0)));
+		assert_eq!(get("listen[x]"), Err(PathError::InvalidPath(7)));
+		assert_eq!(get("[\"a\\x\"]"), Err(PathError::InvalidPath(4)));
+		assert_eq!(get("[\"a\""), Err(PathError::InvalidPath(4)));
+		assert_eq!(get("[0]listen"), Err(PathError::InvalidPath(3)));
+
+		let encoded = crate::encoded(b"\x07\x81\x02\x85\x08a");
+		assert_eq!(
+			get_path(&encoded, "[1]").map(|_| ()),
+			Err(PathError::Read(
+				"[1]".to_string(),
+				ReadError::InvalidSubfield
+			))
+		);
+	}
+}
@@ -0,0 +1,301 @@
+//! The child is given the file descriptors the argdata refers to, numbered
+//! sequentially from 3, after standard input, output and error. All other file
+//! descriptors are closed when the child executes its program. (On systems
+//! without `close_range` and `/proc`, only those below 65536.) The argdata
+//! itself is stored in a memfd, which is passed as the next file descriptor
+//! and named by the `ARGDATA_FD` environment variable, for
+//! [`env::argdata()`](crate::env::argdata) in the child to read. The child is
+//! made the owner (see `fcntl(F_SETOWN)`) of the memfd, so that its own
+//! children don't mistake the variable for theirs.
+
+use crate::{env::FD_VAR, fd, Argdata};
+use std::io;
+use std::os::raw::{c_char, c_int, c_uint};
+use std::os::unix::process::CommandExt as _;
+use std::process::Command;
+
+/// The number of the first file descriptor given to the child.
+pub const FIRST_FD: c_int = 3;
+
+/// Extension for `std::process::Command` to start a child process with argdata.
+pub trait CommandExt {
+	/// Pass argdata to the child process.
+	///
+	/// The argdata is serialized, and the file descriptors it refers to are
+	/// collected, right away. Those file descriptors must remain open until
+	/// the child is spawned.
+	///
+	/// This can only be used once on a `Command`. Fails with
+	/// `io::ErrorKind::InvalidInput` if the `Command` already has argdata, or
+	/// otherwise sets the `ARGDATA_FD` environment variable.
+	fn argdata(&mut self, value: &dyn Argdata) -> io::Result<&mut Command>;
+}
+
+/// Numbers the file descriptors sequentially from [`FIRST_FD`].
+struct ChildFds(Vec<fd::Fd>);
+
+impl fd::FdMapping for ChildFds {
+	fn map(&mut self, fd: fd::Fd) -> u32 {
+		self.0.map(fd) + FIRST_FD as u32
+	}
+}
+
+impl CommandExt for Command {
+	fn argdata(&mut self, value: &dyn Argdata) -> io::Result<&mut Command> {
+		if self.get_envs().any(|(key, _)| key == FD_VAR) {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"Command already has argdata",
+			));
+		}
+		let mut data = Vec::with_capacity(value.serialized_length());
+		let mut fds = ChildFds(Vec::new());
+		value.serialize(&mut data, Some(&mut fds))?;
+		let mut fds = fds.0;
+
+		let argdata_fd = FIRST_FD + fds.len() as c_int;
+		self.env(FD_VAR, argdata_fd.to_string());
+		// The memfd is created by the child, as every child has to own its own.
+		fds.push(fd::Fd(-1));
+
+		// Allocated here, as the child may not allocate.
+		let mut temporary = vec![0; fds.len()];
+		let end = FIRST_FD + fds.len() as c_int;
+		unsafe {
+			self.pre_exec(move || {
+				let memfd = cvt(libc::memfd_create(
+					b"argdata\0".as_ptr() as *const c_char,
+					libc::MFD_CLOEXEC,
+				))?;
+				write_all(memfd, &data)?;
+				if libc::lseek(memfd, 0, libc::SEEK_SET) < 0 {
+					return Err(io::Error::last_os_error());
+				}
+				*fds.last_mut().unwrap() = fd::Fd(memfd);
+				// Move all file descriptors out of the way first, as they
+				// might be in the range they are moved to.
+				for (temporary, fd) in temporary.iter_mut().zip(&fds) {
+					*temporary = cvt(libc::fcntl(fd.0, libc::F_DUPFD_CLOEXEC, end))?;
+				}
+				for (i, &temporary) in temporary.iter().enumerate() {
+					cvt(libc::dup2(temporary, FIRST_FD + i as c_int))?;
+				}
+				cvt(libc::fcntl(argdata_fd, libc::F_SETOWN, libc::getpid()))?;
+				close_on_exec_from(end)
+			})
+		};
+		Ok(self)
+	}
+}
+
+fn cvt(result: c_int) -> io::Result<c_int> {
+	if result < 0 {
+		Err(io::Error::last_os_error())
+	} else {
+		Ok(result)
+	}
+}
+
+/// Write all of `data` to `fd`, without allocating.
+unsafe fn write_all(fd: c_int, mut data: &[u8]) -> io::Result<()> {
+	while !data.is_empty() {
+		match libc::write(fd, data.as_ptr() as *const libc::c_void, data.len()) {
+			n if n >= 0 => data = &data[n as usize..],
+			_ => {
+				let error = io::Error::last_os_error();
+				if error.kind() != io::ErrorKind::Interrupted {
+					return Err(error);
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
+/// The number of file descriptors [`close_on_exec_from`] handles when it
+/// can't find out which ones are open.
+const FALLBACK_FDS: c_int = 1 << 16;
+
+/// Set the close-on-exec flag on all file descriptors starting at `start`.
+///
+/// Only async-signal-safe functions are used, as this runs in the child.
+unsafe fn close_on_exec_from(start: c_int) -> io::Result<()> {
+	let flags = libc::CLOSE_RANGE_CLOEXEC;
+	if libc::syscall(libc::SYS_close_range, start as c_uint, c_uint::MAX, flags) == 0 {
+		return Ok(());
+	}
+	// close_range is not available before Linux 5.11.
+	if close_on_exec_from_proc(start).is_ok() {
+		return Ok(());
+	}
+	// Without /proc, try all possible file descriptors. The limit can be
+	// very high, so give up after a while.
+	let mut limit: libc::rlimit = std::mem::zeroed();
+	cvt(libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit))?;
+	let end = limit.rlim_cur.min(FALLBACK_FDS as libc::rlim_t) as c_int;
+	for fd in start..end {
+		libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+	}
+	Ok(())
+}
+
+/// Set the close-on-exec flag on the file descriptors listed in
+/// `/proc/self/fd`, starting at `start`.
+///
+/// Uses `getdents64` directly, as `readdir` may allocate.
+unsafe fn close_on_exec_from_proc(start: c_int) -> io::Result<()> {
+	let dir = cvt(libc::open(
+		b"/proc/self/fd\0".as_ptr() as *const c_char,
+		libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+	))?;
+	// Aligned for the linux_dirent64 structs.
+	let mut buffer = [0u64; 512];
+	let result = loop {
+		let n = libc::syscall(
+			libc::SYS_getdents64,
+			dir,
+			buffer.as_mut_ptr(),
+			size_of_val(&buffer),
+		);
+		if n <= 0 {
+			break if n == 0 {
+				Ok(())
+			} else {
+				Err(io::Error::last_os_error())
+			};
+		}
+		let entries = std::slice::from_raw_parts(buffer.as_ptr() as *const u8, n as usize);
+		let mut pos = 0;
+		while pos < entries.len() {
+			// struct linux_dirent64: u64 d_ino, i64 d_off, u16 d_reclen,
+			// u8 d_type, and the nul-terminated d_name.
+			let reclen = u16::from_ne_bytes([entries[pos + 16], entries[pos + 17]]) as usize;
+			if let Some(fd) = parse_fd(&entries[pos + 19..pos + reclen]) {
+				if fd >= start && fd != dir {
+					libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+				}
+			}
+			pos += reclen;
+		}
+	};
+	libc::close(dir);
+	result
+}
+
+/// Parse a nul-terminated file descriptor number, without allocating.
+fn parse_fd(name: &[u8]) -> Option<c_int> {
+	let mut fd: c_int = 0;
+	let mut digits = name.iter().take_while(|&&b| b != 0).peekable();
+	digits.peek()?;
+	for &b in digits {
+		if !b.is_ascii_digit() {
+			return None;
+		}
+		fd = fd.checked_mul(10)?.checked_add((b - b'0') as c_int)?;
+	}
+	Some(fd)
+}
+
+#[test]
+fn command_argdata_test() {
+	use crate::OwnedArgdata;
+	use std::io::Read;
+	use std::os::unix::{io::AsRawFd, net::UnixStream};
+
+	let (a, mut b) = UnixStream::pair().unwrap();
+	// Not close-on-exec, so it would be inherited if it wasn't closed.
+	let leaked = unsafe { libc::dup(a.as_raw_fd()) };
+	let value = OwnedArgdata::Seq(vec![fd::Fd(a.as_raw_fd()).into()]);
+	let output = Command::new("sh")
+		.arg("-c")
+		.arg(format!(
+			"echo hi >&3; test -e /proc/self/fd/{} && echo leaked; echo $ARGDATA_FD; od -An -tx1 <&4",
+			leaked
+		))
+		.argdata(value.argdata())
+		.unwrap()
+		.output()
+		.unwrap();
+	unsafe { libc::close(leaked) };
+	drop(a);
+	assert_eq!(
+		String::from_utf8(output.stdout).unwrap(),
+		"4\n 07 85 03 00 00 00 03\n"
+	);
+	let mut s = String::new();
+	b.read_to_string(&mut s).unwrap();
+	assert_eq!(s, "hi\n");
+}
+
+/// Run in a child process by `grandchild_test`.
+#[test]
+#[ignore]
+fn print_argdata() {
+	let argdata = crate::env::argdata();
+	let argdata: &dyn Argdata = &argdata;
+	println!("\nargdata: {:?} {:?}", argdata, std::env::var_os(FD_VAR));
+}
+
+#[test]
+fn grandchild_test() {
+	let exe = std::env::current_exe().unwrap();
+	let args = [
+		"--exact",
+		"process::print_argdata",
+		"--ignored",
+		"--nocapture",
+	];
+	let output = |command: &mut Command| {
+		let output = command.argdata(&crate::int(5)).unwrap().output().unwrap();
+		let stdout = String::from_utf8(output.stdout).unwrap();
+		let lines: Vec<_> = stdout
+			.lines()
+			.filter(|l| l.starts_with("argdata:"))
+			.collect();
+		lines.join("\n")
+	};
+
+	// The child reads the argdata, and removes the variable.
+	assert_eq!(output(Command::new(&exe).args(args)), "argdata: 5 None");
+
+	// The child doesn't read the argdata, but its child must not use it.
+	assert_eq!(
+		output(
+			Command::new("sh")
+				.arg("-c")
+				.arg("test -e /proc/self/fd/$ARGDATA_FD && \"$0\" \"$@\"")
+				.arg(&exe)
+				.args(args)
+		),
+		"argdata: null None"
+	);
+}
+
+#[test]
+fn close_on_exec_test() {
+	assert_eq!(parse_fd(b"123\0\0"), Some(123));
+	assert_eq!(parse_fd(b".\0"), None);
+	assert_eq!(parse_fd(b"\0"), None);
+	assert_eq!(parse_fd(b"99999999999\0"), None);
+
+	let low = unsafe { libc::dup(2) };
+	let high = unsafe { libc::fcntl(low, libc::F_DUPFD, 1000) };
+	unsafe { close_on_exec_from_proc(high).unwrap() };
+	assert_eq!(unsafe { libc::fcntl(low, libc::F_GETFD) }, 0);
+	assert_eq!(
+		unsafe { libc::fcntl(high, libc::F_GETFD) },
+		libc::FD_CLOEXEC
+	);
+	unsafe {
+		libc::close(low);
+		libc::close(high);
+	}
+}
+
+#[test]
+fn command_argdata_twice_test() {
+	let mut command = Command::new("true");
+	command.argdata(&crate::int(1)).unwrap();
+	let error = command.argdata(&crate::int(2)).unwrap_err();
+	assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
@@ -9,18 +9,53 @@ pub struct ArgdataRef<'a, 'd: 'a> {
 
 impl<'a, 'd: 'a> ArgdataRef<'a, 'd> {
 	/// Create an ArgdataRef that refers to a substring of an encoded argdata value.
-	pub fn encoded(bytes: &'d [u8], convert_fd: &'a (dyn fd::ConvertFd + 'a)) -> ArgdataRef<'a, 'd> {
+	pub fn encoded(
+		bytes: &'d [u8],
+		convert_fd: &'a (dyn fd::ConvertFd + 'a),
+	) -> ArgdataRef<'a, 'd> {
 		ArgdataRef {
 			inner: Inner::Encoded(crate::encoded_with_fds(bytes, convert_fd)),
 		}
 	}
 
+	/// Create an ArgdataRef that refers to an encoded value, keeping its mode.
+	pub(crate) fn from_encoded(
+		value: values::EncodedArgdata<'d, &'a (dyn fd::ConvertFd + 'a)>,
+	) -> ArgdataRef<'a, 'd> {
+		ArgdataRef {
+			inner: Inner::Encoded(value),
+		}
+	}
+
 	/// Create an ArgdataRef that simply refers to something that implements Argdata.
 	pub fn reference(value: &'a (dyn Argdata<'d> + 'a)) -> ArgdataRef<'a, 'd> {
 		ArgdataRef {
 			inner: Inner::Reference(value),
 		}
 	}
+
+	/// Get a value contained in this value, as an ArgdataRef that lives as
+	/// long as this one would.
+	pub(crate) fn get_inner<E>(
+		self,
+		f: impl for<'b> FnOnce(&'b (dyn Argdata<'d> + 'b)) -> Result<ArgdataRef<'b, 'd>, E>,
+	) -> Result<ArgdataRef<'a, 'd>, E> {
+		match self.inner {
+			Inner::Reference(value) => f(value),
+			Inner::Encoded(value) => {
+				let convert_fd = *value.convert_fd();
+				match f(&value)?.inner {
+					Inner::Encoded(inner) => Ok(ArgdataRef::from_encoded(
+						crate::encoded_with_fds(inner.bytes(), convert_fd)
+							.strict(inner.is_strict()),
+					)),
+					Inner::Reference(_) => {
+						unreachable!("encoded argdata only contains encoded values")
+					}
+				}
+			}
+		}
+	}
 }
 
 enum Inner<'a, 'd: 'a> {
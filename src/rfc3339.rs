@@ -0,0 +1,187 @@
+//! Conversion between timestamps and RFC 3339 date-time strings.
+
+use crate::Timespec;
+
+/// Days since 1970-01-01 of the given date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let yoe = year.rem_euclid(400);
+	let mp = (month as i64 + 9) % 12;
+	let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	era * 146_097 + doe - 719_468
+}
+
+/// The date of the given number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+	let z = days + 719_468;
+	let era = z.div_euclid(146_097);
+	let doe = z.rem_euclid(146_097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+	let year = yoe + era * 400 + (month <= 2) as i64;
+	(year, month, day)
+}
+
+/// Whether `format` gives a valid RFC 3339 date-time for the timestamp,
+/// which is the case for the years 0 to 9999.
+#[cfg(feature = "json")]
+pub(crate) fn in_range(t: Timespec) -> bool {
+	(-62_167_219_200..253_402_300_800).contains(&t.sec)
+}
+
+/// Format a timestamp as an RFC 3339 date-time in UTC, like
+/// `2018-06-01T12:34:56.789Z`.
+///
+/// The fraction of a second is left out if it is zero, and trailing zeros are
+/// removed, unless `full_fraction` is set, in which case it's always written
+/// with nine digits.
+///
+/// Years outside of the range 0 to 9999 are written with as many digits as
+/// needed, which is not valid RFC 3339. See `in_range`.
+pub(crate) fn format(t: Timespec, full_fraction: bool) -> String {
+	let days = t.sec.div_euclid(86400);
+	let secs = t.sec.rem_euclid(86400);
+	let (year, month, day) = civil_from_days(days);
+	let mut s = format!(
+		"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+		year,
+		month,
+		day,
+		secs / 3600,
+		secs / 60 % 60,
+		secs % 60
+	);
+	if full_fraction {
+		s += &format!(".{:09}", t.nsec);
+	} else if t.nsec != 0 {
+		let frac = format!(".{:09}", t.nsec);
+		s += frac.trim_end_matches('0');
+	}
+	s.push('Z');
+	s
+}
+
+/// Parse an RFC 3339 date-time, like `2018-06-01T12:34:56.789+02:00`.
+///
+/// Fractions of nanoseconds are truncated. Leap seconds are not supported.
+pub(crate) fn parse(s: &str) -> Option<Timespec> {
+	let b = s.as_bytes();
+	let num = |range: std::ops::Range<usize>| -> Option<u32> {
+		let digits = b.get(range)?;
+		if !digits.iter().all(u8::is_ascii_digit) {
+			return None;
+		}
+		Some(digits.iter().fold(0, |n, d| n * 10 + (d - b'0') as u32))
+	};
+	let sep = |i: usize, c: &[u8]| b.get(i).is_some_and(|x| c.contains(x));
+
+	if !sep(4, b"-") || !sep(7, b"-") || !sep(10, b"Tt ") || !sep(13, b":") || !sep(16, b":") {
+		return None;
+	}
+	let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+	let (hour, minute, second) = (num(11..13)?, num(14..16)?, num(17..19)?);
+
+	let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+	let month_days = [31, 28 + leap as u32, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+	if !(1..=12).contains(&month) || day < 1 || day > month_days[month as usize - 1] {
+		return None;
+	}
+	if hour > 23 || minute > 59 || second > 59 {
+		return None;
+	}
+
+	let mut i = 19;
+	let mut nsec = 0;
+	if sep(i, b".") {
+		i += 1;
+		let start = i;
+		while b.get(i).is_some_and(u8::is_ascii_digit) {
+			if i - start < 9 {
+				nsec = nsec * 10 + (b[i] - b'0') as u32;
+			}
+			i += 1;
+		}
+		if i == start {
+			return None;
+		}
+		for _ in (i - start)..9 {
+			nsec *= 10;
+		}
+	}
+
+	let offset = match b.get(i)? {
+		b'Z' | b'z' if i + 1 == b.len() => 0,
+		sign @ (b'+' | b'-') if i + 6 == b.len() && sep(i + 3, b":") => {
+			let (h, m) = (num(i + 1..i + 3)?, num(i + 4..i + 6)?);
+			if h > 23 || m > 59 {
+				return None;
+			}
+			let offset = (h * 3600 + m * 60) as i64;
+			if *sign == b'-' {
+				-offset
+			} else {
+				offset
+			}
+		}
+		_ => return None,
+	};
+
+	let days = days_from_civil(year as i64, month, day);
+	let sec = days * 86400 + (hour * 3600 + minute * 60 + second) as i64 - offset;
+	Some(Timespec { sec, nsec })
+}
+
+#[test]
+fn rfc3339_test() {
+	let t = |sec, nsec| Timespec { sec, nsec };
+	assert_eq!(format(t(0, 0), false), "1970-01-01T00:00:00Z");
+	assert_eq!(format(t(0, 0), true), "1970-01-01T00:00:00.000000000Z");
+	assert_eq!(format(t(-1, 500_000_000), false), "1969-12-31T23:59:59.5Z");
+	assert_eq!(
+		format(t(1_528_000_000, 123), false),
+		"2018-06-03T04:26:40.000000123Z"
+	);
+	assert_eq!(format(t(951_782_400, 0), false), "2000-02-29T00:00:00Z");
+	assert_eq!(format(t(-62_167_219_200, 0), false), "0000-01-01T00:00:00Z");
+	assert_eq!(
+		format(t(253_402_300_800, 0), false),
+		"10000-01-01T00:00:00Z"
+	);
+	#[cfg(feature = "json")]
+	{
+		assert!(in_range(t(-62_167_219_200, 0)));
+		assert!(!in_range(t(-62_167_219_201, 0)));
+		assert!(!in_range(t(253_402_300_800, 0)));
+	}
+
+	for &(sec, nsec) in &[
+		(0, 0),
+		(-1, 500_000_000),
+		(1_528_000_000, 123),
+		(951_782_400, 0),
+		(-62_167_219_200, 0),
+		(253_402_300_799, 999_999_999),
+	] {
+		assert_eq!(parse(&format(t(sec, nsec), false)), Some(t(sec, nsec)));
+		assert_eq!(parse(&format(t(sec, nsec), true)), Some(t(sec, nsec)));
+	}
+
+	assert_eq!(
+		parse("2018-06-03T06:26:40.5+02:00"),
+		Some(t(1_528_000_000, 500_000_000))
+	);
+	assert_eq!(
+		parse("2018-06-03 04:26:40.1234567891z"),
+		Some(t(1_528_000_000, 123_456_789))
+	);
+	assert_eq!(parse("2018-06-03T04:26:40"), None);
+	assert_eq!(parse("2018-02-29T00:00:00Z"), None);
+	assert_eq!(parse("2018-06-03T04:26:60Z"), None);
+	assert_eq!(parse("2018-06-03T04:26:40.Z"), None);
+	assert_eq!(parse("2018-06-03T04:26:40+0200"), None);
+}
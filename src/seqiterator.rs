@@ -46,6 +46,20 @@ impl<'a, 'd: 'a> SeqIterator<'a, 'd> {
 	pub fn new(seq: &'a (dyn SeqIterable<'d> + 'a), cookie: usize) -> Self {
 		SeqIterator { seq, cookie }
 	}
+
+	/// Get the element at the given index, counting from the current position
+	/// of the iterator.
+	///
+	/// This does not advance the iterator.
+	pub fn get(&self, index: usize) -> Result<Option<ArgdataRef<'a, 'd>>, ReadError> {
+		let mut it = *self;
+		for _ in 0..index {
+			if it.next().transpose()?.is_none() {
+				return Ok(None);
+			}
+		}
+		it.next().transpose()
+	}
 }
 
 impl<'a, 'd: 'a> Iterator for SeqIterator<'a, 'd> {
@@ -60,3 +74,16 @@ impl std::fmt::Debug for SeqIterator<'_, '_> {
 		write!(f, "SeqIterator(.., {})", self.cookie)
 	}
 }
+
+#[test]
+fn seq_iterator_get_test() {
+	use crate::{encoded, Argdata};
+	let seq = encoded(b"\x07\x81\x02\x82\x02\x01\x85");
+	let mut it = seq.read_seq().unwrap();
+	assert_eq!(it.get(0).unwrap().unwrap().read_bool(), Ok(false));
+	assert_eq!(it.get(1).unwrap().unwrap().read_bool(), Ok(true));
+	assert_eq!(it.get(2).err(), Some(ReadError::InvalidSubfield));
+	it.next();
+	assert_eq!(it.get(0).unwrap().unwrap().read_bool(), Ok(true));
+	assert_eq!(encoded(b"\x07").read_seq().unwrap().get(0).err(), None);
+}
@@ -0,0 +1,816 @@
+//! # Mapping
+//!
+//! The serde data model is mapped onto argdata as follows:
+//!
+//!  - `bool` is a bool, and all integer types are ints.
+//!  - `f32` and `f64` are floats.
+//!  - `char` and strings are strings, and byte arrays are binary blobs.
+//!  - `None`, `()` and unit structs are null. `Some(x)` is just `x`.
+//!  - Sequences, tuples and tuple structs are seqs.
+//!  - Maps are maps, and structs are maps with their field names as string keys.
+//!  - Unit variants are strings containing the name of the variant.
+//!    Other enum variants are a map containing a single key-value pair: the
+//!    name of the variant, and its contents.
+//!
+//! Argdata types that serde doesn't know about use newtype structs with
+//! special names:
+//!
+//!  - A timestamp is a newtype struct named [`TIMESTAMP`], containing a
+//!    tuple of the seconds (`i64`) and nanoseconds (`u32`) since the unix
+//!    epoch.
+//!  - A file descriptor is a newtype struct named [`FD`], containing the file
+//!    descriptor as `i32`.
+//!  - An integer of arbitrary length is a newtype struct named [`BIGINT`],
+//!    containing its big-endian 2's-complement representation as bytes.
+//!    (Integers up to 128 bits are also directly supported as `i128` and `u128`.)
+//!
+//! [`Timespec`] and [`Fd`](fd::Fd) (de)serialize themselves as such newtype
+//! structs, so can simply be used as fields.
+
+use crate::{
+	fd,
+	subfield::{subfield_length, write_subfield_length},
+	Argdata, ArgdataExt, IntValue, Timespec,
+};
+use serde::ser::{self, Serialize};
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{self, Write as _};
+
+/// Name of the newtype struct representing a timestamp.
+pub const TIMESTAMP: &str = "$argdata::Timestamp";
+
+/// Name of the newtype struct representing a file descriptor.
+pub const FD: &str = "$argdata::Fd";
+
+/// Name of the newtype struct representing an integer of arbitrary length.
+pub const BIGINT: &str = "$argdata::BigInt";
+
+/// Serializes any `Serialize` value as argdata.
+///
+/// See the [module documentation](index.html#mapping) for how values are mapped onto argdata.
+///
+/// Every element of a map or seq is prefixed with its length. To know those
+/// lengths up front, each element of the outermost map or seq is serialized
+/// twice: first only to measure the lengths of everything in it, and then
+/// to write it. So values must serialize the same way every time. Only the
+/// contents of an enum variant at the top level are buffered instead.
+pub struct Serializer<'a> {
+	output: Output<'a>,
+	fd_map: Option<&'a mut dyn fd::FdMapping>,
+	/// The measured lengths of the elements and variant contents in the
+	/// element that is being written, in the order in which they are written.
+	lengths: Vec<usize>,
+	/// The index in `lengths` of the next one to be written.
+	next: usize,
+}
+
+/// Where a [`Serializer`] writes to, keeping track of the number of bytes
+/// written.
+struct Output<'a> {
+	target: Target<'a>,
+	position: usize,
+}
+
+enum Target<'a> {
+	/// Only measure the lengths.
+	Measure,
+	Writer(&'a mut dyn io::Write),
+	/// The contents of an enum variant at the top level, of which the length
+	/// isn't known yet.
+	Buffer(Vec<u8>),
+}
+
+impl<'a> io::Write for Output<'a> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = match &mut self.target {
+			Target::Measure => buf.len(),
+			Target::Writer(w) => w.write(buf)?,
+			Target::Buffer(b) => b.write(buf)?,
+		};
+		self.position += n;
+		Ok(n)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		match &mut self.target {
+			Target::Writer(w) => w.flush(),
+			_ => Ok(()),
+		}
+	}
+}
+
+/// An error while serializing argdata.
+#[derive(Debug)]
+pub enum Error {
+	/// Writing the output failed.
+	Io(io::Error),
+
+	/// The value could not be serialized.
+	Message(String),
+}
+
+impl<'a> Serializer<'a> {
+	/// Create a serializer that writes to the given writer.
+	///
+	/// File descriptors are mapped using `fd_map`, like in [`Argdata::serialize`].
+	pub fn new(
+		writer: &'a mut dyn io::Write,
+		fd_map: Option<&'a mut dyn fd::FdMapping>,
+	) -> Serializer<'a> {
+		Serializer {
+			output: Output {
+				target: Target::Writer(writer),
+				position: 0,
+			},
+			fd_map,
+			lengths: Vec::new(),
+			next: 0,
+		}
+	}
+
+	fn measuring(&self) -> bool {
+		matches!(self.output.target, Target::Measure)
+	}
+
+	fn write(&mut self, value: &dyn Argdata) -> Result<(), Error> {
+		if self.measuring() {
+			self.output.position += value.serialized_length();
+		} else {
+			value.serialize(&mut self.output, self.fd_map.as_mut().map(|x| *x as _))?;
+		}
+		Ok(())
+	}
+
+	/// Serialize a value that is prefixed with its length.
+	fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+		if self.measuring() {
+			let i = self.begin_measure();
+			value.serialize(&mut *self)?;
+			self.end_measure(i);
+			return Ok(());
+		}
+		if self.next == self.lengths.len() {
+			// This is an element of the outermost map or seq, so measure
+			// everything in it first.
+			let mut measure = Serializer {
+				output: Output {
+					target: Target::Measure,
+					position: 0,
+				},
+				fd_map: None,
+				lengths: std::mem::take(&mut self.lengths),
+				next: 0,
+			};
+			measure.lengths.clear();
+			measure.element(value)?;
+			self.lengths = measure.lengths;
+			self.next = 0;
+		}
+		let end = self.begin_write()?;
+		value.serialize(&mut *self)?;
+		self.end_write(end)
+	}
+
+	/// Start measuring the length of an element or variant contents.
+	/// Returns the index of its length.
+	fn begin_measure(&mut self) -> usize {
+		self.lengths.push(self.output.position);
+		self.lengths.len() - 1
+	}
+
+	/// Finish measuring, and count the length that will be written before it.
+	fn end_measure(&mut self, i: usize) {
+		let start = self.lengths[i];
+		self.lengths[i] = self.output.position - start;
+		self.output.position = start + subfield_length(self.lengths[i]);
+	}
+
+	/// Write the measured length of an element or variant contents.
+	/// Returns the position where it should end.
+	fn begin_write(&mut self) -> Result<usize, Error> {
+		let length = self.lengths[self.next];
+		self.next += 1;
+		write_subfield_length(length, &mut self.output)?;
+		Ok(self.output.position + length)
+	}
+
+	/// Check that the element or variant contents were as long as measured.
+	fn end_write(&mut self, end: usize) -> Result<(), Error> {
+		if self.output.position != end {
+			return Err(Error::Message(
+				"value serialized differently when measured".to_string(),
+			));
+		}
+		Ok(())
+	}
+
+	fn write_int(&mut self, value: i128) -> Result<(), Error> {
+		if let Ok(v) = value.try_into() {
+			self.write(&crate::int::<i64>(v))
+		} else if let Ok(v) = value.try_into() {
+			self.write(&crate::int::<u64>(v))
+		} else {
+			// Strip the redundant leading bytes.
+			let bytes = value.to_be_bytes();
+			let sign = if value < 0 { 0xFF } else { 0 };
+			let mut n = bytes.iter().take_while(|&&b| b == sign).count();
+			if (bytes[n] >= 0x80) != (value < 0) {
+				n -= 1;
+			}
+			self.write(&crate::bigint(&bytes[n..]))
+		}
+	}
+
+	fn buffered<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<Vec<u8>, Error> {
+		let mut buf = Vec::new();
+		value.serialize(&mut Serializer::new(
+			&mut buf,
+			self.fd_map.as_mut().map(|x| *x as _),
+		))?;
+		Ok(buf)
+	}
+}
+
+/// Serialize a value as argdata to the given writer.
+///
+/// File descriptors are mapped using `fd_map`, like in [`Argdata::serialize`].
+///
+/// Elements of the outermost map or seq are written as soon as they are
+/// serialized, so part of the value may have been written if this fails.
+pub fn to_writer<T: Serialize + ?Sized>(
+	writer: &mut dyn io::Write,
+	value: &T,
+	fd_map: Option<&mut dyn fd::FdMapping>,
+) -> Result<(), Error> {
+	value.serialize(&mut Serializer::new(writer, fd_map.map(|x| x as _)))
+}
+
+/// Serialize a value as argdata.
+///
+/// File descriptors are encoded as is, without any mapping.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+	let mut buf = Vec::new();
+	to_writer(&mut buf, value, None)?;
+	Ok(buf)
+}
+
+impl<'a, 'b> ser::Serializer for &'b mut Serializer<'a> {
+	type Ok = ();
+	type Error = Error;
+	type SerializeSeq = Compound<'b, 'a>;
+	type SerializeTuple = Compound<'b, 'a>;
+	type SerializeTupleStruct = Compound<'b, 'a>;
+	type SerializeTupleVariant = Compound<'b, 'a>;
+	type SerializeMap = Compound<'b, 'a>;
+	type SerializeStruct = Compound<'b, 'a>;
+	type SerializeStructVariant = Compound<'b, 'a>;
+
+	fn serialize_bool(self, v: bool) -> Result<(), Error> {
+		self.write(&crate::bool(v))
+	}
+
+	fn serialize_i8(self, v: i8) -> Result<(), Error> {
+		self.write(&crate::int(v))
+	}
+
+	fn serialize_i16(self, v: i16) -> Result<(), Error> {
+		self.write(&crate::int(v))
+	}
+
+	fn serialize_i32(self, v: i32) -> Result<(), Error> {
+		self.write(&crate::int(v))
+	}
+
+	fn serialize_i64(self, v: i64) -> Result<(), Error> {
+		self.write(&crate::int(v))
+	}
+
+	fn serialize_i128(self, v: i128) -> Result<(), Error> {
+		self.write_int(v)
+	}
+
+	fn serialize_u8(self, v: u8) -> Result<(), Error> {
+		self.write(&crate::int(v))
+	}
+
+	fn serialize_u16(self, v: u16) -> Result<(), Error> {
+		self.write(&crate::int(v))
+	}
+
+	fn serialize_u32(self, v: u32) -> Result<(), Error> {
+		self.write(&crate::int(v))
+	}
+
+	fn serialize_u64(self, v: u64) -> Result<(), Error> {
+		self.write(&crate::int(v))
+	}
+
+	fn serialize_u128(self, v: u128) -> Result<(), Error> {
+		if let Ok(v) = v.try_into() {
+			self.write_int(v)
+		} else {
+			let mut bytes = [0; 17];
+			bytes[1..].copy_from_slice(&v.to_be_bytes());
+			self.write(&crate::bigint(&bytes))
+		}
+	}
+
+	fn serialize_f32(self, v: f32) -> Result<(), Error> {
+		self.write(&crate::float(v))
+	}
+
+	fn serialize_f64(self, v: f64) -> Result<(), Error> {
+		self.write(&crate::float(v))
+	}
+
+	fn serialize_char(self, v: char) -> Result<(), Error> {
+		self.write(&crate::str(v.encode_utf8(&mut [0; 4])))
+	}
+
+	fn serialize_str(self, v: &str) -> Result<(), Error> {
+		self.write(&crate::str(v))
+	}
+
+	fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+		self.write(&crate::binary(v))
+	}
+
+	fn serialize_none(self) -> Result<(), Error> {
+		self.write(&crate::null())
+	}
+
+	fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+		value.serialize(self)
+	}
+
+	fn serialize_unit(self) -> Result<(), Error> {
+		self.write(&crate::null())
+	}
+
+	fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+		self.write(&crate::null())
+	}
+
+	fn serialize_unit_variant(
+		self,
+		_name: &'static str,
+		_variant_index: u32,
+		variant: &'static str,
+	) -> Result<(), Error> {
+		self.write(&crate::str(variant))
+	}
+
+	fn serialize_newtype_struct<T: Serialize + ?Sized>(
+		self,
+		name: &'static str,
+		value: &T,
+	) -> Result<(), Error> {
+		match name {
+			TIMESTAMP => {
+				let inner = self.buffered(value)?;
+				let inner = crate::encoded(&inner);
+				let ints = inner.read_seq().ok().and_then(|it| {
+					it.map(|v| v.ok()?.read_int_value().ok())
+						.collect::<Option<Vec<IntValue>>>()
+				});
+				let timespec = match ints.as_deref() {
+					Some(&[sec, nsec]) => sec.try_into().ok().and_then(|sec| {
+						let nsec = nsec.try_into().ok().filter(|&n| n < 1_000_000_000)?;
+						Some(Timespec { sec, nsec })
+					}),
+					_ => None,
+				};
+				self.write(&crate::timestamp(
+					timespec.ok_or_else(|| invalid(TIMESTAMP))?,
+				))
+			}
+			FD => {
+				let inner = self.buffered(value)?;
+				let fd = crate::encoded(&inner).read_int().map_err(|_| invalid(FD))?;
+				self.write(&fd::Fd(fd))
+			}
+			BIGINT => {
+				let inner = self.buffered(value)?;
+				let bytes = crate::encoded(&inner)
+					.read_binary()
+					.map_err(|_| invalid(BIGINT))?;
+				self.write(&crate::bigint(bytes))
+			}
+			_ => value.serialize(self),
+		}
+	}
+
+	fn serialize_newtype_variant<T: Serialize + ?Sized>(
+		self,
+		_name: &'static str,
+		_variant_index: u32,
+		variant: &'static str,
+		value: &T,
+	) -> Result<(), Error> {
+		let mut compound = Compound::new(self, 6, None)?;
+		compound.element(variant)?;
+		compound.element(value)?;
+		compound.end()
+	}
+
+	fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'b, 'a>, Error> {
+		Compound::new(self, 7, None)
+	}
+
+	fn serialize_tuple(self, _len: usize) -> Result<Compound<'b, 'a>, Error> {
+		Compound::new(self, 7, None)
+	}
+
+	fn serialize_tuple_struct(
+		self,
+		_name: &'static str,
+		_len: usize,
+	) -> Result<Compound<'b, 'a>, Error> {
+		Compound::new(self, 7, None)
+	}
+
+	fn serialize_tuple_variant(
+		self,
+		_name: &'static str,
+		_variant_index: u32,
+		variant: &'static str,
+		_len: usize,
+	) -> Result<Compound<'b, 'a>, Error> {
+		Compound::new(self, 7, Some(variant))
+	}
+
+	fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'b, 'a>, Error> {
+		Compound::new(self, 6, None)
+	}
+
+	fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound<'b, 'a>, Error> {
+		Compound::new(self, 6, None)
+	}
+
+	fn serialize_struct_variant(
+		self,
+		_name: &'static str,
+		_variant_index: u32,
+		variant: &'static str,
+		_len: usize,
+	) -> Result<Compound<'b, 'a>, Error> {
+		Compound::new(self, 6, Some(variant))
+	}
+}
+
+fn invalid(name: &str) -> Error {
+	Error::Message(format!("invalid contents of {}", name))
+}
+
+/// Serializes the elements of a seq or map.
+pub struct Compound<'b, 'a> {
+	ser: &'b mut Serializer<'a>,
+	/// How the contents of an enum variant are finished.
+	variant: Option<Variant<'a>>,
+}
+
+enum Variant<'a> {
+	/// The index of their length in `lengths` while measuring, or the
+	/// position where they end while writing.
+	Measured(usize),
+	/// The target to write the buffered contents to.
+	Buffered(Target<'a>),
+}
+
+impl<'b, 'a> Compound<'b, 'a> {
+	fn new(
+		ser: &'b mut Serializer<'a>,
+		tag: u8,
+		variant: Option<&'static str>,
+	) -> Result<Self, Error> {
+		let variant = match variant {
+			Some(variant) => {
+				// A map with the name of the variant as its only key.
+				let variant = crate::str(variant);
+				ser.output.write_all(&[6])?;
+				write_subfield_length(variant.serialized_length(), &mut ser.output)?;
+				ser.write(&variant)?;
+				Some(if ser.measuring() {
+					Variant::Measured(ser.begin_measure())
+				} else if ser.next < ser.lengths.len() {
+					Variant::Measured(ser.begin_write()?)
+				} else {
+					let buffer = Target::Buffer(Vec::new());
+					Variant::Buffered(std::mem::replace(&mut ser.output.target, buffer))
+				})
+			}
+			None => None,
+		};
+		ser.output.write_all(&[tag])?;
+		Ok(Compound { ser, variant })
+	}
+
+	fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+		self.ser.element(value)
+	}
+
+	fn end(self) -> Result<(), Error> {
+		match self.variant {
+			Some(Variant::Measured(i)) if self.ser.measuring() => self.ser.end_measure(i),
+			Some(Variant::Measured(end)) => self.ser.end_write(end)?,
+			Some(Variant::Buffered(target)) => {
+				let buffer = match std::mem::replace(&mut self.ser.output.target, target) {
+					Target::Buffer(buffer) => buffer,
+					_ => unreachable!(),
+				};
+				write_subfield_length(buffer.len(), &mut self.ser.output)?;
+				self.ser.output.write_all(&buffer)?;
+			}
+			None => {}
+		}
+		Ok(())
+	}
+}
+
+impl<'b, 'a> ser::SerializeSeq for Compound<'b, 'a> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+		self.element(value)
+	}
+
+	fn end(self) -> Result<(), Error> {
+		Compound::end(self)
+	}
+}
+
+impl<'b, 'a> ser::SerializeTuple for Compound<'b, 'a> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+		self.element(value)
+	}
+
+	fn end(self) -> Result<(), Error> {
+		Compound::end(self)
+	}
+}
+
+impl<'b, 'a> ser::SerializeTupleStruct for Compound<'b, 'a> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+		self.element(value)
+	}
+
+	fn end(self) -> Result<(), Error> {
+		Compound::end(self)
+	}
+}
+
+impl<'b, 'a> ser::SerializeTupleVariant for Compound<'b, 'a> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+		self.element(value)
+	}
+
+	fn end(self) -> Result<(), Error> {
+		Compound::end(self)
+	}
+}
+
+impl<'b, 'a> ser::SerializeMap for Compound<'b, 'a> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+		self.element(key)
+	}
+
+	fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+		self.element(value)
+	}
+
+	fn end(self) -> Result<(), Error> {
+		Compound::end(self)
+	}
+}
+
+impl<'b, 'a> ser::SerializeStruct for Compound<'b, 'a> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T: Serialize + ?Sized>(
+		&mut self,
+		key: &'static str,
+		value: &T,
+	) -> Result<(), Error> {
+		self.element(key)?;
+		self.element(value)
+	}
+
+	fn end(self) -> Result<(), Error> {
+		Compound::end(self)
+	}
+}
+
+impl<'b, 'a> ser::SerializeStructVariant for Compound<'b, 'a> {
+	type Ok = ();
+	type Error = Error;
+
+	fn serialize_field<T: Serialize + ?Sized>(
+		&mut self,
+		key: &'static str,
+		value: &T,
+	) -> Result<(), Error> {
+		self.element(key)?;
+		self.element(value)
+	}
+
+	fn end(self) -> Result<(), Error> {
+		Compound::end(self)
+	}
+}
+
+impl Serialize for Timespec {
+	fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_newtype_struct(TIMESTAMP, &(self.sec, self.nsec))
+	}
+}
+
+impl Serialize for fd::Fd {
+	fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_newtype_struct(FD, &self.0)
+	}
+}
+
+impl ser::Error for Error {
+	fn custom<T: fmt::Display>(msg: T) -> Error {
+		Error::Message(msg.to_string())
+	}
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Io(e) => e.fmt(f),
+			Error::Message(msg) => f.write_str(msg),
+		}
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Error {
+		Error::Io(e)
+	}
+}
+
+impl fmt::Debug for Serializer<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Serializer(..)")
+	}
+}
+
+impl fmt::Debug for Compound<'_, '_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Compound(..)")
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use serde::Serialize;
+	use std::collections::BTreeMap;
+
+	#[derive(Serialize)]
+	struct Config<'a> {
+		name: &'a str,
+		port: u16,
+		verbose: bool,
+		ratio: f64,
+		tags: Vec<&'a str>,
+		missing: Option<i32>,
+		kind: Kind,
+		started: Timespec,
+		socket: fd::Fd,
+	}
+
+	#[derive(Serialize)]
+	enum Kind {
+		Plain,
+		Wrapped(i32),
+		Pair(i32, i32),
+		Named { x: i32 },
+	}
+
+	fn debug(data: &[u8]) -> String {
+		format!(
+			"{:?}",
+			&crate::encoded_with_fds(data, fd::Identity) as &dyn Argdata
+		)
+	}
+
+	#[test]
+	fn serialize_struct_test() {
+		let config = Config {
+			name: "server",
+			port: 8080,
+			verbose: true,
+			ratio: 0.5,
+			tags: vec!["a", "b"],
+			missing: None,
+			kind: Kind::Plain,
+			started: Timespec {
+				sec: 485,
+				nsec: 88045333,
+			},
+			socket: fd::Fd(7),
+		};
+
+		let mut buf = Vec::new();
+		let mut fds = Vec::new();
+		to_writer(&mut buf, &config, Some(&mut fds)).unwrap();
+		assert_eq!(&fds, &[fd::Fd(7)]);
+		assert_eq!(
+			debug(&buf),
+			"{\"name\": \"server\", \"port\": 8080, \"verbose\": true, \"ratio\": 0.5, \
+			 \"tags\": [\"a\", \"b\"], \"missing\": null, \"kind\": \"Plain\", \
+			 \"started\": timestamp(485, 88045333), \"socket\": fd(0)}"
+		);
+	}
+
+	#[test]
+	fn serialize_enum_test() {
+		let kinds = vec![
+			Kind::Plain,
+			Kind::Wrapped(1),
+			Kind::Pair(2, 3),
+			Kind::Named { x: 4 },
+		];
+		assert_eq!(
+			debug(&to_vec(&kinds).unwrap()),
+			"[\"Plain\", {\"Wrapped\": 1}, {\"Pair\": [2, 3]}, {\"Named\": {\"x\": 4}}]"
+		);
+	}
+
+	#[test]
+	fn serialize_int_test() {
+		let mut map = BTreeMap::new();
+		map.insert(1u8, i128::MIN);
+		map.insert(2u8, u128::MAX as i128);
+		assert_eq!(
+			debug(&to_vec(&map).unwrap()),
+			"{1: -170141183460469231731687303715884105728, 2: -1}"
+		);
+		assert_eq!(
+			debug(&to_vec(&(u128::MAX, 1u128 << 64, -1i128)).unwrap()),
+			"[340282366920938463463374607431768211455, 18446744073709551616, -1]"
+		);
+	}
+
+	#[test]
+	fn serialize_nested_test() {
+		#[derive(Serialize)]
+		struct Nested(Vec<Nested>);
+
+		let mut nested = Nested(vec![]);
+		let mut text = "[]".to_string();
+		for _ in 0..200 {
+			nested = Nested(vec![nested]);
+			text = format!("[{}]", text);
+		}
+		assert_eq!(debug(&to_vec(&nested).unwrap()), text);
+
+		// A variant at the top level, with variants inside.
+		let kinds = Kind::Named { x: 1 };
+		assert_eq!(debug(&to_vec(&kinds).unwrap()), "{\"Named\": {\"x\": 1}}");
+		let pair = (Kind::Pair(1, 2), vec![Kind::Wrapped(3)]);
+		assert_eq!(
+			debug(&to_vec(&pair).unwrap()),
+			"[{\"Pair\": [1, 2]}, [{\"Wrapped\": 3}]]"
+		);
+	}
+
+	#[test]
+	fn serialize_changing_test() {
+		struct Changing(std::cell::Cell<u32>);
+
+		impl Serialize for Changing {
+			fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				self.0.set(self.0.get() + 1);
+				serializer.serialize_str(&"x".repeat(self.0.get() as usize))
+			}
+		}
+
+		assert_eq!(
+			to_vec(&[Changing(Default::default())])
+				.unwrap_err()
+				.to_string(),
+			"value serialized differently when measured"
+		);
+	}
+}
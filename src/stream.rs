@@ -0,0 +1,418 @@
+//! Argdata is read from an `io::Read` one piece at a time, so a value doesn't
+//! need to fit in memory. Maps and seqs are read one element at a time, and
+//! binary blobs are themselves `io::Read` streams. All other values are
+//! small enough to be read completely, and are returned as an `OwnedArgdata`.
+//!
+//! Example:
+//!
+//! ```
+//! # use argdata::{fd, stream};
+//! # fn handle(_: &mut dyn std::io::Read) {}
+//! # fn example(mut input: &[u8]) -> std::io::Result<()> {
+//! if let stream::Value::Map(mut map) = stream::read(&mut input, &fd::NoConvert)? {
+//!     while let Some((key, value)) = map.next_entry()? {
+//!         if let stream::Value::Binary(mut blob) = value {
+//!             handle(&mut blob);
+//!         }
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Errors in the data are reported as an `io::Error` of kind `InvalidData`
+//! containing a [`ReadError`], and a stream that ends in the middle of a
+//! value as an `io::Error` of kind `UnexpectedEof`.
+
+use crate::{fd, subfield::read_subfield_length, OwnedArgdata, ReadError};
+use std::io;
+
+/// A value that is being read from a stream.
+///
+/// Elements of maps and seqs, and the contents of a binary blob, don't need
+/// to be read completely. The unread part is skipped when the next element is
+/// read.
+pub enum Value<'r> {
+	Binary(BinaryReader<'r>),
+	Map(MapReader<'r>),
+	Seq(SeqReader<'r>),
+	/// Any other value, which is read completely.
+	Other(OwnedArgdata),
+}
+
+/// A binary blob that is being read from a stream.
+pub struct BinaryReader<'r> {
+	source: Source<'r>,
+}
+
+/// A map that is being read from a stream.
+pub struct MapReader<'r> {
+	frame: Frame<'r>,
+}
+
+/// A seq that is being read from a stream.
+pub struct SeqReader<'r> {
+	frame: Frame<'r>,
+}
+
+/// Start reading an argdata value from the given stream.
+///
+/// The value extends until the end of the stream.
+///
+/// Encoded file descriptor numbers are converted using `convert_fd`.
+pub fn read<'r>(
+	reader: &'r mut dyn io::Read,
+	convert_fd: &'r dyn fd::ConvertFd,
+) -> io::Result<Value<'r>> {
+	Value::read(Source {
+		reader,
+		remaining: None,
+		convert_fd,
+	})
+}
+
+impl<'r> Value<'r> {
+	fn read(mut source: Source<'r>) -> io::Result<Value<'r>> {
+		let mut tag = [0u8];
+		if source.read_all(&mut tag)? == 0 {
+			return Ok(Value::Other(OwnedArgdata::Null));
+		}
+		Ok(match tag[0] {
+			1 => Value::Binary(BinaryReader { source }),
+			6 => Value::Map(MapReader {
+				frame: Frame::new(source),
+			}),
+			7 => Value::Seq(SeqReader {
+				frame: Frame::new(source),
+			}),
+			_ => {
+				let mut data = tag.to_vec();
+				io::Read::read_to_end(&mut source, &mut data)?;
+				let value = crate::encoded_with_fds(&data[..], source.convert_fd)
+					.to_owned()
+					.map_err(invalid_data)?;
+				Value::Other(value)
+			}
+		})
+	}
+
+	/// Read the rest of the value completely into memory.
+	pub fn into_owned(self) -> io::Result<OwnedArgdata> {
+		Ok(match self {
+			Value::Binary(mut b) => {
+				let mut data = Vec::new();
+				io::Read::read_to_end(&mut b, &mut data)?;
+				OwnedArgdata::Binary(data)
+			}
+			Value::Map(mut m) => {
+				let mut entries = Vec::new();
+				while let Some((key, value)) = m.next_entry()? {
+					entries.push((key, value.into_owned()?));
+				}
+				OwnedArgdata::Map(entries)
+			}
+			Value::Seq(mut s) => {
+				let mut elements = Vec::new();
+				while let Some(value) = s.next_element()? {
+					elements.push(value.into_owned()?);
+				}
+				OwnedArgdata::Seq(elements)
+			}
+			Value::Other(v) => v,
+		})
+	}
+}
+
+impl<'r> BinaryReader<'r> {
+	/// The number of bytes that are left, if known.
+	///
+	/// This is only unknown for a blob that isn't contained in a map or seq,
+	/// as it then extends until the end of the stream.
+	pub fn remaining(&self) -> Option<u64> {
+		self.source.remaining
+	}
+}
+
+impl<'r> io::Read for BinaryReader<'r> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		self.source.read(buf)
+	}
+}
+
+impl<'r> MapReader<'r> {
+	/// Read the next entry of the map.
+	///
+	/// The key is read completely, but the value can be read incrementally.
+	pub fn next_entry(&mut self) -> io::Result<Option<(OwnedArgdata, Value<'_>)>> {
+		let key = match self.frame.next()? {
+			Some(key) => key.into_owned()?,
+			None => return Ok(None),
+		};
+		match self.frame.next()? {
+			Some(value) => Ok(Some((key, value))),
+			None => Err(invalid_data(ReadError::InvalidKeyValuePair)),
+		}
+	}
+}
+
+impl<'r> SeqReader<'r> {
+	/// Read the next element of the seq.
+	pub fn next_element(&mut self) -> io::Result<Option<Value<'_>>> {
+		self.frame.next()
+	}
+}
+
+/// The (rest of the) data of a single value.
+struct Source<'r> {
+	reader: &'r mut dyn io::Read,
+	/// None if the value extends until the end of the stream.
+	remaining: Option<u64>,
+	convert_fd: &'r dyn fd::ConvertFd,
+}
+
+impl<'r> Source<'r> {
+	/// Read until `buf` is full, or the end of the value is reached.
+	fn read_all(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let mut n = 0;
+		while n < buf.len() {
+			match io::Read::read(self, &mut buf[n..]) {
+				Ok(0) => break,
+				Ok(m) => n += m,
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(n)
+	}
+}
+
+impl<'r> io::Read for Source<'r> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let buf = match self.remaining {
+			Some(0) => return Ok(0),
+			Some(r) if r < buf.len() as u64 => &mut buf[..r as usize],
+			_ => buf,
+		};
+		let n = self.reader.read(buf)?;
+		if let Some(r) = &mut self.remaining {
+			if n == 0 {
+				return Err(io::ErrorKind::UnexpectedEof.into());
+			}
+			*r -= n as u64;
+		}
+		Ok(n)
+	}
+}
+
+/// The data of a map or seq, from which the elements are read.
+struct Frame<'r> {
+	source: Source<'r>,
+	/// The number of bytes of the current element that have not been read yet.
+	element: u64,
+}
+
+impl<'r> Frame<'r> {
+	fn new(source: Source<'r>) -> Frame<'r> {
+		Frame { source, element: 0 }
+	}
+
+	fn next(&mut self) -> io::Result<Option<Value<'_>>> {
+		// Skip whatever is left of the previous element.
+		let mut buf = [0u8; 512];
+		while self.element > 0 {
+			if io::Read::read(self, &mut buf)? == 0 {
+				return Err(io::ErrorKind::UnexpectedEof.into());
+			}
+		}
+
+		let len = match read_subfield_length(&mut self.source) {
+			Ok(Some(len)) => len,
+			Ok(None) => return Ok(None),
+			// The map or seq ended in the middle of the length, rather than
+			// the stream.
+			Err(ref e)
+				if e.kind() == io::ErrorKind::UnexpectedEof && self.source.remaining == Some(0) =>
+			{
+				return Err(invalid_data(ReadError::InvalidSubfield));
+			}
+			Err(e) => return Err(e),
+		};
+		if self.source.remaining.is_some_and(|r| len > r) {
+			return Err(invalid_data(ReadError::InvalidSubfield));
+		}
+		self.element = len;
+		let convert_fd = self.source.convert_fd;
+		Value::read(Source {
+			reader: self,
+			remaining: Some(len),
+			convert_fd,
+		})
+		.map(Some)
+	}
+}
+
+impl<'r> io::Read for Frame<'r> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let len = std::cmp::min(buf.len() as u64, self.element) as usize;
+		let n = self.source.read(&mut buf[..len])?;
+		self.element -= n as u64;
+		Ok(n)
+	}
+}
+
+fn invalid_data(e: ReadError) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+impl<'r> std::fmt::Debug for Value<'r> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Value::Binary(v) => v.fmt(f),
+			Value::Map(v) => v.fmt(f),
+			Value::Seq(v) => v.fmt(f),
+			Value::Other(v) => v.fmt(f),
+		}
+	}
+}
+
+impl<'r> std::fmt::Debug for BinaryReader<'r> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(
+			f,
+			"BinaryReader {{ remaining: {:?} }}",
+			self.source.remaining
+		)
+	}
+}
+
+impl<'r> std::fmt::Debug for MapReader<'r> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "MapReader")
+	}
+}
+
+impl<'r> std::fmt::Debug for SeqReader<'r> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "SeqReader")
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::Argdata;
+	use io::Read;
+
+	/// A reader that returns only one byte at a time.
+	struct Slow<'a>(&'a [u8]);
+
+	impl<'a> Read for Slow<'a> {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			let n = std::cmp::min(1, buf.len());
+			self.0.read(&mut buf[..n])
+		}
+	}
+
+	fn encode(value: &OwnedArgdata) -> Vec<u8> {
+		let mut data = Vec::new();
+		value.argdata().serialize(&mut data, None).unwrap();
+		data
+	}
+
+	fn sample() -> OwnedArgdata {
+		OwnedArgdata::Map(vec![
+			("a".into(), OwnedArgdata::Binary(vec![1; 300])),
+			(
+				"b".into(),
+				OwnedArgdata::Seq(vec![1.into(), OwnedArgdata::Null, "x".into()]),
+			),
+			(OwnedArgdata::Seq(vec![]), b"xyz"[..].into()),
+		])
+	}
+
+	#[test]
+	fn stream_owned_test() {
+		let data = encode(&sample());
+		let mut reader = Slow(&data);
+		let value = read(&mut reader, &fd::NoConvert).unwrap();
+		let owned = value.into_owned().unwrap();
+		assert_eq!(owned, sample());
+	}
+
+	#[test]
+	fn stream_partial_test() {
+		let data = encode(&sample());
+		let mut reader = Slow(&data);
+		let mut map = match read(&mut reader, &fd::NoConvert).unwrap() {
+			Value::Map(map) => map,
+			v => panic!("unexpected {:?}", v),
+		};
+
+		// Read only a bit of the first blob.
+		match map.next_entry().unwrap() {
+			Some((key, Value::Binary(mut b))) => {
+				assert_eq!(format!("{:?}", key), "\"a\"");
+				assert_eq!(b.remaining(), Some(300));
+				let mut buf = [0; 10];
+				b.read_exact(&mut buf).unwrap();
+				assert_eq!(b.remaining(), Some(290));
+			}
+			v => panic!("unexpected {:?}", v),
+		}
+
+		// Skip the seq completely.
+		assert!(map.next_entry().unwrap().is_some());
+
+		match map.next_entry().unwrap() {
+			Some((_, Value::Binary(mut b))) => {
+				let mut buf = Vec::new();
+				b.read_to_end(&mut buf).unwrap();
+				assert_eq!(buf, b"xyz");
+			}
+			v => panic!("unexpected {:?}", v),
+		}
+
+		assert!(map.next_entry().unwrap().is_none());
+	}
+
+	#[test]
+	fn stream_error_test() {
+		let read_all = |data: &[u8]| {
+			let mut data = data;
+			read(&mut data, &fd::NoConvert).and_then(Value::into_owned)
+		};
+
+		let data = encode(&sample());
+		assert_eq!(
+			read_all(&data[..data.len() - 1]).unwrap_err().kind(),
+			io::ErrorKind::UnexpectedEof
+		);
+
+		// The stream ends in the middle of a subfield length.
+		assert_eq!(
+			read_all(b"\x07\x01").unwrap_err().kind(),
+			io::ErrorKind::UnexpectedEof
+		);
+		assert_eq!(
+			read_all(b"\x07\x83\x07\x01").unwrap_err().kind(),
+			io::ErrorKind::UnexpectedEof
+		);
+		// The seq ends in the middle of a subfield length.
+		let e = read_all(b"\x07\x82\x07\x01").unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+		assert_eq!(e.to_string(), ReadError::InvalidSubfield.to_string());
+
+		let e = read_all(b"\x07\x83\x07\x82\x02").unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+		assert_eq!(e.to_string(), ReadError::InvalidSubfield.to_string());
+
+		let e = read_all(b"\x06\x81\x02").unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+		assert_eq!(e.to_string(), ReadError::InvalidKeyValuePair.to_string());
+
+		let e = read_all(b"\x07\x81\x0A").unwrap_err();
+		assert_eq!(e.to_string(), ReadError::InvalidTag(10).to_string());
+
+		assert_eq!(format!("{:?}", read_all(b"").unwrap()), "null");
+	}
+}
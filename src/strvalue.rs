@@ -38,6 +38,7 @@ impl<'d> StrValue<'d> {
 	}
 
 	/// Create a StrValue referring to a non-zero terminated UTF-8 `str`.
+	#[allow(clippy::should_implement_trait)]
 	pub fn from_str(s: &'d str) -> StrValue<'d> {
 		StrValue {
 			inner: Inner::Str(s),
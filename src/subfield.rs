@@ -15,7 +15,7 @@ pub(crate) fn read_subfield(data: &[u8]) -> (Option<Result<&[u8], ReadError>>, u
 			None => return (Some(Err(ReadError::InvalidSubfield)), data.len()),
 		};
 		len_bytes += 1;
-		if len > usize::max_value() >> 7 {
+		if len > usize::MAX >> 7 {
 			return (Some(Err(ReadError::InvalidSubfield)), data.len());
 		}
 		len = len << 7 | (byte & 0x7F) as usize;
@@ -33,9 +33,48 @@ pub(crate) fn read_subfield(data: &[u8]) -> (Option<Result<&[u8], ReadError>>, u
 	(Some(Ok(field)), len_bytes + len)
 }
 
+/// Like `read_subfield`, but fails if the length is not minimally encoded:
+/// if it has a leading zero byte.
+pub(crate) fn read_canonical_subfield(data: &[u8]) -> (Option<Result<&[u8], ReadError>>, usize) {
+	match data.first() {
+		Some(0) => (Some(Err(ReadError::NonMinimalSubfieldLength)), data.len()),
+		_ => read_subfield(data),
+	}
+}
+
+/// Read the length of a subfield from a stream.
+///
+/// Returns None if the stream ends before the first byte, and an error of kind
+/// `UnexpectedEof` if it ends after that.
+pub(crate) fn read_subfield_length(reader: &mut dyn io::Read) -> io::Result<Option<u64>> {
+	let mut len: u64 = 0;
+	let mut first = true;
+	loop {
+		let mut byte = [0u8];
+		match reader.read(&mut byte) {
+			Ok(0) if first => return Ok(None),
+			Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+			Ok(_) => {}
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(e) => return Err(e),
+		}
+		first = false;
+		if len > u64::MAX >> 7 {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				ReadError::InvalidSubfield,
+			));
+		}
+		len = len << 7 | (byte[0] & 0x7F) as u64;
+		if byte[0] >= 0x80 {
+			return Ok(Some(len));
+		}
+	}
+}
+
 fn subfield_length_length(length: usize) -> usize {
 	let n_bits = 0usize.count_zeros() - (length | 1).leading_zeros();
-	((n_bits + 6) / 7) as usize
+	n_bits.div_ceil(7) as usize
 }
 
 pub(crate) fn subfield_length(length: usize) -> usize {
@@ -58,6 +97,7 @@ pub(crate) fn write_subfield_length(length: usize, writer: &mut dyn io::Write) -
 }
 
 #[test]
+#[allow(clippy::identity_op)]
 fn test_subfield_length() {
 	assert_eq!(subfield_length(0), 1 + 0);
 	assert_eq!(subfield_length(1), 1 + 1);
@@ -89,3 +129,24 @@ fn test_write_subfield_length() {
 	write_subfield_length(0x4000, &mut v).unwrap();
 	assert_eq!(v, &[0x01, 0x00, 0x80]);
 }
+
+#[test]
+fn test_read_subfield_length() {
+	let read = |mut data: &[u8]| read_subfield_length(&mut data).map_err(|e| e.kind());
+	assert_eq!(read(&[]), Ok(None));
+	assert_eq!(read(&[0x80]), Ok(Some(0)));
+	assert_eq!(read(&[0x01, 0x00, 0x80, 0x12]), Ok(Some(0x4000)));
+	assert_eq!(read(&[0x01, 0x00]), Err(io::ErrorKind::UnexpectedEof));
+	assert_eq!(read(&[0x7F; 11]), Err(io::ErrorKind::InvalidData));
+}
+
+#[test]
+fn test_read_canonical_subfield() {
+	assert_eq!(read_canonical_subfield(b""), (None, 0));
+	assert_eq!(read_canonical_subfield(b"\x81a"), (Some(Ok(&b"a"[..])), 2));
+	assert_eq!(read_subfield(b"\x00\x81a"), (Some(Ok(&b"a"[..])), 3));
+	assert_eq!(
+		read_canonical_subfield(b"\x00\x81a"),
+		(Some(Err(ReadError::NonMinimalSubfieldLength)), 3)
+	);
+}
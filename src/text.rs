@@ -0,0 +1,409 @@
+//! The notation is the one used by the `Debug` implementations of argdata
+//! values, for example:
+//!
+//! ```text
+//! {"name": "example", "fds": [fd(0), fd(1)], 5: binary([1, 2, 3]),
+//!  null: [true, -1.5, timestamp(1528000000, 500)]}
+//! ```
+//!
+//! Strings use Rust's escape sequences. Integers can be of any size. Floats
+//! are written with a `.` or exponent to distinguish them from integers, or as
+//! `NaN`, `inf` or `-inf`. Whitespace is ignored, and trailing commas in maps
+//! and seqs are allowed, so the output of `{:#?}` can be read as well.
+//!
+//! Example:
+//!
+//! ```
+//! # use argdata::text;
+//! let value = text::parse("{\"a\": [1, 2.0, fd(3)]}").unwrap();
+//! assert_eq!(text::to_string(value.argdata()).unwrap(), "{\"a\": [1, 2.0, fd(3)]}");
+//! ```
+
+use crate::{
+	debug::{write_value, Notation},
+	fd, intvalue,
+	parse::{Input, Parse, MAX_DEPTH},
+	Argdata, OwnedArgdata, ParseError, ReadError, Timespec, Value,
+};
+use std::fmt::Write;
+
+/// Write an argdata value in the text notation.
+///
+/// This gives the same result as the `Debug` implementation, except that
+/// errors are returned instead of written into the output. The result can
+/// always be read back with [`parse`].
+pub fn to_string(value: &dyn Argdata) -> Result<String, ReadError> {
+	let mut s = String::new();
+	write_value(&mut TextNotation(&mut s), value.read(), 0)?;
+	Ok(s)
+}
+
+/// The notation of the `Debug` implementations, without `{:#?}`, and with
+/// errors returned instead of written.
+struct TextNotation<'a>(&'a mut String);
+
+impl<'a> Notation for TextNotation<'a> {
+	type Error = ReadError;
+
+	fn scalar(&mut self, value: Value) -> Result<(), ReadError> {
+		let s = &mut *self.0;
+		match value {
+			Value::Null => s.push_str("null"),
+			Value::Binary(v) => write!(s, "binary({:?})", v).unwrap(),
+			Value::Bool(v) => write!(s, "{}", v).unwrap(),
+			Value::Fd(v) => write!(s, "fd({})", v.raw_encoded_number()).unwrap(),
+			Value::Float(v) => write!(s, "{:?}", v).unwrap(),
+			Value::Int(v) => write!(s, "{}", v).unwrap(),
+			Value::Str(v) => write!(s, "{:?}", v.as_str()?).unwrap(),
+			Value::Timestamp(v) => write!(s, "timestamp({}, {})", v.sec, v.nsec).unwrap(),
+			Value::Map(_) | Value::Seq(_) => unreachable!(),
+		}
+		Ok(())
+	}
+
+	fn error(&mut self, error: ReadError) -> Result<(), ReadError> {
+		Err(error)
+	}
+
+	fn begin(&mut self, map: bool) -> Result<(), ReadError> {
+		self.0.push(if map { '{' } else { '[' });
+		Ok(())
+	}
+
+	fn item(&mut self, _map: bool, first: bool, _depth: usize) -> Result<(), ReadError> {
+		if !first {
+			self.0.push_str(", ");
+		}
+		Ok(())
+	}
+
+	fn colon(&mut self, _depth: usize) -> Result<(), ReadError> {
+		self.0.push_str(": ");
+		Ok(())
+	}
+
+	fn end(&mut self, map: bool, _empty: bool, _depth: usize) -> Result<(), ReadError> {
+		self.0.push(if map { '}' } else { ']' });
+		Ok(())
+	}
+}
+
+/// Parse a value in the text notation.
+pub fn parse(s: &str) -> Result<OwnedArgdata, ParseError> {
+	let mut parser = Parser {
+		input: Input::new(s.as_bytes()),
+	};
+	let value = parser.value(0)?;
+	parser.whitespace();
+	if parser.input.pos != s.len() {
+		return Err(parser.input.error("Trailing characters after value"));
+	}
+	Ok(value)
+}
+
+struct Parser<'a> {
+	input: Input<'a>,
+}
+
+impl<'a> Parse<'a> for Parser<'a> {
+	fn input(&mut self) -> &mut Input<'a> {
+		&mut self.input
+	}
+
+	fn whitespace(&mut self) {
+		while self.input.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+			self.input.pos += 1;
+		}
+	}
+}
+
+impl<'a> Parser<'a> {
+	/// Read a word consisting of ASCII letters.
+	fn word(&mut self) -> &'a str {
+		let start = self.input.pos;
+		while self.input.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+			self.input.pos += 1;
+		}
+		std::str::from_utf8(&self.input.data[start..self.input.pos]).unwrap()
+	}
+
+	/// Read a number, returning the text and whether it's a float.
+	fn number(&mut self) -> (&'a str, bool) {
+		let start = self.input.pos;
+		let mut is_float = false;
+		while let Some(c) = self.input.peek() {
+			match c {
+				b'0'..=b'9' => {}
+				b'-' if self.input.pos == start
+					|| b"eE".contains(&self.input.data[self.input.pos - 1]) => {}
+				b'.' | b'e' | b'E' => is_float = true,
+				_ => break,
+			}
+			self.input.pos += 1;
+		}
+		let text = std::str::from_utf8(&self.input.data[start..self.input.pos]).unwrap();
+		(text, is_float)
+	}
+
+	/// Read an integer that fits in the given type.
+	fn int<T: std::str::FromStr>(&mut self) -> Result<T, ParseError> {
+		self.whitespace();
+		let start = self.input.pos;
+		let (text, is_float) = self.number();
+		match text.parse() {
+			Ok(v) if !is_float => Ok(v),
+			_ => Err(ParseError::new(start, "Invalid integer")),
+		}
+	}
+
+	fn value(&mut self, depth: usize) -> Result<OwnedArgdata, ParseError> {
+		if depth > MAX_DEPTH {
+			return Err(self.input.error("Value nested too deeply"));
+		}
+		self.whitespace();
+		let start = self.input.pos;
+		match self.input.peek() {
+			None => Err(self.input.error("Unexpected end of input")),
+			Some(b'"') => self.string().map(OwnedArgdata::Str),
+			Some(b'[') => {
+				self.input.pos += 1;
+				let mut elements = Vec::new();
+				while !self.eat(b']') {
+					elements.push(self.value(depth + 1)?);
+					if !self.eat(b',') {
+						self.expect(b']', "Expected ',' or ']'")?;
+						break;
+					}
+				}
+				Ok(OwnedArgdata::Seq(elements))
+			}
+			Some(b'{') => {
+				self.input.pos += 1;
+				let mut entries = Vec::new();
+				while !self.eat(b'}') {
+					let key = self.value(depth + 1)?;
+					self.expect(b':', "Expected ':'")?;
+					let value = self.value(depth + 1)?;
+					entries.push((key, value));
+					if !self.eat(b',') {
+						self.expect(b'}', "Expected ',' or '}'")?;
+						break;
+					}
+				}
+				Ok(OwnedArgdata::Map(entries))
+			}
+			Some(b'-') if self.input.data[self.input.pos..].starts_with(b"-inf") => {
+				self.input.pos += 4;
+				Ok(OwnedArgdata::Float(f64::NEG_INFINITY))
+			}
+			Some(b'0'..=b'9' | b'-') => {
+				let (text, is_float) = self.number();
+				let value = if is_float {
+					text.parse().ok().map(OwnedArgdata::Float)
+				} else {
+					intvalue::parse_decimal(text).map(OwnedArgdata::Int)
+				};
+				value.ok_or(ParseError::new(start, "Invalid number"))
+			}
+			Some(c) if c.is_ascii_alphabetic() => match self.word() {
+				"null" => Ok(OwnedArgdata::Null),
+				"true" => Ok(OwnedArgdata::Bool(true)),
+				"false" => Ok(OwnedArgdata::Bool(false)),
+				"NaN" => Ok(OwnedArgdata::Float(f64::NAN)),
+				"inf" => Ok(OwnedArgdata::Float(f64::INFINITY)),
+				"binary" => {
+					self.expect(b'(', "Expected '('")?;
+					self.expect(b'[', "Expected '['")?;
+					let mut data = Vec::new();
+					while !self.eat(b']') {
+						data.push(self.int()?);
+						if !self.eat(b',') {
+							self.expect(b']', "Expected ',' or ']'")?;
+							break;
+						}
+					}
+					self.expect(b')', "Expected ')'")?;
+					Ok(OwnedArgdata::Binary(data))
+				}
+				"fd" => {
+					self.expect(b'(', "Expected '('")?;
+					let fd: u32 = self.int()?;
+					self.expect(b')', "Expected ')'")?;
+					Ok(OwnedArgdata::Fd(fd::Fd(fd as i32)))
+				}
+				"timestamp" => {
+					self.expect(b'(', "Expected '('")?;
+					let sec = self.int()?;
+					self.expect(b',', "Expected ','")?;
+					self.whitespace();
+					let nsec_start = self.input.pos;
+					let nsec = self.int()?;
+					if nsec >= 1_000_000_000 {
+						return Err(ParseError::new(nsec_start, "Invalid integer"));
+					}
+					self.expect(b')', "Expected ')'")?;
+					Ok(OwnedArgdata::Timestamp(Timespec { sec, nsec }))
+				}
+				_ => Err(ParseError::new(start, "Unknown keyword")),
+			},
+			Some(_) => Err(self.input.error("Expected a value")),
+		}
+	}
+
+	fn string(&mut self) -> Result<String, ParseError> {
+		self.input.pos += 1; // Opening quote.
+		let mut s = String::new();
+		let mut plain = self.input.pos;
+		loop {
+			match self.input.peek() {
+				None => return Err(self.input.error("Unterminated string")),
+				Some(b'"') => break,
+				Some(b'\\') => {
+					s += std::str::from_utf8(&self.input.data[plain..self.input.pos]).unwrap();
+					let escape = self.input.pos;
+					self.input.pos += 1;
+					let c = match self.input.peek() {
+						Some(b'n') => Some('\n'),
+						Some(b'r') => Some('\r'),
+						Some(b't') => Some('\t'),
+						Some(b'0') => Some('\0'),
+						Some(b'\\') => Some('\\'),
+						Some(b'"') => Some('"'),
+						Some(b'\'') => Some('\''),
+						Some(b'x') => {
+							let c = self
+								.input
+								.data
+								.get(self.input.pos + 1..self.input.pos + 3)
+								.and_then(|d| std::str::from_utf8(d).ok())
+								.and_then(|d| u8::from_str_radix(d, 16).ok())
+								.filter(|&c| c < 0x80);
+							self.input.pos += 2;
+							c.map(char::from)
+						}
+						Some(b'u') if self.input.data.get(self.input.pos + 1) == Some(&b'{') => {
+							let end = self.input.data[self.input.pos..]
+								.iter()
+								.position(|&c| c == b'}');
+							end.and_then(|end| {
+								let digits =
+									&self.input.data[self.input.pos + 2..self.input.pos + end];
+								self.input.pos += end;
+								u32::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+							})
+							.and_then(std::char::from_u32)
+						}
+						_ => None,
+					}
+					.ok_or(ParseError::new(escape, "Invalid escape sequence"))?;
+					s.push(c);
+					plain = self.input.pos + 1;
+				}
+				Some(_) => {}
+			}
+			self.input.pos += 1;
+		}
+		s += std::str::from_utf8(&self.input.data[plain..self.input.pos]).unwrap();
+		self.input.pos += 1; // Closing quote.
+		Ok(s)
+	}
+}
+
+#[test]
+fn text_roundtrip_test() {
+	let value = OwnedArgdata::Map(vec![
+		("a\"\\\n\t\u{1}é".into(), OwnedArgdata::Null),
+		(true.into(), OwnedArgdata::Seq(vec![])),
+		(
+			OwnedArgdata::Map(vec![]),
+			OwnedArgdata::Seq(vec![
+				0.into(),
+				(-5).into(),
+				OwnedArgdata::Int(vec![0xFE, 0, 0, 0, 0, 0, 0, 0, 0]),
+				1.0.into(),
+				1e-7.into(),
+				f64::NEG_INFINITY.into(),
+				f64::NAN.into(),
+			]),
+		),
+		(b"\x00\xFF"[..].into(), fd::Fd(7).into()),
+		(
+			Timespec {
+				sec: -1,
+				nsec: 999_999_999,
+			}
+			.into(),
+			1e100.into(),
+		),
+	]);
+	let text = to_string(value.argdata()).unwrap();
+	assert_eq!(text, format!("{:?}", value));
+	assert_eq!(
+		text,
+		"{\"a\\\"\\\\\\n\\t\\u{1}é\": null, true: [], {}: [0, -5, -36893488147419103232, \
+		 1.0, 1e-7, -inf, NaN], binary([0, 255]): fd(7), \
+		 timestamp(-1, 999999999): 1e100}"
+	);
+	assert_eq!(to_string(parse(&text).unwrap().argdata()).unwrap(), text);
+
+	let pretty = format!("{:#?}", value);
+	assert_eq!(to_string(parse(&pretty).unwrap().argdata()).unwrap(), text);
+
+	let invalid = crate::encoded(&b"\x07\x82\x08a"[..]);
+	assert_eq!(to_string(&invalid), Err(ReadError::MissingNullTerminator));
+}
+
+#[test]
+fn text_parse_test() {
+	let parse = |s| parse(s).map(|v| format!("{:?}", v));
+	assert_eq!(
+		parse("[ 1e3 , \"\\x41\\'\\u{1F600}\" , binary( [ ] ) ,]").unwrap(),
+		"[1000.0, \"A'😀\", binary([])]"
+	);
+
+	let error = |s| self::parse(s).unwrap_err().to_string();
+	assert_eq!(error(""), "Unexpected end of input at byte 0");
+	assert_eq!(error("[1 2]"), "Expected ',' or ']' at byte 3");
+	assert_eq!(error("{1}"), "Expected ':' at byte 2");
+	assert_eq!(error("1.2.3"), "Invalid number at byte 0");
+	assert_eq!(error("nul"), "Unknown keyword at byte 0");
+	assert_eq!(error("binary([256])"), "Invalid integer at byte 8");
+	assert_eq!(error("fd(-1)"), "Invalid integer at byte 3");
+	assert_eq!(
+		error("timestamp(1, 1000000000)"),
+		"Invalid integer at byte 13"
+	);
+	assert_eq!(error("error(\"x\")"), "Unknown keyword at byte 0");
+	assert_eq!(error("\"\\u{D800}\""), "Invalid escape sequence at byte 1");
+	assert_eq!(error("'a'"), "Expected a value at byte 0");
+	assert_eq!(error("1 2"), "Trailing characters after value at byte 2");
+	assert_eq!(
+		error(&"[".repeat(1000)),
+		"Value nested too deeply at byte 257"
+	);
+}
+
+#[test]
+fn text_deep_nesting_test() {
+	let data = crate::parse::nested_seqs(MAX_DEPTH + 1);
+	let brackets = |b: &str| b.repeat(MAX_DEPTH + 1);
+	assert_eq!(
+		to_string(&crate::encoded(&data)),
+		Ok(brackets("[") + "null" + &brackets("]"))
+	);
+	let data = crate::parse::nested_seqs(MAX_DEPTH + 2);
+	assert_eq!(
+		to_string(&crate::encoded(&data)),
+		Err(ReadError::NestedTooDeeply)
+	);
+	assert_eq!(
+		format!("{:?}", &crate::encoded(&data) as &dyn Argdata),
+		brackets("[") + "error(\"NestedTooDeeply\")" + &brackets("]")
+	);
+	let data = crate::parse::nested_seqs(200_000);
+	assert_eq!(
+		to_string(&crate::encoded(&data)),
+		Err(ReadError::NestedTooDeeply)
+	);
+	assert!(format!("{:?}", &crate::encoded(&data) as &dyn Argdata).contains("NestedTooDeeply"));
+}
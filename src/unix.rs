@@ -0,0 +1,323 @@
+//! Argdata is sent together with the file descriptors it refers to, which are
+//! attached to the message with `SCM_RIGHTS`. The receiving process gets its
+//! own copies of those file descriptors, numbered as in the sent argdata.
+//!
+//! On a stream socket, every message is preceded by its length as a 32-bit
+//! big-endian integer, which may not exceed
+//! [`MAX_STREAM_MESSAGE`](crate::unix::MAX_STREAM_MESSAGE). On a datagram
+//! socket, every datagram is one message.
+
+use crate::{encoded_with_fds, fd::Fd, values::EncodedArgdata, Argdata};
+use byteorder::{BigEndian, ByteOrder};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixDatagram, UnixStream};
+use std::ptr;
+
+/// The maximum number of file descriptors that can be received with a message.
+///
+/// This is the limit Linux puts on the number of file descriptors sent at once.
+pub const MAX_FDS: usize = 253;
+
+/// The maximum length of the argdata in a message on a stream socket.
+///
+/// The length is sent before the message, so without a limit, the receiver
+/// would allocate whatever the sender asks for.
+pub const MAX_STREAM_MESSAGE: usize = 64 << 20;
+
+/// A received message.
+///
+/// The file descriptors are owned by the receiver, and have the close-on-exec
+/// flag set. They are not closed when the message is dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+	/// The encoded argdata.
+	pub data: Vec<u8>,
+	/// The received file descriptors, in the order of their encoded numbers.
+	pub fds: Vec<Fd>,
+}
+
+impl Message {
+	/// The argdata, of which file descriptors refer to the received ones.
+	pub fn argdata(&self) -> EncodedArgdata<'_, &[Fd]> {
+		encoded_with_fds(&self.data, &self.fds[..])
+	}
+}
+
+/// Send argdata over a stream socket, together with the file descriptors it
+/// refers to.
+///
+/// File descriptors that can't be converted are sent as invalid.
+pub fn send_stream(socket: &UnixStream, value: &dyn Argdata) -> io::Result<()> {
+	let mut data = vec![0; 4];
+	let mut fds = Vec::new();
+	value.serialize(&mut data, Some(&mut fds))?;
+	let len = data.len() - 4;
+	if len > MAX_STREAM_MESSAGE {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"Argdata too large to send",
+		));
+	}
+	BigEndian::write_u32(&mut data[..4], len as u32);
+	let n = send_msg(socket.as_raw_fd(), &data, &fds)?;
+	// The file descriptors are attached to the part that was already sent.
+	(&*socket).write_all(&data[n..])
+}
+
+/// Receive argdata from a stream socket, as sent by [`send_stream`].
+///
+/// Fails with `io::ErrorKind::InvalidData` if the message is longer than
+/// [`MAX_STREAM_MESSAGE`].
+pub fn recv_stream(socket: &UnixStream) -> io::Result<Message> {
+	let mut header = [0; 4];
+	let (n, fds, _) = recv_msg(socket.as_raw_fd(), &mut header, 0, MAX_FDS)?;
+	let data = if n == 0 {
+		Err(io::ErrorKind::UnexpectedEof.into())
+	} else {
+		(&*socket).read_exact(&mut header[n..]).and_then(|()| {
+			let len = BigEndian::read_u32(&header) as usize;
+			if len > MAX_STREAM_MESSAGE {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidData,
+					"Argdata message too large",
+				));
+			}
+			let mut data = vec![0; len];
+			(&*socket).read_exact(&mut data)?;
+			Ok(data)
+		})
+	};
+	match data {
+		Ok(data) => Ok(Message { data, fds }),
+		Err(e) => {
+			close_all(&fds);
+			Err(e)
+		}
+	}
+}
+
+/// Send argdata as a single datagram, together with the file descriptors it
+/// refers to.
+///
+/// File descriptors that can't be converted are sent as invalid.
+pub fn send_datagram(socket: &UnixDatagram, value: &dyn Argdata) -> io::Result<()> {
+	let mut data = Vec::with_capacity(value.serialized_length());
+	let mut fds = Vec::new();
+	value.serialize(&mut data, Some(&mut fds))?;
+	send_msg(socket.as_raw_fd(), &data, &fds)?;
+	Ok(())
+}
+
+/// Receive argdata from a datagram socket, as sent by [`send_datagram`].
+pub fn recv_datagram(socket: &UnixDatagram) -> io::Result<Message> {
+	let mut data = vec![0; 4096];
+	// Peek (without receiving any file descriptors) until the buffer is large
+	// enough for the whole datagram.
+	while recv_msg(socket.as_raw_fd(), &mut data, libc::MSG_PEEK, 0)?.2 & libc::MSG_TRUNC != 0 {
+		let len = data.len() * 2;
+		data.resize(len, 0);
+	}
+	let (n, fds, _) = recv_msg(socket.as_raw_fd(), &mut data, 0, MAX_FDS)?;
+	data.truncate(n);
+	Ok(Message { data, fds })
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SEND_FLAGS: c_int = libc::MSG_NOSIGNAL;
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const SEND_FLAGS: c_int = 0;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const RECV_FLAGS: c_int = libc::MSG_CMSG_CLOEXEC;
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const RECV_FLAGS: c_int = 0;
+
+/// A buffer for control messages, aligned for `cmsghdr`.
+fn control_buffer(fds: usize) -> (Vec<u64>, usize) {
+	if fds == 0 {
+		return (Vec::new(), 0);
+	}
+	let len = unsafe { libc::CMSG_SPACE((fds * size_of::<c_int>()) as u32) } as usize;
+	(vec![0; len.div_ceil(8)], len)
+}
+
+/// Send the data with `sendmsg`, with the file descriptors attached.
+///
+/// Returns the number of bytes sent.
+fn send_msg(socket: RawFd, data: &[u8], fds: &[Fd]) -> io::Result<usize> {
+	let mut iov = libc::iovec {
+		iov_base: data.as_ptr() as *mut c_void,
+		iov_len: data.len(),
+	};
+	let (mut control, control_len) = control_buffer(fds.len());
+	let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	if !fds.is_empty() {
+		msg.msg_control = control.as_mut_ptr() as *mut c_void;
+		msg.msg_controllen = control_len as _;
+		unsafe {
+			let cmsg = libc::CMSG_FIRSTHDR(&msg);
+			(*cmsg).cmsg_level = libc::SOL_SOCKET;
+			(*cmsg).cmsg_type = libc::SCM_RIGHTS;
+			(*cmsg).cmsg_len = libc::CMSG_LEN((fds.len() * size_of::<c_int>()) as u32) as _;
+			let data = libc::CMSG_DATA(cmsg) as *mut c_int;
+			for (i, fd) in fds.iter().enumerate() {
+				ptr::write_unaligned(data.add(i), fd.0);
+			}
+		}
+	}
+	loop {
+		let n = unsafe { libc::sendmsg(socket, &msg, SEND_FLAGS) };
+		if n >= 0 {
+			return Ok(n as usize);
+		}
+		let error = io::Error::last_os_error();
+		if error.kind() != io::ErrorKind::Interrupted {
+			return Err(error);
+		}
+	}
+}
+
+/// Receive data with `recvmsg`, together with at most `max_fds` file descriptors.
+///
+/// Returns the number of bytes received, the file descriptors, and the flags
+/// of the received message.
+fn recv_msg(
+	socket: RawFd,
+	buf: &mut [u8],
+	flags: c_int,
+	max_fds: usize,
+) -> io::Result<(usize, Vec<Fd>, c_int)> {
+	let mut iov = libc::iovec {
+		iov_base: buf.as_mut_ptr() as *mut c_void,
+		iov_len: buf.len(),
+	};
+	let (mut control, control_len) = control_buffer(max_fds);
+	let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.as_mut_ptr() as *mut c_void;
+	msg.msg_controllen = control_len as _;
+	let n = loop {
+		let n = unsafe { libc::recvmsg(socket, &mut msg, flags | RECV_FLAGS) };
+		if n >= 0 {
+			break n as usize;
+		}
+		let error = io::Error::last_os_error();
+		if error.kind() != io::ErrorKind::Interrupted {
+			return Err(error);
+		}
+	};
+	let mut fds = Vec::new();
+	if control_len != 0 {
+		unsafe {
+			let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+			while !cmsg.is_null() {
+				if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+					let data = libc::CMSG_DATA(cmsg) as *const c_int;
+					let header_len = data as usize - cmsg as usize;
+					let len = ((*cmsg).cmsg_len as usize - header_len) / size_of::<c_int>();
+					for i in 0..len {
+						fds.push(Fd(ptr::read_unaligned(data.add(i))));
+					}
+				}
+				cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+			}
+		}
+		if RECV_FLAGS == 0 {
+			for fd in &fds {
+				unsafe { libc::fcntl(fd.0, libc::F_SETFD, libc::FD_CLOEXEC) };
+			}
+		}
+		if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+			close_all(&fds);
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"Too many file descriptors attached to message",
+			));
+		}
+	}
+	Ok((n, fds, msg.msg_flags))
+}
+
+fn close_all(fds: &[Fd]) {
+	for fd in fds {
+		unsafe { libc::close(fd.0) };
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{ArgdataExt, OwnedArgdata};
+	use std::os::unix::io::FromRawFd;
+
+	/// A value containing `fd` twice.
+	fn sample(fd: &UnixStream) -> OwnedArgdata {
+		let fd = Fd(fd.as_raw_fd());
+		OwnedArgdata::Seq(vec!["hello".into(), fd.into(), fd.into()])
+	}
+
+	/// Check the received message, and use its file descriptor to send
+	/// something to `peer`.
+	fn check(message: Message, peer: &mut UnixStream) {
+		assert_eq!(message.fds.len(), 1);
+		let argdata = message.argdata();
+		let seq: Vec<_> = argdata.read_seq().unwrap().map(|v| v.unwrap()).collect();
+		assert_eq!(seq[0].read_str(), Ok("hello"));
+		assert_eq!(seq[1].read_fd(), Ok(message.fds[0]));
+		assert_eq!(seq[2].read_fd(), Ok(message.fds[0]));
+
+		let mut stream = unsafe { UnixStream::from_raw_fd(message.fds[0].0) };
+		stream.write_all(b"!").unwrap();
+		let mut buf = [0];
+		peer.read_exact(&mut buf).unwrap();
+		assert_eq!(&buf, b"!");
+	}
+
+	#[test]
+	fn unix_stream_test() {
+		let (a, b) = UnixStream::pair().unwrap();
+		let (c, mut d) = UnixStream::pair().unwrap();
+		send_stream(&a, sample(&c).argdata()).unwrap();
+		send_stream(&a, OwnedArgdata::Null.argdata()).unwrap();
+		drop(c);
+		check(recv_stream(&b).unwrap(), &mut d);
+		assert_eq!(recv_stream(&b).unwrap().data, b"");
+		drop(a);
+		assert_eq!(
+			recv_stream(&b).unwrap_err().kind(),
+			io::ErrorKind::UnexpectedEof
+		);
+
+		let (mut a, b) = UnixStream::pair().unwrap();
+		a.write_all(&[0xFF; 4]).unwrap();
+		assert_eq!(
+			recv_stream(&b).unwrap_err().kind(),
+			io::ErrorKind::InvalidData
+		);
+		let big = OwnedArgdata::Binary(vec![0; MAX_STREAM_MESSAGE]);
+		assert_eq!(
+			send_stream(&a, big.argdata()).unwrap_err().kind(),
+			io::ErrorKind::InvalidInput
+		);
+	}
+
+	#[test]
+	fn unix_datagram_test() {
+		let (a, b) = UnixDatagram::pair().unwrap();
+		let (c, mut d) = UnixStream::pair().unwrap();
+		let big = OwnedArgdata::Binary(vec![1; 10000]);
+		send_datagram(&a, sample(&c).argdata()).unwrap();
+		send_datagram(&a, big.argdata()).unwrap();
+		drop(c);
+		check(recv_datagram(&b).unwrap(), &mut d);
+		assert_eq!(recv_datagram(&b).unwrap().data.len(), 10001);
+	}
+}
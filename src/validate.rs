@@ -0,0 +1,291 @@
+use crate::{fd, values::EncodedArgdata, Argdata, ReadError, Value};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+/// An error found while validating encoded argdata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationError {
+	/// The byte offset of the value (or subfield) containing the error.
+	pub offset: usize,
+	/// The error.
+	pub error: ReadError,
+}
+
+impl Error for ValidationError {}
+
+impl Display for ValidationError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} at byte {}", self.error, self.offset)
+	}
+}
+
+/// Validation of the encoded data, without reading it in any other way.
+///
+/// A value is valid if reading it doesn't result in an error: It has a valid
+/// tag, and the right contents for that tag. Strings must be valid UTF-8, and
+/// file descriptors must convert to an `Fd` using the `ConvertFd` of this
+/// value. (So when using [`encoded`](crate::encoded), which doesn't have any
+/// file descriptors, every file descriptor is invalid.)
+///
+/// In [strict](EncodedArgdata::strict) mode, a value must also be encoded
+/// canonically.
+impl<'d, F: fd::ConvertFd> EncodedArgdata<'d, F> {
+	/// Check if this value is valid, without checking the elements of a map
+	/// or seq.
+	///
+	/// For a map or seq, this only checks that it is made of complete
+	/// subfields, and, for a map, complete key-value pairs.
+	pub fn validate(&self) -> Result<(), ReadError> {
+		let mut result = Ok(());
+		self.validate_with(false, &mut |e| {
+			result = Err(e.error);
+			false
+		});
+		result
+	}
+
+	/// Check if this value, and everything in it, is valid.
+	///
+	/// Returns the first error.
+	pub fn validate_deep(&self) -> Result<(), ValidationError> {
+		let mut result = Ok(());
+		self.validate_with(true, &mut |e| {
+			result = Err(e);
+			false
+		});
+		result
+	}
+
+	/// Find all errors in this value, and everything in it.
+	///
+	/// The elements of a map or seq with an invalid subfield are checked up
+	/// to that subfield.
+	pub fn validation_errors(&self) -> Vec<ValidationError> {
+		let mut errors = Vec::new();
+		self.validate_with(true, &mut |e| {
+			errors.push(e);
+			true
+		});
+		errors
+	}
+
+	/// Validate the value, reporting errors in order of their offset to
+	/// `report`, until it returns false.
+	///
+	/// Doesn't recurse, so arbitrarily deeply nested values can be validated.
+	fn validate_with(&self, deep: bool, report: &mut dyn FnMut(ValidationError) -> bool) {
+		let convert_fd: &dyn fd::ConvertFd = self.convert_fd();
+		let strict = self.is_strict();
+		// Values that still have to be checked, and errors that still have to
+		// be reported after the elements of a map or seq. Last one first.
+		let mut todo = vec![Todo::Check(self.bytes(), 0)];
+		while let Some(item) = todo.pop() {
+			let error = match item {
+				Todo::Check(bytes, offset) => match bytes.first() {
+					Some(&tag @ (6 | 7)) => {
+						let value = crate::encoded_with_fds(bytes, convert_fd).strict(strict);
+						let mut pos = 1;
+						let mut n_elements = 0;
+						let mut keys = HashSet::new();
+						// The elements, and errors, in order of their offset.
+						let mut items = Vec::new();
+						loop {
+							match value.read_subfield(pos) {
+								(None, _) => break,
+								(Some(Ok(subfield)), n) => {
+									let offset = offset + pos + n - subfield.len();
+									if strict
+										&& tag == 6 && n_elements % 2 == 0
+										&& !keys.insert(subfield)
+									{
+										items.push(Todo::Report(ValidationError {
+											offset,
+											error: ReadError::DuplicateMapKey,
+										}));
+									}
+									if deep {
+										items.push(Todo::Check(subfield, offset));
+									}
+									n_elements += 1;
+									pos += n;
+								}
+								(Some(Err(error)), _) => {
+									items.push(Todo::Report(ValidationError {
+										offset: offset + pos,
+										error,
+									}));
+									break;
+								}
+							}
+						}
+						if pos == bytes.len() && tag == 6 && n_elements % 2 != 0 {
+							items.push(Todo::Report(ValidationError {
+								offset,
+								error: ReadError::InvalidKeyValuePair,
+							}));
+						}
+						todo.extend(items.into_iter().rev());
+						continue;
+					}
+					_ => match validate_scalar(
+						crate::encoded_with_fds(bytes, convert_fd).strict(strict),
+					) {
+						Ok(()) => continue,
+						Err(error) => ValidationError { offset, error },
+					},
+				},
+				Todo::Report(error) => error,
+			};
+			if !report(error) {
+				return;
+			}
+		}
+	}
+}
+
+enum Todo<'d> {
+	/// Check the value with the given offset.
+	Check(&'d [u8], usize),
+	/// Report an error.
+	Report(ValidationError),
+}
+
+/// Check a value that is not a map or seq.
+fn validate_scalar(value: EncodedArgdata<&dyn fd::ConvertFd>) -> Result<(), ReadError> {
+	match value.read()? {
+		Value::Str(s) => {
+			s.as_str()?;
+		}
+		Value::Fd(fd) => {
+			fd.to_fd().map_err(ReadError::InvalidFdNumber)?;
+		}
+		_ => {}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{encoded, encoded_with_fds, fd::Fd};
+
+	#[test]
+	fn validate_test() {
+		let valid = b"\x06\x82\x08\x00\x87\x07\x85\x03\x00\x00\x00\x00";
+		assert_eq!(
+			encoded_with_fds(valid, &[Fd(0)][..]).validate_deep(),
+			Ok(())
+		);
+		assert_eq!(
+			encoded(valid).validate_deep(),
+			Err(ValidationError {
+				offset: 7,
+				error: ReadError::InvalidFdNumber(0)
+			})
+		);
+		assert_eq!(encoded(valid).validate(), Ok(()));
+
+		let error = |data| encoded(data).validate().err();
+		assert_eq!(error(b"\x0A"), Some(ReadError::InvalidTag(10)));
+		assert_eq!(error(b"\x08a"), Some(ReadError::MissingNullTerminator));
+		assert_eq!(error(b"\x08\xFF\x00"), Some(ReadError::InvalidUtf8));
+		assert_eq!(error(b"\x02\x02"), Some(ReadError::InvalidBoolValue));
+		assert_eq!(error(b"\x04\x00"), Some(ReadError::InvalidFloatLength));
+		assert_eq!(error(b"\x03\x00"), Some(ReadError::InvalidFdLength));
+		assert_eq!(
+			error(b"\x07\x81\x02\x85\x02"),
+			Some(ReadError::InvalidSubfield)
+		);
+		assert_eq!(error(b"\x06\x81\x02"), Some(ReadError::InvalidKeyValuePair));
+		assert_eq!(error(b"\x07\x81\x0A"), None);
+	}
+
+	#[test]
+	fn validation_errors_test() {
+		// A seq containing an invalid string, a map with an invalid key and a
+		// missing value, and an incomplete subfield.
+		let data = b"\x07\x82\x08a\x84\x06\x82\x08a\x81";
+		assert_eq!(
+			encoded(data).validate_deep(),
+			Err(ValidationError {
+				offset: 2,
+				error: ReadError::MissingNullTerminator
+			})
+		);
+		assert_eq!(
+			encoded(data).validation_errors(),
+			[
+				ValidationError {
+					offset: 2,
+					error: ReadError::MissingNullTerminator
+				},
+				ValidationError {
+					offset: 7,
+					error: ReadError::MissingNullTerminator
+				},
+				ValidationError {
+					offset: 5,
+					error: ReadError::InvalidKeyValuePair
+				},
+				ValidationError {
+					offset: 9,
+					error: ReadError::InvalidSubfield
+				},
+			]
+		);
+		assert_eq!(
+			ValidationError {
+				offset: 9,
+				error: ReadError::InvalidSubfield
+			}
+			.to_string(),
+			"Argdata has an incomplete subfield at byte 9"
+		);
+	}
+
+	#[test]
+	fn validate_strict_test() {
+		// A map with a non-minimal int as value, a duplicate key, and a
+		// non-minimal subfield length.
+		let data = b"\x06\x82\x08\x00\x82\x05\x00\x82\x08\x00\x00\x80";
+		assert_eq!(encoded(data).validate_deep(), Ok(()));
+		assert_eq!(
+			encoded(data).strict(true).validation_errors(),
+			[
+				ValidationError {
+					offset: 5,
+					error: ReadError::NonMinimalInt
+				},
+				ValidationError {
+					offset: 8,
+					error: ReadError::DuplicateMapKey
+				},
+				ValidationError {
+					offset: 10,
+					error: ReadError::NonMinimalSubfieldLength
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn validate_deep_nesting_test() {
+		// The lengths of the nested seqs, from the inside out, and then their
+		// headers, from the outside in.
+		let mut lengths = vec![0];
+		while lengths.len() <= 100_000 && lengths[lengths.len() - 1] <= 1_000_000 {
+			let inner = lengths[lengths.len() - 1];
+			let mut header = vec![7];
+			crate::subfield::write_subfield_length(inner, &mut header).unwrap();
+			lengths.push(header.len() + inner);
+		}
+		let mut data = Vec::with_capacity(lengths[lengths.len() - 1]);
+		for &inner in lengths[..lengths.len() - 1].iter().rev() {
+			data.push(7);
+			crate::subfield::write_subfield_length(inner, &mut data).unwrap();
+		}
+		assert_eq!(data.len(), lengths[lengths.len() - 1]);
+		assert_eq!(encoded(&data).validate_deep(), Ok(()));
+	}
+}
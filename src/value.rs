@@ -1,7 +1,9 @@
 use crate::{fd, IntValue, MapIterator, SeqIterator, StrValue, Timespec};
 
 /// The type of an argdata value.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+///
+/// Types are ordered as they are listed here.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Type {
 	Null,
 	Binary,
@@ -31,8 +33,8 @@ pub enum Value<'a, 'd: 'a> {
 }
 
 impl<'a, 'd: 'a> Value<'a, 'd> {
+	#[rustfmt::skip]
 	pub fn get_type(&self) -> Type {
-		#[cfg_attr(rustfmt, rustfmt_skip)]
 		match self {
 			Value::Null         => Type::Null,
 			Value::Binary(_)    => Type::Binary,
@@ -1,20 +1,109 @@
 use crate::{
-	fd, fd::EncodedFd, subfield::read_subfield, Argdata, ArgdataRef, IntValue, MapIterable,
-	MapIterator, NoFit, NotRead, ReadError, SeqIterable, SeqIterator, StrValue, Timespec, Type,
+	fd,
+	fd::EncodedFd,
+	intvalue::is_minimal_bigint,
+	subfield::{read_canonical_subfield, read_subfield},
+	Argdata, ArgdataRef, EncodedIndex, IntValue, MapIterable, MapIterator, NoFit, NotRead,
+	ReadError, SeqIterable, SeqIterator, StrValue, Timespec, Type,
 };
 use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashSet;
 use std::io;
 
 #[derive(Clone, Copy, Debug)]
 pub struct EncodedArgdata<'d, F> {
 	encoded: &'d [u8],
 	convert_fd: F,
+	strict: bool,
 }
 
 impl<'d, F: fd::ConvertFd> EncodedArgdata<'d, F> {
 	pub fn bytes(&self) -> &'d [u8] {
 		self.encoded
 	}
+
+	pub(crate) fn convert_fd(&self) -> &F {
+		&self.convert_fd
+	}
+
+	pub(crate) fn into_convert_fd(self) -> F {
+		self.convert_fd
+	}
+
+	/// Only accept canonically encoded data, such that a value can only be
+	/// encoded in one way.
+	///
+	/// In strict mode, reading fails with a `ReadError` for integers,
+	/// timestamps and subfield lengths with redundant leading bytes, and for
+	/// maps that contain the same (encoded) key more than once. This applies
+	/// to everything inside this value as well.
+	///
+	/// Map keys don't need to be sorted. Use
+	/// [`EncodedIndex::new_sorted`](crate::EncodedIndex::new_sorted) to check that.
+	///
+	/// To find duplicate keys, every `read_map()` in strict mode reads the
+	/// whole map and puts its keys in a hash set before returning. To look up
+	/// keys in a large map, or to read it more than once, build an
+	/// [`index`](EncodedArgdata::index) instead, which checks this only once.
+	pub fn strict(self, strict: bool) -> Self {
+		EncodedArgdata { strict, ..self }
+	}
+
+	/// Whether this value is read in [strict](EncodedArgdata::strict) mode.
+	pub fn is_strict(&self) -> bool {
+		self.strict
+	}
+
+	/// Read the subfield at `offset`, which must be minimally encoded in
+	/// strict mode.
+	pub(crate) fn read_subfield(
+		&self,
+		offset: usize,
+	) -> (Option<Result<&'d [u8], ReadError>>, usize) {
+		if self.strict {
+			read_canonical_subfield(&self.encoded[offset..])
+		} else {
+			read_subfield(&self.encoded[offset..])
+		}
+	}
+
+	/// Refer to a value inside of this one, read in the same mode.
+	pub(crate) fn subfield_ref<'a>(&'a self, bytes: &'d [u8]) -> ArgdataRef<'a, 'd>
+	where
+		'd: 'a,
+	{
+		ArgdataRef::from_encoded(encoded_with_fds(bytes, &self.convert_fd as _).strict(self.strict))
+	}
+
+	/// Check that the keys of this map are unique, by their encoded bytes.
+	fn check_unique_keys(&self) -> Result<(), ReadError> {
+		let mut keys = HashSet::new();
+		let mut offset = 1;
+		while let (Some(key), n) = self.read_subfield(offset) {
+			offset += n;
+			if !keys.insert(key?) {
+				return Err(ReadError::DuplicateMapKey);
+			}
+			match self.read_subfield(offset) {
+				(Some(value), n) => {
+					value?;
+					offset += n;
+				}
+				(None, _) => return Err(ReadError::InvalidKeyValuePair),
+			}
+		}
+		Ok(())
+	}
+
+	/// Index the elements of this seq or map, for random access.
+	///
+	/// See [`EncodedIndex::new`].
+	pub fn index(&self) -> Result<EncodedIndex<'d, F>, NotRead>
+	where
+		F: Clone,
+	{
+		EncodedIndex::new(self.clone())
+	}
 }
 
 /// Create an argdata value directly from an encoded argdata buffer.
@@ -24,6 +113,7 @@ pub fn encoded<'d>(encoded: &'d [u8]) -> EncodedArgdata<'d, fd::NoConvert> {
 	EncodedArgdata {
 		encoded,
 		convert_fd: fd::NoConvert,
+		strict: false,
 	}
 }
 
@@ -40,6 +130,7 @@ pub fn encoded_with_fds<'d, F: fd::ConvertFd>(
 	EncodedArgdata {
 		encoded,
 		convert_fd,
+		strict: false,
 	}
 }
 
@@ -107,6 +198,9 @@ impl<'d, F: fd::ConvertFd> Argdata<'d> for EncodedArgdata<'d, F> {
 
 	fn read_int_value(&self) -> Result<IntValue<'d>, NotRead> {
 		match self.bytes().split_first() {
+			Some((5, data)) if self.strict && !is_minimal_bigint(data) => {
+				Err(ReadError::NonMinimalInt.into())
+			}
 			Some((5, data)) => Ok(IntValue::from_bigint(data)),
 			_ => Err(NoFit::DifferentType.into()),
 		}
@@ -117,7 +211,12 @@ impl<'d, F: fd::ConvertFd> Argdata<'d> for EncodedArgdata<'d, F> {
 		'd: 'a,
 	{
 		match self.bytes().first() {
-			Some(6) => Ok(MapIterator::new(self, 1)),
+			Some(6) => {
+				if self.strict {
+					self.check_unique_keys()?;
+				}
+				Ok(MapIterator::new(self, 1))
+			}
 			_ => Err(NoFit::DifferentType.into()),
 		}
 	}
@@ -147,6 +246,9 @@ impl<'d, F: fd::ConvertFd> Argdata<'d> for EncodedArgdata<'d, F> {
 				if data.len() > 12 {
 					return Err(ReadError::TimestampOutOfRange.into());
 				}
+				if self.strict && !is_minimal_bigint(data) {
+					return Err(ReadError::NonMinimalTimestamp.into());
+				}
 
 				// Read nanoseconds into an integer (128 bits are enough).
 				let mut nsec = if data.is_empty() {
@@ -181,6 +283,10 @@ impl<'d, F: fd::ConvertFd> Argdata<'d> for EncodedArgdata<'d, F> {
 		self.encoded.len()
 	}
 
+	fn encoded_bytes(&self) -> Option<&'d [u8]> {
+		Some(self.encoded)
+	}
+
 	fn serialize(
 		&self,
 		writer: &mut dyn io::Write,
@@ -200,10 +306,7 @@ fn rewrite_serialized(
 	writer: &mut dyn io::Write,
 	fd_map: &mut dyn fd::FdMapping,
 ) -> io::Result<()> {
-	let argdata = EncodedArgdata {
-		encoded: source,
-		convert_fd,
-	};
+	let argdata = encoded_with_fds(source, convert_fd);
 	match argdata.get_type() {
 		Ok(Type::Map) | Ok(Type::Seq) => {
 			let mut last_write_offset = 0;
@@ -235,9 +338,9 @@ impl<'d, F: fd::ConvertFd> EncodedArgdata<'d, F> {
 	where
 		'd: 'a,
 	{
-		let (result, offset_delta) = read_subfield(&self.bytes()[*offset..]);
+		let (result, offset_delta) = self.read_subfield(*offset);
 		*offset += offset_delta;
-		result.map(|r| r.map(|d| ArgdataRef::encoded(d, &self.convert_fd)))
+		result.map(|r| r.map(|d| self.subfield_ref(d)))
 	}
 }
 
@@ -249,7 +352,7 @@ impl<'d, F: fd::ConvertFd> SeqIterable<'d> for EncodedArgdata<'d, F> {
 	where
 		'd: 'a,
 	{
-		if self.bytes().get(0) != Some(&7) {
+		if self.bytes().first() != Some(&7) {
 			return None;
 		}
 		self.iter_subfield_next(offset)
@@ -264,7 +367,7 @@ impl<'d, F: fd::ConvertFd> MapIterable<'d> for EncodedArgdata<'d, F> {
 	where
 		'd: 'a,
 	{
-		if self.bytes().get(0) != Some(&6) {
+		if self.bytes().first() != Some(&6) {
 			return None;
 		}
 		let key = match self.iter_subfield_next(offset) {
@@ -278,6 +381,45 @@ impl<'d, F: fd::ConvertFd> MapIterable<'d> for EncodedArgdata<'d, F> {
 			Some(Err(e)) => Some(Err(e)),
 		}
 	}
+
+	/// Compares the encoded keys to the encoded form of `key`, without
+	/// decoding them.
+	fn iter_map_get_str<'a>(
+		&'a self,
+		mut offset: usize,
+		key: &str,
+	) -> Result<Option<ArgdataRef<'a, 'd>>, ReadError>
+	where
+		'd: 'a,
+	{
+		if self.bytes().first() != Some(&6) {
+			return Ok(None);
+		}
+		let key = key.as_bytes();
+		loop {
+			let k = match self.read_subfield(offset) {
+				(None, _) => return Ok(None),
+				(Some(k), n) => {
+					offset += n;
+					k?
+				}
+			};
+			let value = match self.read_subfield(offset) {
+				(None, _) => return Err(ReadError::InvalidKeyValuePair),
+				(Some(v), n) => {
+					offset += n;
+					v?
+				}
+			};
+			if k.len() == key.len() + 2
+				&& k[0] == 8 && &k[1..k.len() - 1] == key
+				&& k[k.len() - 1] == 0
+			{
+				// Only valid UTF-8 strings can be equal to `key`.
+				return Ok(Some(self.subfield_ref(value)));
+			}
+		}
+	}
 }
 
 #[test]
@@ -398,7 +540,7 @@ fn read_float_test() {
 	);
 	assert_eq!(
 		encoded(b"\x04\x7F\xF0\x00\x00\x00\x00\x00\x00").read_float(),
-		Ok(std::f64::INFINITY)
+		Ok(f64::INFINITY)
 	);
 	assert!(encoded(b"\x04\xFF\xFF\xFF\xFF\xFF\x00\x00\x00")
 		.read_float()
@@ -476,7 +618,7 @@ fn read_map_test() {
 			.unwrap()
 			.map(|e| e.map(|(k, v)| (k.read_int().unwrap(), v.read_int().unwrap(),)))
 			.collect::<Vec<_>>(),
-		[Ok((0, 1)), Err(ReadError::InvalidKeyValuePair.into())]
+		[Ok((0, 1)), Err(ReadError::InvalidKeyValuePair)]
 	);
 }
 
@@ -506,7 +648,7 @@ fn read_seq_test() {
 			.unwrap()
 			.map(|e| e.map(|e| e.read_int().unwrap()))
 			.collect::<Vec<_>>(),
-		[Ok(0), Ok(1), Err(ReadError::InvalidSubfield.into())]
+		[Ok(0), Ok(1), Err(ReadError::InvalidSubfield)]
 	);
 	assert_eq!(
 		encoded(b"\x07\x81\x05\x82\x05\x01\x01\x01\x01")
@@ -514,7 +656,7 @@ fn read_seq_test() {
 			.unwrap()
 			.map(|e| e.map(|e| e.read_int().unwrap()))
 			.collect::<Vec<_>>(),
-		[Ok(0), Ok(1), Err(ReadError::InvalidSubfield.into())]
+		[Ok(0), Ok(1), Err(ReadError::InvalidSubfield)]
 	);
 }
 
@@ -590,6 +732,73 @@ fn read_timestamp_test() {
 	);
 }
 
+#[test]
+fn strict_test() {
+	use crate::{get_path, ArgdataExt};
+	let strict = |data| encoded(data).strict(true);
+
+	assert_eq!(encoded(b"\x05\x00").read_int(), Ok(0));
+	assert_eq!(strict(b"\x05").read_int(), Ok(0));
+	assert_eq!(strict(b"\x05\xFF").read_int(), Ok(-1));
+	assert_eq!(strict(b"\x05\x00\x80").read_int(), Ok(128));
+	assert_eq!(strict(b"\x05\xFF\x7F").read_int(), Ok(-129));
+	for data in [&b"\x05\x00"[..], b"\x05\x00\x7F", b"\x05\xFF\xFF"] {
+		assert_eq!(
+			strict(data).read_int::<i32>(),
+			Err(ReadError::NonMinimalInt.into())
+		);
+	}
+
+	assert_eq!(
+		encoded(b"\x09\x00\x01").read_timestamp(),
+		Ok(Timespec { sec: 0, nsec: 1 })
+	);
+	assert_eq!(
+		strict(b"\x09\x00\x01").read_timestamp(),
+		Err(ReadError::NonMinimalTimestamp.into())
+	);
+
+	let seq = strict(b"\x07\x00\x81\x02");
+	assert_eq!(
+		seq.read_seq().unwrap().next().map(|e| e.err()),
+		Some(Some(ReadError::NonMinimalSubfieldLength))
+	);
+
+	// Values inside of a strict value are read in strict mode as well.
+	let seq = strict(b"\x07\x82\x05\x00");
+	assert_eq!(
+		seq.read_seq()
+			.unwrap()
+			.next()
+			.unwrap()
+			.unwrap()
+			.read_int::<i32>(),
+		Err(ReadError::NonMinimalInt.into())
+	);
+	assert_eq!(
+		get_path(&seq, "[0]").unwrap().read_int::<i32>(),
+		Err(ReadError::NonMinimalInt.into())
+	);
+
+	let map = b"\x06\x82\x08\x00\x80\x82\x08\x00\x81\x02";
+	assert_eq!(encoded(map).read_map().unwrap().count(), 2);
+	assert_eq!(
+		strict(map).read_map().err(),
+		Some(ReadError::DuplicateMapKey.into())
+	);
+	assert_eq!(
+		strict(b"\x06\x82\x08\x00").read_map().err(),
+		Some(ReadError::InvalidKeyValuePair.into())
+	);
+	assert_eq!(
+		strict(b"\x06\x82\x08\x00\x80\x83\x08a\x00\x80")
+			.read_map()
+			.unwrap()
+			.count(),
+		2
+	);
+}
+
 #[test]
 fn serialize_garbage_test() {
 	// Garbage should be let through unmodified when not rewriting file
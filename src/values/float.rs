@@ -34,8 +34,18 @@ impl<'d> Argdata<'d> for Float {
 
 	fn serialize(&self, writer: &mut dyn io::Write, _: Option<&mut dyn fd::FdMapping>) -> io::Result<()> {
 		let mut buf = [0; 9];
-		buf[0] = 5;
+		buf[0] = 4;
 		BigEndian::write_f64(&mut buf[1..], self.value);
 		writer.write_all(&buf)
 	}
 }
+
+#[test]
+fn float_serialize_test() {
+	let value = float(1.5);
+	let mut buf = Vec::new();
+	value.serialize(&mut buf, None).unwrap();
+	assert_eq!(buf, b"\x04\x3F\xF8\x00\x00\x00\x00\x00\x00");
+	assert_eq!(value.serialized_length(), buf.len());
+	assert_eq!(crate::encoded(&buf).read_float(), Ok(1.5));
+}
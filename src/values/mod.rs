@@ -27,6 +27,9 @@ pub use self::int::{int, Int};
 mod map;
 pub use self::map::{map, Map};
 
+mod owned;
+pub use self::owned::{Owned, OwnedArgdata};
+
 mod seq;
 pub use self::seq::{seq, Seq};
 
@@ -0,0 +1,420 @@
+use crate::{
+	fd,
+	parse::MAX_DEPTH,
+	subfield::{subfield_length, write_subfield_length},
+	values::EncodedArgdata,
+	Argdata, ArgdataRef, IntValue, MapIterable, MapIterator, ReadError, SeqIterable, SeqIterator,
+	StrValue, Timespec, Value,
+};
+use std::io;
+use std::marker::PhantomData;
+
+/// An owned argdata value.
+///
+/// Unlike the other argdata values, this doesn't borrow anything. It can be
+/// kept around after the data it was read from is gone, and can be built up
+/// and modified dynamically.
+///
+/// Use [`argdata()`](#method.argdata) to use it as an [`Argdata`] value.
+#[derive(Clone)]
+pub enum OwnedArgdata {
+	Null,
+	Binary(Vec<u8>),
+	Bool(bool),
+	Fd(fd::Fd),
+	Float(f64),
+	/// A big-endian 2's-complement signed integer of arbitrary length.
+	///
+	/// Use `OwnedArgdata::from(value)` to create one from a primitive integer
+	/// or an [`IntValue`].
+	Int(Vec<u8>),
+	Str(String),
+	Timestamp(Timespec),
+	Map(Vec<(OwnedArgdata, OwnedArgdata)>),
+	Seq(Vec<OwnedArgdata>),
+}
+
+/// An [`OwnedArgdata`] borrowed for `'d`, which implements [`Argdata<'d>`](Argdata).
+///
+/// Get one using [`OwnedArgdata::argdata`].
+#[repr(transparent)]
+pub struct Owned<'d> {
+	// Invariant: An Owned<'d> only exists behind a reference to an
+	// OwnedArgdata that is borrowed for 'd.
+	value: OwnedArgdata,
+	borrow: PhantomData<&'d OwnedArgdata>,
+}
+
+impl OwnedArgdata {
+	/// Read an argdata value, including everything it contains, into an owned value.
+	///
+	/// Strings are checked for valid UTF-8, and file descriptors are
+	/// converted to `Fd`s, giving a `ReadError::InvalidFdNumber` if that
+	/// isn't possible. Use [`encoded_with_fds`](crate::encoded_with_fds) with
+	/// [`fd::Identity`] to keep the raw encoded numbers instead.
+	///
+	/// Maps and seqs nested more than 256 levels deep give a
+	/// `ReadError::NestedTooDeeply`.
+	pub fn from_argdata(argdata: &(dyn Argdata + '_)) -> Result<OwnedArgdata, ReadError> {
+		OwnedArgdata::from_argdata_at(argdata, 0)
+	}
+
+	/// Read a value that is nested `depth` levels deep.
+	fn from_argdata_at(
+		argdata: &(dyn Argdata + '_),
+		depth: usize,
+	) -> Result<OwnedArgdata, ReadError> {
+		let value = argdata.read()?;
+		if depth > MAX_DEPTH && matches!(value, Value::Map(_) | Value::Seq(_)) {
+			return Err(ReadError::NestedTooDeeply);
+		}
+		let owned = |v: &(dyn Argdata + '_)| OwnedArgdata::from_argdata_at(v, depth + 1);
+		Ok(match value {
+			Value::Null => OwnedArgdata::Null,
+			Value::Binary(v) => OwnedArgdata::Binary(v.to_vec()),
+			Value::Bool(v) => OwnedArgdata::Bool(v),
+			Value::Fd(v) => OwnedArgdata::Fd(v.to_fd().map_err(ReadError::InvalidFdNumber)?),
+			Value::Float(v) => OwnedArgdata::Float(v),
+			Value::Int(v) => OwnedArgdata::from(v),
+			Value::Str(v) => OwnedArgdata::Str(v.as_str()?.to_string()),
+			Value::Timestamp(v) => OwnedArgdata::Timestamp(v),
+			Value::Map(it) => OwnedArgdata::Map(
+				it.map(|entry| {
+					let (key, value) = entry?;
+					Ok((owned(&*key)?, owned(&*value)?))
+				})
+				.collect::<Result<_, ReadError>>()?,
+			),
+			Value::Seq(it) => OwnedArgdata::Seq(
+				it.map(|value| owned(&*value?))
+					.collect::<Result<_, ReadError>>()?,
+			),
+		})
+	}
+
+	/// Borrow this value as something that implements [`Argdata`].
+	pub fn argdata(&self) -> &Owned<'_> {
+		Owned::new(self)
+	}
+}
+
+impl<'a, 'd> dyn Argdata<'d> + 'a {
+	/// Read this value into an [`OwnedArgdata`].
+	///
+	/// See [`OwnedArgdata::from_argdata`].
+	pub fn to_owned(&self) -> Result<OwnedArgdata, ReadError> {
+		OwnedArgdata::from_argdata(self)
+	}
+}
+
+impl<'a, 'd> ArgdataRef<'a, 'd> {
+	/// Read the referenced value into an [`OwnedArgdata`].
+	///
+	/// See [`OwnedArgdata::from_argdata`].
+	pub fn into_owned(self) -> Result<OwnedArgdata, ReadError> {
+		OwnedArgdata::from_argdata(&*self)
+	}
+}
+
+impl<'d, F: fd::ConvertFd> EncodedArgdata<'d, F> {
+	/// Decode this value into an [`OwnedArgdata`].
+	///
+	/// See [`OwnedArgdata::from_argdata`].
+	#[allow(clippy::wrong_self_convention)]
+	pub fn to_owned(&self) -> Result<OwnedArgdata, ReadError> {
+		OwnedArgdata::from_argdata(self)
+	}
+
+	/// Decode this value into an [`OwnedArgdata`].
+	///
+	/// See [`OwnedArgdata::from_argdata`].
+	pub fn into_owned(self) -> Result<OwnedArgdata, ReadError> {
+		OwnedArgdata::from_argdata(&self)
+	}
+}
+
+impl<'d> Owned<'d> {
+	fn new(value: &'d OwnedArgdata) -> &'d Owned<'d> {
+		// Safe because Owned is a transparent wrapper around OwnedArgdata,
+		// and `value` is borrowed for 'd.
+		unsafe { &*(value as *const OwnedArgdata as *const Owned<'d>) }
+	}
+
+	/// The owned value.
+	pub fn value(&self) -> &'d OwnedArgdata {
+		// Safe because of the invariant of Owned: self.value is borrowed for 'd.
+		unsafe { &*(&self.value as *const OwnedArgdata) }
+	}
+}
+
+impl<'d> Argdata<'d> for Owned<'d> {
+	fn read<'a>(&'a self) -> Result<Value<'a, 'd>, ReadError>
+	where
+		'd: 'a,
+	{
+		Ok(match self.value() {
+			OwnedArgdata::Null => Value::Null,
+			OwnedArgdata::Binary(v) => Value::Binary(v),
+			OwnedArgdata::Bool(v) => Value::Bool(*v),
+			OwnedArgdata::Fd(v) => Value::Fd(crate::encoded_fd(v.0 as u32, &fd::Identity)),
+			OwnedArgdata::Float(v) => Value::Float(*v),
+			OwnedArgdata::Int(v) => Value::Int(IntValue::from_bigint(v)),
+			OwnedArgdata::Str(v) => Value::Str(StrValue::from_str(v)),
+			OwnedArgdata::Timestamp(v) => Value::Timestamp(*v),
+			OwnedArgdata::Map(_) => Value::Map(MapIterator::new(self, 0)),
+			OwnedArgdata::Seq(_) => Value::Seq(SeqIterator::new(self, 0)),
+		})
+	}
+
+	fn serialized_length(&self) -> usize {
+		match self.value() {
+			OwnedArgdata::Null => 0,
+			OwnedArgdata::Binary(v) => crate::binary(v).serialized_length(),
+			OwnedArgdata::Bool(v) => crate::bool(*v).serialized_length(),
+			OwnedArgdata::Fd(v) => Argdata::serialized_length(v),
+			OwnedArgdata::Float(v) => crate::float(*v).serialized_length(),
+			OwnedArgdata::Int(v) => crate::bigint(v).serialized_length(),
+			OwnedArgdata::Str(v) => crate::str(v).serialized_length(),
+			OwnedArgdata::Timestamp(v) => crate::timestamp(*v).serialized_length(),
+			OwnedArgdata::Map(v) => {
+				1 + v
+					.iter()
+					.map(|(k, v)| {
+						subfield_length(k.argdata().serialized_length())
+							+ subfield_length(v.argdata().serialized_length())
+					})
+					.sum::<usize>()
+			}
+			OwnedArgdata::Seq(v) => {
+				1 + v
+					.iter()
+					.map(|a| subfield_length(a.argdata().serialized_length()))
+					.sum::<usize>()
+			}
+		}
+	}
+
+	fn serialize(
+		&self,
+		writer: &mut dyn io::Write,
+		mut fd_map: Option<&mut dyn fd::FdMapping>,
+	) -> io::Result<()> {
+		match self.value() {
+			OwnedArgdata::Null => Ok(()),
+			OwnedArgdata::Binary(v) => crate::binary(v).serialize(writer, fd_map),
+			OwnedArgdata::Bool(v) => crate::bool(*v).serialize(writer, fd_map),
+			OwnedArgdata::Fd(v) => Argdata::serialize(v, writer, fd_map),
+			OwnedArgdata::Float(v) => crate::float(*v).serialize(writer, fd_map),
+			OwnedArgdata::Int(v) => crate::bigint(v).serialize(writer, fd_map),
+			OwnedArgdata::Str(v) => crate::str(v).serialize(writer, fd_map),
+			OwnedArgdata::Timestamp(v) => crate::timestamp(*v).serialize(writer, fd_map),
+			OwnedArgdata::Map(v) => {
+				writer.write_all(&[6])?;
+				for (k, v) in v {
+					for a in &[k.argdata(), v.argdata()] {
+						write_subfield_length(a.serialized_length(), writer)?;
+						a.serialize(writer, fd_map.as_mut().map(|x| *x as _))?;
+					}
+				}
+				Ok(())
+			}
+			OwnedArgdata::Seq(v) => {
+				writer.write_all(&[7])?;
+				for a in v {
+					let a = a.argdata();
+					write_subfield_length(a.serialized_length(), writer)?;
+					a.serialize(writer, fd_map.as_mut().map(|x| *x as _))?;
+				}
+				Ok(())
+			}
+		}
+	}
+}
+
+impl<'d> SeqIterable<'d> for Owned<'d> {
+	fn iter_seq_next<'a>(
+		&'a self,
+		cookie: &mut usize,
+	) -> Option<Result<ArgdataRef<'a, 'd>, ReadError>>
+	where
+		'd: 'a,
+	{
+		match self.value() {
+			OwnedArgdata::Seq(v) => v.get(*cookie).map(|a| {
+				*cookie += 1;
+				Ok(ArgdataRef::reference(Owned::new(a)))
+			}),
+			_ => None,
+		}
+	}
+}
+
+impl<'d> MapIterable<'d> for Owned<'d> {
+	fn iter_map_next<'a>(
+		&'a self,
+		cookie: &mut usize,
+	) -> Option<Result<(ArgdataRef<'a, 'd>, ArgdataRef<'a, 'd>), ReadError>>
+	where
+		'd: 'a,
+	{
+		match self.value() {
+			OwnedArgdata::Map(v) => v.get(*cookie).map(|(k, v)| {
+				*cookie += 1;
+				Ok((
+					ArgdataRef::reference(Owned::new(k)),
+					ArgdataRef::reference(Owned::new(v)),
+				))
+			}),
+			_ => None,
+		}
+	}
+}
+
+impl<'a> From<IntValue<'a>> for OwnedArgdata {
+	fn from(value: IntValue<'a>) -> OwnedArgdata {
+		let mut v = Vec::with_capacity(value.serialized_length());
+		value.serialize(&mut v).unwrap();
+		OwnedArgdata::Int(v)
+	}
+}
+
+macro_rules! impl_from_int {
+	($($t:ty)*) => {
+		$(
+			impl From<$t> for OwnedArgdata {
+				fn from(value: $t) -> OwnedArgdata {
+					IntValue::from(value).into()
+				}
+			}
+		)*
+	};
+}
+
+impl_from_int!(i8 i16 i32 i64 u8 u16 u32 u64);
+
+impl From<bool> for OwnedArgdata {
+	fn from(value: bool) -> OwnedArgdata {
+		OwnedArgdata::Bool(value)
+	}
+}
+
+impl From<f64> for OwnedArgdata {
+	fn from(value: f64) -> OwnedArgdata {
+		OwnedArgdata::Float(value)
+	}
+}
+
+impl From<fd::Fd> for OwnedArgdata {
+	fn from(value: fd::Fd) -> OwnedArgdata {
+		OwnedArgdata::Fd(value)
+	}
+}
+
+impl From<Timespec> for OwnedArgdata {
+	fn from(value: Timespec) -> OwnedArgdata {
+		OwnedArgdata::Timestamp(value)
+	}
+}
+
+impl<'a> From<&'a str> for OwnedArgdata {
+	fn from(value: &'a str) -> OwnedArgdata {
+		OwnedArgdata::Str(value.to_string())
+	}
+}
+
+impl From<String> for OwnedArgdata {
+	fn from(value: String) -> OwnedArgdata {
+		OwnedArgdata::Str(value)
+	}
+}
+
+impl<'a> From<&'a [u8]> for OwnedArgdata {
+	fn from(value: &'a [u8]) -> OwnedArgdata {
+		OwnedArgdata::Binary(value.to_vec())
+	}
+}
+
+impl From<Vec<u8>> for OwnedArgdata {
+	fn from(value: Vec<u8>) -> OwnedArgdata {
+		OwnedArgdata::Binary(value)
+	}
+}
+
+impl From<Vec<OwnedArgdata>> for OwnedArgdata {
+	fn from(value: Vec<OwnedArgdata>) -> OwnedArgdata {
+		OwnedArgdata::Seq(value)
+	}
+}
+
+impl From<Vec<(OwnedArgdata, OwnedArgdata)>> for OwnedArgdata {
+	fn from(value: Vec<(OwnedArgdata, OwnedArgdata)>) -> OwnedArgdata {
+		OwnedArgdata::Map(value)
+	}
+}
+
+#[test]
+fn owned_roundtrip_test() {
+	let data = b"\x06\x87\x08Hello\x00\x87\x08World\x00\x81\x02\x82\x02\x01\x86\x09\
+		\x70\xF1\x80\x29\x15\x84\x05\x58\xe5\xd9\x80\x83\x06\x80\x80\x81\x07\x89\x04\
+		\x3F\xF8\x00\x00\x00\x00\x00\x00\x82\x01\xAA\x8B\x05\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0A";
+
+	let owned = crate::encoded(&data[..]).to_owned().unwrap();
+
+	assert_eq!(
+		format!("{:?}", owned),
+		"{\"Hello\": \"World\", false: true, timestamp(485, 88045333): 5826009, \
+		 null: {null: null}, []: 1.5, binary([170]): 4759477275222530853130}"
+	);
+
+	let mut buf = Vec::new();
+	owned.argdata().serialize(&mut buf, None).unwrap();
+	assert_eq!(owned.argdata().serialized_length(), buf.len());
+	assert_eq!(&buf[..], &data[..]);
+}
+
+#[test]
+fn owned_build_test() {
+	let owned = OwnedArgdata::Map(vec![
+		("socket".into(), fd::Fd(3).into()),
+		(
+			"args".into(),
+			vec![(-1000).into(), "two".into(), 3.5.into(), OwnedArgdata::Null].into(),
+		),
+	]);
+
+	assert_eq!(
+		format!("{:?}", owned),
+		"{\"socket\": fd(3), \"args\": [-1000, \"two\", 3.5, null]}"
+	);
+
+	let mut buf = Vec::new();
+	let mut fds = Vec::new();
+	owned.argdata().serialize(&mut buf, Some(&mut fds)).unwrap();
+	assert_eq!(owned.argdata().serialized_length(), buf.len());
+	assert_eq!(&fds, &[fd::Fd(3)]);
+
+	let convert = fd::ConvertFdFn(|raw| fds.get(raw as usize).cloned().ok_or(fd::InvalidFd));
+	let decoded = crate::encoded_with_fds(&buf[..], convert).to_owned();
+	assert_eq!(decoded.unwrap(), owned);
+}
+
+#[test]
+fn owned_fd_test() {
+	assert_eq!(
+		crate::encoded(b"\x03\x00\x00\x00\x02").to_owned().err(),
+		Some(ReadError::InvalidFdNumber(2))
+	);
+	let owned = crate::encoded_with_fds(b"\x03\x00\x00\x00\x02", fd::Identity).to_owned();
+	assert_eq!(format!("{:?}", owned.unwrap()), "fd(2)");
+}
+
+#[test]
+fn owned_deep_nesting_test() {
+	let data = crate::parse::nested_seqs(MAX_DEPTH + 1);
+	assert!(crate::encoded(&data).to_owned().is_ok());
+	let data = crate::parse::nested_seqs(200_000);
+	assert_eq!(
+		crate::encoded(&data).to_owned().err(),
+		Some(ReadError::NestedTooDeeply)
+	);
+}
@@ -44,7 +44,7 @@ where
 	<T as Container>::Item: Argdata<'d>,
 {
 	pub fn elements(&self) -> &'d T {
-		&self.items
+		self.items
 	}
 }
 
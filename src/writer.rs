@@ -0,0 +1,319 @@
+use crate::{fd, subfield::write_subfield_length, Argdata};
+use std::io;
+
+/// Incrementally writes an argdata value, without needing all contents up front.
+///
+/// Maps and seqs are started with [`begin_map`](#method.begin_map) or
+/// [`begin_seq`](#method.begin_seq), filled with [`value`](#method.value)
+/// or nested containers, and finished with [`end`](#method.end).
+/// Map entries are written as alternating keys and values.
+///
+/// Since every element of a container is prefixed with its length, nested
+/// containers are buffered until they are ended. Elements of the outermost
+/// container are written to the underlying writer as soon as they are complete,
+/// so only the largest element needs to fit in memory, not the whole value.
+///
+/// File descriptors are mapped using `fd_map`, just like in
+/// [`Argdata::serialize`].
+///
+/// With [`sort_maps`](#method.sort_maps), the entries of maps are sorted by
+/// their encoded keys. Those maps are buffered until they are ended, even the
+/// outermost one.
+///
+/// Example:
+///
+/// ```
+/// # use argdata::{ArgdataWriter, int, str};
+/// let mut w = ArgdataWriter::new(Vec::new(), None);
+/// w.begin_map().unwrap();
+/// w.value(&str("numbers")).unwrap();
+/// w.begin_seq().unwrap();
+/// for i in 0..3 {
+///     w.value(&int(i)).unwrap();
+/// }
+/// w.end().unwrap();
+/// w.end().unwrap();
+/// let data = w.finish();
+/// assert_eq!(data, b"\x06\x89\x08numbers\x00\x89\x07\x81\x05\x82\x05\x01\x82\x05\x02");
+/// ```
+pub struct ArgdataWriter<'f, W: io::Write> {
+	writer: W,
+	fd_map: Option<&'f mut dyn fd::FdMapping>,
+	/// The containers that are currently open, outermost first.
+	open: Vec<Container>,
+	/// Whether a complete top-level value has been written.
+	done: bool,
+	/// Whether to sort the entries of maps.
+	sort_maps: bool,
+}
+
+struct Container {
+	is_map: bool,
+	n_elements: usize,
+	/// The encoded container so far, or None for the outermost container,
+	/// which is written directly to the writer.
+	buf: Option<Vec<u8>>,
+	/// The encoded elements of a map that is to be sorted, which are written
+	/// to `buf` or the writer when the map is ended.
+	unsorted: Option<Vec<Vec<u8>>>,
+}
+
+impl<'f, W: io::Write> ArgdataWriter<'f, W> {
+	/// Create a writer that writes a single argdata value to `writer`.
+	pub fn new(writer: W, fd_map: Option<&'f mut dyn fd::FdMapping>) -> Self {
+		ArgdataWriter {
+			writer,
+			fd_map,
+			open: Vec::new(),
+			done: false,
+			sort_maps: false,
+		}
+	}
+
+	/// Sort the entries of all maps that are started after this, by the
+	/// bytes of their encoded keys.
+	///
+	/// This results in maps that can be indexed with
+	/// [`EncodedIndex::new_sorted`](crate::EncodedIndex::new_sorted), if they
+	/// don't contain duplicate keys. Entries with the same key keep their
+	/// order.
+	pub fn sort_maps(mut self, sort: bool) -> Self {
+		self.sort_maps = sort;
+		self
+	}
+
+	/// Start a map. Its entries are written as alternating keys and values.
+	///
+	/// Panics if a complete value was already written.
+	pub fn begin_map(&mut self) -> io::Result<()> {
+		self.begin(true)
+	}
+
+	/// Start a seq.
+	///
+	/// Panics if a complete value was already written.
+	pub fn begin_seq(&mut self) -> io::Result<()> {
+		self.begin(false)
+	}
+
+	fn begin(&mut self, is_map: bool) -> io::Result<()> {
+		assert!(
+			!self.done,
+			"ArgdataWriter already contains a complete value"
+		);
+		let tag = if is_map { 6 } else { 7 };
+		let buf = if self.open.is_empty() {
+			self.writer.write_all(&[tag])?;
+			None
+		} else {
+			Some(vec![tag])
+		};
+		self.open.push(Container {
+			is_map,
+			n_elements: 0,
+			buf,
+			unsorted: if is_map && self.sort_maps {
+				Some(Vec::new())
+			} else {
+				None
+			},
+		});
+		Ok(())
+	}
+
+	/// End the innermost map or seq that was started.
+	///
+	/// Panics if there is no open map or seq, or if a map has a key without a value.
+	pub fn end(&mut self) -> io::Result<()> {
+		let mut container = self.open.pop().expect("no open map or seq to end");
+		assert!(
+			!container.is_map || container.n_elements.is_multiple_of(2),
+			"map ended after a key without a value"
+		);
+		if let Some(elements) = container.unsorted.take() {
+			let mut entries: Vec<&[Vec<u8>]> = elements.chunks(2).collect();
+			entries.sort_by(|a, b| a[0].cmp(&b[0]));
+			let writer: &mut dyn io::Write = match &mut container.buf {
+				Some(buf) => buf,
+				None => &mut self.writer,
+			};
+			for element in entries.iter().flat_map(|entry| entry.iter()) {
+				write_subfield_length(element.len(), writer)?;
+				writer.write_all(element)?;
+			}
+		}
+		match container.buf {
+			Some(buf) => self.element(buf),
+			None => {
+				self.done = true;
+				Ok(())
+			}
+		}
+	}
+
+	/// Write a complete value, as element of the innermost open map or seq, or as
+	/// the top-level value.
+	///
+	/// Panics if a complete top-level value was already written.
+	pub fn value(&mut self, value: &dyn Argdata) -> io::Result<()> {
+		let fd_map = self.fd_map.as_mut().map(|x| *x as _);
+		match self.open.last_mut() {
+			None => {
+				assert!(
+					!self.done,
+					"ArgdataWriter already contains a complete value"
+				);
+				value.serialize(&mut self.writer, fd_map)?;
+				self.done = true;
+			}
+			Some(container) => {
+				if let Some(elements) = &mut container.unsorted {
+					let mut data = Vec::with_capacity(value.serialized_length());
+					value.serialize(&mut data, fd_map)?;
+					elements.push(data);
+				} else {
+					let writer: &mut dyn io::Write = match &mut container.buf {
+						Some(buf) => buf,
+						None => &mut self.writer,
+					};
+					write_subfield_length(value.serialized_length(), writer)?;
+					value.serialize(writer, fd_map)?;
+				}
+				container.n_elements += 1;
+			}
+		}
+		Ok(())
+	}
+
+	/// Add an already encoded element to the innermost open container.
+	fn element(&mut self, data: Vec<u8>) -> io::Result<()> {
+		let container = self.open.last_mut().unwrap();
+		if let Some(elements) = &mut container.unsorted {
+			elements.push(data);
+		} else {
+			let writer: &mut dyn io::Write = match &mut container.buf {
+				Some(buf) => buf,
+				None => &mut self.writer,
+			};
+			write_subfield_length(data.len(), writer)?;
+			writer.write_all(&data)?;
+		}
+		container.n_elements += 1;
+		Ok(())
+	}
+
+	/// The number of maps and seqs that are started but not yet ended.
+	pub fn depth(&self) -> usize {
+		self.open.len()
+	}
+
+	/// Get the underlying writer back.
+	///
+	/// Panics if there are still open maps or seqs, or if nothing was written at all.
+	pub fn finish(self) -> W {
+		assert!(
+			self.open.is_empty(),
+			"ArgdataWriter has unended maps or seqs"
+		);
+		assert!(self.done, "ArgdataWriter has not written any value");
+		self.writer
+	}
+}
+
+impl<'f, W: io::Write> std::fmt::Debug for ArgdataWriter<'f, W> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("ArgdataWriter")
+			.field("depth", &self.open.len())
+			.field("done", &self.done)
+			.finish()
+	}
+}
+
+#[test]
+fn writer_test() {
+	use crate::{int, null, str, timestamp, OwnedArgdata, Timespec};
+
+	let mut out = Vec::new();
+	let mut w = ArgdataWriter::new(&mut out, None);
+	w.begin_seq().unwrap();
+	w.value(&int(1)).unwrap();
+	w.begin_map().unwrap();
+	w.value(&str("a")).unwrap();
+	w.begin_seq().unwrap();
+	w.end().unwrap();
+	w.value(&str("b")).unwrap();
+	w.value(&timestamp(Timespec { sec: 1, nsec: 2 })).unwrap();
+	w.end().unwrap();
+	w.value(&null()).unwrap();
+	assert_eq!(w.depth(), 1);
+	w.end().unwrap();
+	w.finish();
+
+	let expected = OwnedArgdata::Seq(vec![
+		1.into(),
+		OwnedArgdata::Map(vec![
+			("a".into(), OwnedArgdata::Seq(vec![])),
+			("b".into(), Timespec { sec: 1, nsec: 2 }.into()),
+		]),
+		OwnedArgdata::Null,
+	]);
+	let expected = expected.argdata();
+	let mut buf = Vec::new();
+	expected.serialize(&mut buf, None).unwrap();
+	assert_eq!(out, buf);
+}
+
+#[test]
+fn writer_fd_test() {
+	use crate::{fd::Fd, process_fd};
+
+	let mut fds = Vec::new();
+	let mut w = ArgdataWriter::new(Vec::new(), Some(&mut fds));
+	w.begin_seq().unwrap();
+	w.begin_seq().unwrap();
+	w.value(&process_fd(10)).unwrap();
+	w.end().unwrap();
+	w.value(&process_fd(20)).unwrap();
+	w.end().unwrap();
+	let out = w.finish();
+
+	assert_eq!(fds, vec![Fd(10), Fd(20)]);
+	assert_eq!(out, b"\x07\x87\x07\x85\x03\0\0\0\0\x85\x03\0\0\0\x01");
+}
+
+#[test]
+#[should_panic(expected = "map ended after a key without a value")]
+fn writer_incomplete_map_test() {
+	let mut w = ArgdataWriter::new(Vec::new(), None);
+	w.begin_map().unwrap();
+	w.value(&crate::null()).unwrap();
+	w.end().unwrap();
+}
+
+#[test]
+fn writer_sort_maps_test() {
+	use crate::{int, str};
+
+	let mut w = ArgdataWriter::new(Vec::new(), None).sort_maps(true);
+	w.begin_map().unwrap();
+	w.value(&str("b")).unwrap();
+	w.begin_map().unwrap();
+	w.value(&int(2)).unwrap();
+	w.value(&int(0)).unwrap();
+	w.value(&int(1)).unwrap();
+	w.value(&int(0)).unwrap();
+	w.end().unwrap();
+	w.value(&str("a")).unwrap();
+	w.begin_seq().unwrap();
+	w.value(&int(2)).unwrap();
+	w.value(&int(1)).unwrap();
+	w.end().unwrap();
+	w.end().unwrap();
+	let out = w.finish();
+
+	assert_eq!(
+		out,
+		&b"\x06\x83\x08a\x00\x87\x07\x82\x05\x02\x82\x05\x01\
+		   \x83\x08b\x00\x8B\x06\x82\x05\x01\x81\x05\x82\x05\x02\x81\x05"[..]
+	);
+}
@@ -0,0 +1,608 @@
+//! The printer writes the style of YAML that `argdata_print_yaml` from the C
+//! implementation writes: every value is explicitly tagged and written as a
+//! quoted scalar or a flow collection, with file descriptors tagged as `!fd`
+//! using the `tag:nuxi.nl,2015:cloudabi/` prefix. The output is not checked
+//! against that of the C implementation, and is not byte for byte the same:
+//! floats, for example, are formatted the way Rust formats them.
+//!
+//! The parser reads that output back, and more generally any YAML document
+//! consisting of double-quoted scalars and flow collections, with or without
+//! tags. Untagged scalars are read as strings. Plain (unquoted) scalars and
+//! block collections are not supported.
+//!
+//! Example:
+//!
+//! ```
+//! # use argdata::{yaml, OwnedArgdata};
+//! let value = OwnedArgdata::Seq(vec![1.into(), "two".into()]);
+//! let text = yaml::Yaml(value.argdata()).to_string();
+//! assert_eq!(
+//!     text,
+//!     "%TAG ! tag:nuxi.nl,2015:cloudabi/\n---\n\
+//!      !!seq [\n  !!int \"1\",\n  !!str \"two\",\n]\n"
+//! );
+//! let parsed = yaml::from_str(&text).unwrap();
+//! assert_eq!(parsed, value);
+//! ```
+
+use crate::{
+	base64,
+	debug::{write_value, Notation},
+	fd, intvalue,
+	parse::{Input, Parse, MAX_DEPTH},
+	rfc3339, Argdata, OwnedArgdata, ParseError, ReadError, Value,
+};
+use std::fmt::{self, Display};
+
+/// The prefix of the standard YAML tags, written as `!!`.
+const YAML_PREFIX: &str = "tag:yaml.org,2002:";
+
+/// The prefix of the CloudABI specific tags, written as `!`.
+const CLOUDABI_PREFIX: &str = "tag:nuxi.nl,2015:cloudabi/";
+
+/// Formats an argdata value as a YAML document, in the style of
+/// `argdata_print_yaml`.
+///
+/// Values that can't be read are written as `!!null` with the error message
+/// as content.
+#[derive(Debug)]
+pub struct Yaml<'a, 'd: 'a>(pub &'a (dyn Argdata<'d> + 'a));
+
+impl<'a, 'd: 'a> Display for Yaml<'a, 'd> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "%TAG ! {}", CLOUDABI_PREFIX)?;
+		writeln!(f, "---")?;
+		write_value(&mut YamlNotation(f), self.0.read(), 0)?;
+		writeln!(f)
+	}
+}
+
+/// The notation of the YAML documents, in the style of `argdata_print_yaml`.
+struct YamlNotation<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl<'a, 'b> YamlNotation<'a, 'b> {
+	fn newline(&mut self, depth: usize) -> fmt::Result {
+		writeln!(self.0)?;
+		for _ in 0..depth {
+			write!(self.0, "  ")?;
+		}
+		Ok(())
+	}
+}
+
+impl<'a, 'b> Notation for YamlNotation<'a, 'b> {
+	type Error = fmt::Error;
+
+	fn scalar(&mut self, value: Value) -> fmt::Result {
+		let f = &mut *self.0;
+		match value {
+			Value::Null => write!(f, "!!null \"null\""),
+			Value::Binary(v) => write!(f, "!!binary \"{}\"", base64::encode(v)),
+			Value::Bool(v) => write!(f, "!!bool \"{}\"", v),
+			Value::Fd(v) => write!(f, "!fd \"{}\"", v.raw_encoded_number()),
+			Value::Float(v) if v.is_nan() => write!(f, "!!float \".nan\""),
+			Value::Float(v) if v.is_infinite() && v > 0.0 => write!(f, "!!float \".inf\""),
+			Value::Float(v) if v.is_infinite() => write!(f, "!!float \"-.inf\""),
+			Value::Float(v) => write!(f, "!!float \"{:?}\"", v),
+			Value::Int(v) => write!(f, "!!int \"{}\"", v),
+			Value::Str(v) => match v.as_str() {
+				Ok(s) => {
+					write!(f, "!!str ")?;
+					write_str(f, s)
+				}
+				Err(e) => self.error(e.into()),
+			},
+			Value::Timestamp(v) => write!(f, "!!timestamp \"{}\"", rfc3339::format(v, true)),
+			Value::Map(_) | Value::Seq(_) => unreachable!(),
+		}
+	}
+
+	/// Errors are written as `!!null` with the error message as content.
+	fn error(&mut self, error: ReadError) -> fmt::Result {
+		write!(self.0, "!!null ")?;
+		write_str(self.0, &error.to_string())
+	}
+
+	fn begin(&mut self, map: bool) -> fmt::Result {
+		write!(self.0, "{}", if map { "!!map {" } else { "!!seq [" })
+	}
+
+	fn item(&mut self, map: bool, first: bool, depth: usize) -> fmt::Result {
+		if !first {
+			write!(self.0, ",")?;
+		}
+		self.newline(depth)?;
+		if map {
+			write!(self.0, "? ")?;
+		}
+		Ok(())
+	}
+
+	fn colon(&mut self, depth: usize) -> fmt::Result {
+		self.newline(depth)?;
+		write!(self.0, ": ")
+	}
+
+	fn end(&mut self, map: bool, empty: bool, depth: usize) -> fmt::Result {
+		if !empty {
+			write!(self.0, ",")?;
+			self.newline(depth)?;
+		}
+		write!(self.0, "{}", if map { "}" } else { "]" })
+	}
+}
+
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+	write!(f, "\"")?;
+	for c in s.chars() {
+		match c {
+			'"' => write!(f, "\\\"")?,
+			'\\' => write!(f, "\\\\")?,
+			'\n' => write!(f, "\\n")?,
+			'\t' => write!(f, "\\t")?,
+			c if c.is_control() && (c as u32) < 0x100 => write!(f, "\\x{:02x}", c as u32)?,
+			c => write!(f, "{}", c)?,
+		}
+	}
+	write!(f, "\"")
+}
+
+/// Parse a YAML document into an argdata value.
+///
+/// Scalars tagged as `!!null`, `!!bool`, `!!int`, `!!float`, `!!str`,
+/// `!!binary`, `!!timestamp`, or as fd (`!fd` after the `%TAG` directive that
+/// [`Yaml`] writes) are converted to the corresponding argdata types.
+/// Integers can be of any size.
+pub fn from_str(s: &str) -> Result<OwnedArgdata, ParseError> {
+	let mut parser = Parser {
+		input: Input::new(s.as_bytes()),
+		handles: Vec::new(),
+	};
+	parser.document()
+}
+
+struct Parser<'a> {
+	input: Input<'a>,
+	/// Tag handles defined by `%TAG` directives, with their prefix.
+	handles: Vec<(String, String)>,
+}
+
+impl<'a> Parse<'a> for Parser<'a> {
+	fn input(&mut self) -> &mut Input<'a> {
+		&mut self.input
+	}
+
+	/// Skip whitespace, line breaks and comments.
+	fn whitespace(&mut self) {
+		while let Some(c) = self.input.peek() {
+			match c {
+				b' ' | b'\t' | b'\n' | b'\r' => self.input.pos += 1,
+				b'#' => {
+					while !matches!(self.input.peek(), None | Some(b'\n')) {
+						self.input.pos += 1;
+					}
+				}
+				_ => break,
+			}
+		}
+	}
+}
+
+impl<'a> Parser<'a> {
+	/// Read up to (but not including) the next whitespace, or flow indicator if
+	/// `flow` is set.
+	fn token(&mut self, flow: bool) -> &'a str {
+		let start = self.input.pos;
+		while let Some(c) = self.input.peek() {
+			if c.is_ascii_whitespace() || (flow && b",[]{}".contains(&c)) {
+				break;
+			}
+			self.input.pos += 1;
+		}
+		std::str::from_utf8(&self.input.data[start..self.input.pos]).unwrap()
+	}
+
+	/// Whether the input continues with the given marker, followed by whitespace
+	/// or the end of the input.
+	fn marker(&self, marker: &[u8]) -> bool {
+		self.input.data[self.input.pos..].starts_with(marker)
+			&& self
+				.input
+				.data
+				.get(self.input.pos + marker.len())
+				.is_none_or(u8::is_ascii_whitespace)
+	}
+
+	fn document(&mut self) -> Result<OwnedArgdata, ParseError> {
+		self.whitespace();
+		let mut directives = false;
+		while self.input.peek() == Some(b'%') {
+			directives = true;
+			match self.token(false) {
+				"%TAG" => {
+					self.inline_whitespace();
+					let handle = self.token(false).to_string();
+					self.inline_whitespace();
+					let prefix = self.token(false).to_string();
+					if !handle.starts_with('!') || !handle.ends_with('!') || prefix.is_empty() {
+						return Err(self.input.error("Invalid %TAG directive"));
+					}
+					self.handles.push((handle, prefix));
+				}
+				"%YAML" => {
+					self.inline_whitespace();
+					self.token(false);
+				}
+				_ => return Err(self.input.error("Unknown directive")),
+			}
+			self.whitespace();
+		}
+		if self.marker(b"---") {
+			self.input.pos += 3;
+		} else if directives {
+			return Err(self.input.error("Expected '---' after directives"));
+		}
+		let value = self.node(0)?;
+		self.whitespace();
+		if self.marker(b"...") {
+			self.input.pos += 3;
+			self.whitespace();
+		}
+		if self.input.pos != self.input.data.len() {
+			return Err(self.input.error("Trailing characters after YAML document"));
+		}
+		Ok(value)
+	}
+
+	fn inline_whitespace(&mut self) {
+		while let Some(b' ' | b'\t') = self.input.peek() {
+			self.input.pos += 1;
+		}
+	}
+
+	/// Read a tag, and resolve it to its full name.
+	fn tag(&mut self) -> Result<String, ParseError> {
+		let start = self.input.pos;
+		if self.input.data[self.input.pos..].starts_with(b"!<") {
+			let end = self.input.data[self.input.pos..]
+				.iter()
+				.position(|&c| c == b'>');
+			return match end {
+				Some(end) => {
+					self.input.pos += end + 1;
+					Ok(
+						std::str::from_utf8(&self.input.data[start + 2..self.input.pos - 1])
+							.unwrap()
+							.to_string(),
+					)
+				}
+				None => Err(self.input.error("Invalid verbatim tag")),
+			};
+		}
+		let tag = self.token(true);
+		let error = ParseError::new(start, "Undefined tag handle");
+		let split = tag[1..].find('!').map_or(1, |i| i + 2);
+		let (handle, suffix) = tag.split_at(split);
+		match self.handles.iter().rev().find(|(h, _)| h == handle) {
+			Some((_, prefix)) => Ok(format!("{}{}", prefix, suffix)),
+			None if handle == "!!" => Ok(format!("{}{}", YAML_PREFIX, suffix)),
+			None if handle == "!" => Ok(tag.to_string()),
+			None => Err(error),
+		}
+	}
+
+	fn node(&mut self, depth: usize) -> Result<OwnedArgdata, ParseError> {
+		if depth > MAX_DEPTH {
+			return Err(self.input.error("YAML nested too deeply"));
+		}
+		self.whitespace();
+		let tag_start = self.input.pos;
+		let tag = match self.input.peek() {
+			Some(b'!') => Some(self.tag()?),
+			_ => None,
+		};
+		let tag = tag
+			.as_ref()
+			.map(|t| t.strip_prefix(YAML_PREFIX).unwrap_or(t));
+		let tag_error = ParseError::new(tag_start, "Unsupported tag");
+		self.whitespace();
+		match self.input.peek() {
+			Some(b'"') => {
+				let start = self.input.pos;
+				let s = self.string()?;
+				match scalar(tag, s) {
+					Some(Ok(value)) => Ok(value),
+					Some(Err(message)) => Err(ParseError::new(start, message)),
+					None => Err(tag_error),
+				}
+			}
+			Some(b'{') => {
+				if !matches!(tag, None | Some("map")) {
+					return Err(tag_error);
+				}
+				self.input.pos += 1;
+				let mut entries = Vec::new();
+				while !self.eat(b'}') {
+					if self.marker(b"?") {
+						// Explicit key.
+						self.input.pos += 1;
+					}
+					let key = self.node(depth + 1)?;
+					self.expect(b':', "Expected ':'")?;
+					let value = self.node(depth + 1)?;
+					entries.push((key, value));
+					if !self.eat(b',') {
+						self.expect(b'}', "Expected ',' or '}'")?;
+						break;
+					}
+				}
+				Ok(OwnedArgdata::Map(entries))
+			}
+			Some(b'[') => {
+				if !matches!(tag, None | Some("seq")) {
+					return Err(tag_error);
+				}
+				self.input.pos += 1;
+				let mut elements = Vec::new();
+				while !self.eat(b']') {
+					elements.push(self.node(depth + 1)?);
+					if !self.eat(b',') {
+						self.expect(b']', "Expected ',' or ']'")?;
+						break;
+					}
+				}
+				Ok(OwnedArgdata::Seq(elements))
+			}
+			None => Err(self.input.error("Unexpected end of YAML")),
+			Some(_) => Err(self
+				.input
+				.error("Expected a double-quoted scalar, '{' or '['")),
+		}
+	}
+
+	fn hex(&mut self, digits: usize) -> Result<char, ParseError> {
+		let c = self
+			.input
+			.data
+			.get(self.input.pos + 1..self.input.pos + 1 + digits)
+			.and_then(|d| std::str::from_utf8(d).ok())
+			.and_then(|d| u32::from_str_radix(d, 16).ok())
+			.and_then(std::char::from_u32)
+			.ok_or_else(|| self.input.error("Invalid escape sequence"))?;
+		self.input.pos += digits;
+		Ok(c)
+	}
+
+	/// Read a double-quoted scalar.
+	fn string(&mut self) -> Result<String, ParseError> {
+		self.input.pos += 1; // Opening quote.
+		let mut s = String::new();
+		let start = self.input.pos;
+		let mut plain = start;
+		loop {
+			match self.input.peek() {
+				None => return Err(self.input.error("Unterminated string")),
+				Some(b'"') => break,
+				Some(b'\n' | b'\r') => return Err(self.input.error("Line break in string")),
+				Some(b'\\') => {
+					s += std::str::from_utf8(&self.input.data[plain..self.input.pos]).unwrap();
+					self.input.pos += 1;
+					let c = match self.input.peek() {
+						Some(b'0') => '\0',
+						Some(b'a') => '\x07',
+						Some(b'b') => '\x08',
+						Some(b't' | b'\t') => '\t',
+						Some(b'n') => '\n',
+						Some(b'v') => '\x0B',
+						Some(b'f') => '\x0C',
+						Some(b'r') => '\r',
+						Some(b'e') => '\x1B',
+						Some(b' ') => ' ',
+						Some(b'"') => '"',
+						Some(b'/') => '/',
+						Some(b'\\') => '\\',
+						Some(b'N') => '\u{85}',
+						Some(b'_') => '\u{A0}',
+						Some(b'L') => '\u{2028}',
+						Some(b'P') => '\u{2029}',
+						Some(b'x') => self.hex(2)?,
+						Some(b'u') => self.hex(4)?,
+						Some(b'U') => self.hex(8)?,
+						_ => return Err(self.input.error("Invalid escape sequence")),
+					};
+					s.push(c);
+					plain = self.input.pos + 1;
+				}
+				Some(_) => {}
+			}
+			self.input.pos += 1;
+		}
+		s += std::str::from_utf8(&self.input.data[plain..self.input.pos]).unwrap();
+		self.input.pos += 1; // Closing quote.
+		Ok(s)
+	}
+}
+
+/// Convert a scalar with the given tag (without the `tag:yaml.org,2002:` prefix).
+///
+/// Returns None if the tag is not supported.
+fn scalar(tag: Option<&str>, s: String) -> Option<Result<OwnedArgdata, &'static str>> {
+	let fd_tag = &format!("{}fd", CLOUDABI_PREFIX)[..];
+	let value = match tag {
+		None | Some("str") => Ok(OwnedArgdata::Str(s)),
+		Some("null") => Ok(OwnedArgdata::Null),
+		Some("bool") => match &s[..] {
+			"true" => Ok(OwnedArgdata::Bool(true)),
+			"false" => Ok(OwnedArgdata::Bool(false)),
+			_ => Err("Invalid !!bool value"),
+		},
+		Some("int") => intvalue::parse_decimal(&s)
+			.map(OwnedArgdata::Int)
+			.ok_or("Invalid !!int value"),
+		Some("float") => match &s[..] {
+			".nan" | ".NaN" | ".NAN" => Ok(f64::NAN),
+			".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => Ok(f64::INFINITY),
+			"-.inf" | "-.Inf" | "-.INF" => Ok(f64::NEG_INFINITY),
+			_ if s.bytes().all(|c| b"0123456789+-.eE".contains(&c)) => {
+				s.parse().map_err(|_| "Invalid !!float value")
+			}
+			_ => Err("Invalid !!float value"),
+		}
+		.map(OwnedArgdata::Float),
+		Some("binary") => {
+			let s: String = s.split_whitespace().collect();
+			base64::decode(&s)
+				.map(OwnedArgdata::Binary)
+				.ok_or("Invalid !!binary value")
+		}
+		Some("timestamp") => rfc3339::parse(&s)
+			.map(OwnedArgdata::Timestamp)
+			.ok_or("Invalid !!timestamp value"),
+		Some(tag) if tag == fd_tag => s
+			.parse::<u32>()
+			.map(|n| OwnedArgdata::Fd(fd::Fd(n as i32)))
+			.map_err(|_| "Invalid !fd value"),
+		Some(_) => return None,
+	};
+	Some(value)
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::Timespec;
+
+	#[test]
+	fn yaml_print_test() {
+		let argdata = crate::encoded(
+			&b"\x06\x87\x08Hello\x00\x87\x08World\x00\x81\x02\x82\x02\x01\x86\x09\
+			\x70\xF1\x80\x29\x15\x84\x05\x58\xe5\xd9\x80\x83\x06\x80\x80"[..],
+		);
+		assert_eq!(
+			Yaml(&argdata).to_string(),
+			"%TAG ! tag:nuxi.nl,2015:cloudabi/\n\
+			 ---\n\
+			 !!map {\n\
+			 \x20 ? !!str \"Hello\"\n\
+			 \x20 : !!str \"World\",\n\
+			 \x20 ? !!bool \"false\"\n\
+			 \x20 : !!bool \"true\",\n\
+			 \x20 ? !!timestamp \"1970-01-01T00:08:05.088045333Z\"\n\
+			 \x20 : !!int \"5826009\",\n\
+			 \x20 ? !!null \"null\"\n\
+			 \x20 : !!map {\n\
+			 \x20   ? !!null \"null\"\n\
+			 \x20   : !!null \"null\",\n\
+			 \x20 },\n\
+			 }\n"
+		);
+
+		let value = OwnedArgdata::Seq(vec![
+			b"\x01\x02"[..].into(),
+			fd::Fd(3).into(),
+			(-1.5).into(),
+			f64::NEG_INFINITY.into(),
+			"a\"\\\n\u{1}é".into(),
+			OwnedArgdata::Seq(vec![]),
+			OwnedArgdata::Map(vec![]),
+		]);
+		assert_eq!(
+			Yaml(value.argdata()).to_string(),
+			"%TAG ! tag:nuxi.nl,2015:cloudabi/\n\
+			 ---\n\
+			 !!seq [\n\
+			 \x20 !!binary \"AQI=\",\n\
+			 \x20 !fd \"3\",\n\
+			 \x20 !!float \"-1.5\",\n\
+			 \x20 !!float \"-.inf\",\n\
+			 \x20 !!str \"a\\\"\\\\\\n\\x01é\",\n\
+			 \x20 !!seq [],\n\
+			 \x20 !!map {},\n\
+			 ]\n"
+		);
+
+		let invalid = crate::encoded(&b"\x07\x82\x08a"[..]);
+		assert_eq!(
+			Yaml(&invalid).to_string(),
+			"%TAG ! tag:nuxi.nl,2015:cloudabi/\n\
+			 ---\n\
+			 !!seq [\n\
+			 \x20 !!null \"Argdata contains a string without nul terminator\",\n\
+			 ]\n"
+		);
+	}
+
+	#[test]
+	fn yaml_roundtrip_test() {
+		let value = OwnedArgdata::Map(vec![
+			("str".into(), "a\"b\\c\n\t\u{1}é\u{2028}".into()),
+			(
+				OwnedArgdata::Int(vec![0xFE, 0, 0, 0, 0, 0, 0, 0, 0]),
+				0.1.into(),
+			),
+			(true.into(), OwnedArgdata::Null),
+			(
+				Timespec {
+					sec: -1,
+					nsec: 500_000_000,
+				}
+				.into(),
+				b"\xFF"[..].into(),
+			),
+			(
+				fd::Fd(0).into(),
+				OwnedArgdata::Seq(vec![OwnedArgdata::Seq(vec![]), 1e100.into()]),
+			),
+		]);
+		let text = Yaml(value.argdata()).to_string();
+		let parsed = from_str(&text).unwrap();
+		assert_eq!(parsed, value);
+	}
+
+	#[test]
+	fn yaml_parse_test() {
+		let parse = |s| from_str(s).map(|v| format!("{:?}", v));
+		assert_eq!(
+			parse(
+				"%YAML 1.2\n\
+				 %TAG !c! tag:nuxi.nl,2015:cloudabi/\n\
+				 --- # A comment.\n\
+				 {\"a\": [!!int \"-7\", !<tag:yaml.org,2002:bool> \"true\",],\n\
+				 \x20? \"b\" : !c!fd \"2\", !!str \"c\":!!float \".inf\"}\n\
+				 ...\n"
+			)
+			.unwrap(),
+			"{\"a\": [-7, true], \"b\": fd(2), \"c\": inf}"
+		);
+		assert_eq!(parse("\"\\u00e9\\x41\\_\"").unwrap(), "\"éA\\u{a0}\"");
+		assert_eq!(
+			parse("!fd \"2\"").unwrap_err().to_string(),
+			"Unsupported tag at byte 0"
+		);
+
+		let error = |s| from_str(s).unwrap_err().to_string();
+		assert_eq!(error(""), "Unexpected end of YAML at byte 0");
+		assert_eq!(error("[\"a\" \"b\"]"), "Expected ',' or ']' at byte 5");
+		assert_eq!(error("{\"a\"}"), "Expected ':' at byte 4");
+		assert_eq!(error("!!int \"x\""), "Invalid !!int value at byte 6");
+		assert_eq!(error("!!map [\"a\"]"), "Unsupported tag at byte 0");
+		assert_eq!(error("!x!y \"\""), "Undefined tag handle at byte 0");
+		assert_eq!(
+			error("a"),
+			"Expected a double-quoted scalar, '{' or '[' at byte 0"
+		);
+		assert_eq!(
+			error("%TAG ! x\n\"\""),
+			"Expected '---' after directives at byte 9"
+		);
+		assert_eq!(error("\"\\q\""), "Invalid escape sequence at byte 2");
+		assert_eq!(
+			error("\"a\" \"b\""),
+			"Trailing characters after YAML document at byte 4"
+		);
+		assert_eq!(
+			error(&"[".repeat(1000)),
+			"YAML nested too deeply at byte 257"
+		);
+	}
+}